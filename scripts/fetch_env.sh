@@ -6,18 +6,27 @@ get_id() {
   echo "$JSON" | jq -r --arg name "$1" '.data[] | select(.name|test($name; "i")) | .root_canister_id' | head -n 1
 }
 export ICPSWAP_FACTORY=$(get_id ICPSwap)
+export ICPSWAP_FARM_INDEX=$(get_id "ICPSwap Farm")
 export SONIC_ROUTER=$(get_id Sonic)
 export INFINITY_VAULT=$(get_id InfinitySwap)
+export KONGSWAP_BACKEND=$(get_id KongSwap)
+export ICDEX_ROUTER=$(get_id ICDex)
 export SNS_DISTRIBUTOR=$(get_id Distributor)
 
 echo "ICPSWAP_FACTORY=$ICPSWAP_FACTORY"
+echo "ICPSWAP_FARM_INDEX=$ICPSWAP_FARM_INDEX"
 echo "SONIC_ROUTER=$SONIC_ROUTER"
 echo "INFINITY_VAULT=$INFINITY_VAULT"
+echo "KONGSWAP_BACKEND=$KONGSWAP_BACKEND"
+echo "ICDEX_ROUTER=$ICDEX_ROUTER"
 echo "SNS_DISTRIBUTOR=$SNS_DISTRIBUTOR"
 
 cat > .env.generated <<EOF
 ICPSWAP_FACTORY=$ICPSWAP_FACTORY
+ICPSWAP_FARM_INDEX=$ICPSWAP_FARM_INDEX
 SONIC_ROUTER=$SONIC_ROUTER
 INFINITY_VAULT=$INFINITY_VAULT
+KONGSWAP_BACKEND=$KONGSWAP_BACKEND
+ICDEX_ROUTER=$ICDEX_ROUTER
 SNS_DISTRIBUTOR=$SNS_DISTRIBUTOR
 EOF
@@ -7,7 +7,7 @@ dfx start --background --clean
 trap 'dfx stop' EXIT
 
 # deploy mock canisters
-CANISTERS=(mock_ledger mock_icpswap mock_sonic mock_infinity aggregator)
+CANISTERS=(mock_ledger mock_icpswap mock_sonic mock_infinity mock_kongswap mock_icdex mock_icpswap_farm mock_liquid_staking mock_ckbtc_minter mock_cketh_minter aggregator)
 for c in "${CANISTERS[@]}"; do
   dfx deploy "$c" >/dev/null
   id=$(dfx canister id "$c")
@@ -16,6 +16,12 @@ for c in "${CANISTERS[@]}"; do
     mock_icpswap) export ICPSWAP_FACTORY=$id;;
     mock_sonic) export SONIC_ROUTER=$id;;
     mock_infinity) export INFINITY_VAULT=$id;;
+    mock_kongswap) export KONGSWAP_BACKEND=$id;;
+    mock_icdex) export ICDEX_ROUTER=$id;;
+    mock_icpswap_farm) export ICPSWAP_FARM_INDEX=$id;;
+    mock_liquid_staking) export LIQUID_STAKING_PROTOCOL=$id;;
+    mock_ckbtc_minter) export CKBTC_MINTER=$id BITCOIN_CANISTER=$id;;
+    mock_cketh_minter) export CKETH_MINTER=$id;;
     mock_ledger) export LEDGER_ID=$id;;
   esac
 done
@@ -1,12 +1,312 @@
+use crate::lru::{holdings_bytes, Lru, Usage};
 use bx_core::Holding;
-use candid::Principal;
-use dashmap::DashMap;
+use candid::{CandidType, Principal};
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
 
-pub type Cache = DashMap<Principal, (Vec<Holding>, u64)>;
+const SEC_NS: u64 = 1_000_000_000;
 
-static CACHE: Lazy<Cache> = Lazy::new(DashMap::new);
+/// Holdings reported by one source and when they were fetched.
+#[derive(Clone)]
+pub struct SourceEntry {
+    pub holdings: Vec<Holding>,
+    pub fetched_at: u64,
+}
+
+type Sources = BTreeMap<String, SourceEntry>;
+
+/// Principals are evicted least recently used first once either limit is hit.
+static CACHE: Lazy<Lru<Principal, Sources>> = Lazy::new(|| {
+    let max_entries = option_env!("CACHE_MAX_ENTRIES")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(10_000);
+    let max_bytes = option_env!("CACHE_MAX_BYTES")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(32 * 1024 * 1024);
+    Lru::new(max_entries, max_bytes)
+});
+
+fn sources_bytes(sources: &Sources) -> usize {
+    sources
+        .iter()
+        .map(|(s, e)| s.len() + std::mem::size_of::<SourceEntry>() + holdings_bytes(&e.holdings))
+        .sum()
+}
+
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Seconds a source's data counts as fresh, e.g. `("ledger", 60)`
+    pub ttls: Vec<(String, u64)>,
+    /// TTL for sources not listed in `ttls`
+    pub default_ttl_secs: u64,
+    /// Data older than this is refused and fetched again before answering
+    pub max_staleness_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttls: vec![("ledger".into(), 60), ("neuron".into(), 600)],
+            default_ttl_secs: option_env!("CACHE_DEFAULT_TTL_SECS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(300),
+            max_staleness_secs: option_env!("CACHE_MAX_STALENESS_SECS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(3600),
+        }
+    }
+}
+
+static CONFIG: Lazy<RwLock<CacheConfig>> = Lazy::new(|| RwLock::new(CacheConfig::default()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    Fresh,
+    /// Past its TTL but still servable while a refresh runs
+    Stale,
+    Expired,
+}
+
+pub fn config() -> CacheConfig {
+    CONFIG.read().unwrap().clone()
+}
+
+pub fn set_config(cfg: CacheConfig) -> Result<(), String> {
+    for (source, ttl) in &cfg.ttls {
+        if source.is_empty() {
+            return Err("empty source name".into());
+        }
+        if *ttl > cfg.max_staleness_secs {
+            return Err(format!("ttl for {source} exceeds max_staleness_secs"));
+        }
+    }
+    if cfg.default_ttl_secs > cfg.max_staleness_secs {
+        return Err("default_ttl_secs exceeds max_staleness_secs".into());
+    }
+    tracing::info!(
+        sources = cfg.ttls.len(),
+        max_staleness = cfg.max_staleness_secs,
+        "cache config updated"
+    );
+    *CONFIG.write().unwrap() = cfg;
+    Ok(())
+}
+
+fn freshness(cfg: &CacheConfig, source: &str, fetched_at: u64, now: u64) -> Freshness {
+    let ttl = cfg
+        .ttls
+        .iter()
+        .find(|(s, _)| s == source)
+        .map_or(cfg.default_ttl_secs, |(_, t)| *t);
+    let age = now.saturating_sub(fetched_at);
+    if age < ttl.saturating_mul(SEC_NS) {
+        Freshness::Fresh
+    } else if age < cfg.max_staleness_secs.saturating_mul(SEC_NS) {
+        Freshness::Stale
+    } else {
+        Freshness::Expired
+    }
+}
+
+pub fn lookup(principal: Principal, source: &str) -> Option<(SourceEntry, Freshness)> {
+    let entry = CACHE.get_with(&principal, |s| s.get(source).cloned())??;
+    let state = freshness(&config(), source, entry.fetched_at, crate::utils::now());
+    Some((entry, state))
+}
+
+pub fn store(principal: Principal, source: &str, holdings: Vec<Holding>) {
+    put_source(
+        principal,
+        source.to_string(),
+        SourceEntry {
+            holdings,
+            fetched_at: crate::utils::now(),
+        },
+    );
+}
+
+/// Merge one source into the principal's entry atomically, so concurrent
+/// stores for different sources do not overwrite each other.
+fn put_source(principal: Principal, source: String, entry: SourceEntry) {
+    CACHE.upsert(principal, |sources| {
+        sources.insert(source, entry);
+        sources_bytes(sources)
+    });
+}
+
+/// Drop cached holdings for `principal`, `source`, both, or everything when
+/// neither is given. Returns the number of source entries removed.
+pub fn flush(principal: Option<Principal>, source: Option<&str>) -> usize {
+    let mut removed = 0;
+    CACHE.retain_mut(|p, sources| {
+        if principal.is_some_and(|want| want != *p) {
+            return Some(sources_bytes(sources));
+        }
+        match source {
+            Some(s) => removed += usize::from(sources.remove(s).is_some()),
+            None => {
+                removed += sources.len();
+                sources.clear();
+            }
+        }
+        (!sources.is_empty()).then(|| sources_bytes(sources))
+    });
+    removed
+}
+
+pub fn usage() -> Usage {
+    CACHE.usage()
+}
+
+/// `(principal, source)` pairs past their TTL but not yet expired, oldest first.
+pub fn stale_entries(limit: usize) -> Vec<(Principal, String)> {
+    let cfg = config();
+    let now = crate::utils::now();
+    let mut out: Vec<(u64, Principal, String)> = Vec::new();
+    CACHE.for_each(|principal, sources| {
+        for (source, entry) in sources {
+            if freshness(&cfg, source, entry.fetched_at, now) == Freshness::Stale {
+                out.push((entry.fetched_at, *principal, source.clone()));
+            }
+        }
+    });
+    out.sort();
+    out.into_iter()
+        .take(limit)
+        .map(|(_, p, s)| (p, s))
+        .collect()
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct StableEntry {
+    pub principal: Principal,
+    pub source: String,
+    pub holdings: Vec<Holding>,
+    pub fetched_at: u64,
+}
+
+/// Cached sources, least recently used principals first.
+pub fn stable_save() -> Vec<StableEntry> {
+    CACHE
+        .entries()
+        .into_iter()
+        .flat_map(|(principal, sources)| {
+            sources.into_iter().map(move |(source, e)| StableEntry {
+                principal,
+                source,
+                holdings: e.holdings,
+                fetched_at: e.fetched_at,
+            })
+        })
+        .collect()
+}
+
+pub fn stable_restore(entries: Vec<StableEntry>) {
+    CACHE.clear();
+    entries.into_iter().for_each(restore_entry);
+}
+
+fn restore_entry(e: StableEntry) {
+    put_source(
+        e.principal,
+        e.source,
+        SourceEntry {
+            holdings: e.holdings,
+            fetched_at: e.fetched_at,
+        },
+    );
+}
+
+pub fn stable_save_config() -> CacheConfig {
+    config()
+}
+
+pub fn stable_restore_config(cfg: CacheConfig) {
+    if let Err(e) = set_config(cfg) {
+        tracing::warn!("ignoring stored cache config: {e}");
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn backdate(principal: Principal, source: &str, secs: u64) {
+    CACHE.update(&principal, |sources| {
+        if let Some(entry) = sources.get_mut(source) {
+            entry.fetched_at = entry.fetched_at.saturating_sub(secs * SEC_NS);
+        }
+        Some(sources_bytes(sources))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_age_independently() {
+        let cfg = CacheConfig::default();
+        let now = 10_000 * SEC_NS;
+        let at = |secs: u64| now - secs * SEC_NS;
+        assert_eq!(freshness(&cfg, "ledger", at(59), now), Freshness::Fresh);
+        assert_eq!(freshness(&cfg, "ledger", at(61), now), Freshness::Stale);
+        assert_eq!(freshness(&cfg, "neuron", at(61), now), Freshness::Fresh);
+        assert_eq!(freshness(&cfg, "ICPSwap", at(299), now), Freshness::Fresh);
+        assert_eq!(
+            freshness(&cfg, "ICPSwap", at(3600), now),
+            Freshness::Expired
+        );
+    }
+
+    #[test]
+    fn config_rejects_ttls_beyond_max_staleness() {
+        let mut cfg = CacheConfig::default();
+        cfg.ttls.push(("Sonic".into(), 7200));
+        assert!(set_config(cfg).is_err());
+        let cfg = CacheConfig {
+            default_ttl_secs: 10,
+            max_staleness_secs: 5,
+            ..CacheConfig::default()
+        };
+        assert!(set_config(cfg).is_err());
+        assert_eq!(config(), CacheConfig::default());
+    }
+
+    #[test]
+    fn entries_keep_fetch_times_across_upgrades() {
+        let p = Principal::from_slice(&[23; 29]);
+        store(p, "ledger", Vec::new());
+        backdate(p, "ledger", 120);
+        store(p, "neuron", Vec::new());
+        let saved: Vec<StableEntry> = stable_save()
+            .into_iter()
+            .filter(|e| e.principal == p)
+            .collect();
+        assert_eq!(saved.len(), 2);
+        flush(Some(p), None);
+        assert!(lookup(p, "ledger").is_none());
+        // a full restore would clear entries other tests are using
+        saved.into_iter().for_each(restore_entry);
+        assert_eq!(lookup(p, "ledger").unwrap().1, Freshness::Stale);
+        assert_eq!(lookup(p, "neuron").unwrap().1, Freshness::Fresh);
+        flush(Some(p), None);
+    }
 
-pub fn get() -> &'static Cache {
-    &CACHE
+    #[test]
+    fn flush_by_principal_and_source() {
+        let (a, b) = (
+            Principal::from_slice(&[21; 29]),
+            Principal::from_slice(&[22; 29]),
+        );
+        store(a, "ledger", Vec::new());
+        store(a, "Sonic", Vec::new());
+        store(b, "Sonic", Vec::new());
+        assert_eq!(flush(Some(a), Some("Sonic")), 1);
+        assert!(lookup(a, "ledger").is_some());
+        assert!(lookup(b, "Sonic").is_some());
+        assert_eq!(flush(Some(a), None), 1);
+        assert!(lookup(a, "ledger").is_none());
+        assert_eq!(flush(Some(b), Some("ledger")), 0);
+        assert_eq!(flush(Some(b), Some("Sonic")), 1);
+    }
 }
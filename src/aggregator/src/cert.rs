@@ -42,3 +42,47 @@ pub fn witness(principal: candid::Principal) -> Vec<u8> {
 pub fn witness(_principal: candid::Principal) -> Vec<u8> {
     Vec::new()
 }
+
+/// A certified leaf: the principal's text bytes and its holdings hash.
+#[derive(candid::CandidType, serde::Deserialize)]
+pub struct StableLeaf {
+    pub key: Vec<u8>,
+    pub hash: Vec<u8>,
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn stable_save() -> Vec<StableLeaf> {
+    TREE.with(|t| {
+        t.borrow()
+            .iter()
+            .map(|(key, hash)| StableLeaf {
+                key: key.clone(),
+                hash: hash.to_vec(),
+            })
+            .collect()
+    })
+}
+
+/// Rebuild the tree and certify its root again, since certified data does
+/// not survive an upgrade.
+#[cfg(target_arch = "wasm32")]
+pub fn stable_restore(leaves: Vec<StableLeaf>) {
+    TREE.with(|t| {
+        let mut tree = t.borrow_mut();
+        *tree = RbTree::new();
+        for leaf in leaves {
+            if let Ok(hash) = Hash::try_from(leaf.hash) {
+                tree.insert(leaf.key, hash);
+            }
+        }
+        ic_cdk::api::set_certified_data(&tree.root_hash());
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn stable_save() -> Vec<StableLeaf> {
+    Vec::new()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn stable_restore(_leaves: Vec<StableLeaf>) {}
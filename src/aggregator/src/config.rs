@@ -0,0 +1,800 @@
+use crate::error::ConfigError;
+use crate::stable::{self, Memory};
+use candid::{CandidType, Principal};
+use ic_stable_structures::{storable::Bound, Memory as _, StableCell, Storable};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, RwLock};
+
+// Ledger and DEX principals live in a single store in a stable cell, which
+// also keeps the decoded copy that lookups read. Canisters seed it from the
+// embedded `ledgers.toml` on first install and every admin change is written
+// through, so controllers can register new tokens without a redeploy.
+
+#[cfg(target_arch = "wasm32")]
+const EMBEDDED: &str = include_str!("../../../config/ledgers.toml");
+
+/// Environment variables that override a `[dex]` entry of the same name.
+pub const DEX_ENV_KEYS: [&str; 11] = [
+    "ICPSWAP_FACTORY",
+    "ICPSWAP_FARM_INDEX",
+    "SONIC_ROUTER",
+    "INFINITY_VAULT",
+    "KONGSWAP_BACKEND",
+    "ICDEX_ROUTER",
+    "LIQUID_STAKING_PROTOCOL",
+    "CKBTC_MINTER",
+    "BITCOIN_CANISTER",
+    "CKETH_MINTER",
+    "SNS_DISTRIBUTOR",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub name: String,
+    pub id: Principal,
+    pub enabled: bool,
+    /// Index canister read for transactions when the ledger has no ICRC-3
+    /// blocks, from `[indexes]`
+    pub index: Option<Principal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct DexEntry {
+    pub name: String,
+    pub id: Principal,
+    /// Expected controller from `[dex_controllers]`
+    pub controller: Option<Principal>,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct Config {
+    pub ledgers: Vec<LedgerEntry>,
+    pub dex: Vec<DexEntry>,
+}
+
+#[derive(Clone, Default)]
+struct Store {
+    ledgers: BTreeMap<String, LedgerEntry>,
+    dex: BTreeMap<String, DexEntry>,
+}
+
+impl From<Config> for Store {
+    fn from(cfg: Config) -> Self {
+        Store {
+            ledgers: cfg
+                .ledgers
+                .into_iter()
+                .map(|e| (e.name.clone(), e))
+                .collect(),
+            dex: cfg.dex.into_iter().map(|e| (e.name.clone(), e)).collect(),
+        }
+    }
+}
+
+impl Storable for Store {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        stable::to_bytes(&config_of(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        stable::from_bytes::<Config>(bytes).into()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+static STORE: Lazy<RwLock<StableCell<Store, Memory>>> = Lazy::new(|| {
+    let memory = stable::memory(stable::CONFIG);
+    let cell = if memory.size() == 0 {
+        StableCell::new(memory, seed().into()).expect("seed config cell")
+    } else {
+        StableCell::init(memory, Store::default()).expect("load config cell")
+    };
+    RwLock::new(cell)
+});
+static LOAD_ERRORS: Lazy<Mutex<BTreeMap<Source, Vec<ConfigError>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Parse a `ledgers.toml` document. Invalid entries are skipped and returned
+/// alongside the config; only a document that is not TOML at all fails.
+pub fn parse_toml(text: &str) -> Result<(Config, Vec<ConfigError>), ConfigError> {
+    let value: toml::Value = toml::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+    let mut errors = Vec::new();
+    let mut principals = |section: &str| {
+        let table = value
+            .get(section)
+            .and_then(|v| v.as_table())
+            .cloned()
+            .unwrap_or_default();
+        let mut out = BTreeMap::new();
+        for (name, v) in table {
+            let Some(text) = v.as_str() else {
+                errors.push(ConfigError::NotAString {
+                    section: section.to_string(),
+                    name,
+                });
+                continue;
+            };
+            match Principal::from_text(text) {
+                Ok(id) => {
+                    out.insert(name, id);
+                }
+                Err(_) => errors.push(ConfigError::InvalidPrincipal {
+                    section: section.to_string(),
+                    name,
+                    value: text.to_string(),
+                }),
+            }
+        }
+        out
+    };
+    let ledgers = principals("ledgers");
+    let dex = principals("dex");
+    let controllers = principals("dex_controllers");
+    let indexes = principals("indexes");
+    let cfg = Config {
+        ledgers: ledgers
+            .into_iter()
+            .map(|(name, id)| LedgerEntry {
+                index: indexes.get(&name).copied(),
+                name,
+                id,
+                enabled: true,
+            })
+            .collect(),
+        dex: dex
+            .into_iter()
+            .map(|(name, id)| DexEntry {
+                controller: controllers.get(&name).copied(),
+                name,
+                id,
+                enabled: true,
+            })
+            .collect(),
+    };
+    for e in &errors {
+        tracing::warn!("skipping config entry: {e}");
+    }
+    Ok((cfg, errors))
+}
+
+/// Valid entries of a `name = "principal"` section such as `nfts`;
+/// `validate_toml` reports the rest.
+pub fn parse_principals(text: &str, section: &str) -> BTreeMap<String, Principal> {
+    let Ok(value) = toml::from_str::<toml::Value>(text) else {
+        return BTreeMap::new();
+    };
+    value
+        .get(section)
+        .and_then(|v| v.as_table())
+        .map(|table| {
+            table
+                .iter()
+                .filter_map(|(name, v)| {
+                    let id = Principal::from_text(v.as_str()?).ok()?;
+                    Some((name.clone(), id))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The `ledgers.toml` document new stores are seeded from.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn seed_text() -> Option<String> {
+    Some(EMBEDDED.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn seed_text() -> Option<String> {
+    std::fs::read_to_string(ledgers_path()).ok()
+}
+
+/// Report every problem in a `ledgers.toml` document instead of skipping it.
+pub fn validate_toml(text: &str) -> Vec<String> {
+    let value: toml::Value = match toml::from_str(text) {
+        Ok(v) => v,
+        Err(e) => return vec![format!("parse error: {e}")],
+    };
+    let Some(root) = value.as_table() else {
+        return vec!["expected a table at the top level".into()];
+    };
+    let mut problems = Vec::new();
+    for key in root.keys() {
+        if !matches!(
+            key.as_str(),
+            "ledgers" | "dex" | "dex_controllers" | "nfts" | "ext_nfts" | "lockers" | "indexes"
+        ) {
+            problems.push(format!("unknown section [{key}]"));
+        }
+    }
+    let mut sections = BTreeMap::new();
+    for name in [
+        "ledgers",
+        "dex",
+        "dex_controllers",
+        "nfts",
+        "ext_nfts",
+        "indexes",
+    ] {
+        let mut ids = BTreeMap::new();
+        match root.get(name) {
+            None => {}
+            Some(toml::Value::Table(table)) => {
+                for (key, v) in table {
+                    match v.as_str().map(Principal::from_text) {
+                        None => problems.push(format!("[{name}] {key}: expected a string")),
+                        Some(Err(e)) => {
+                            problems.push(format!("[{name}] {key}: invalid principal: {e}"))
+                        }
+                        Some(Ok(id)) => {
+                            ids.insert(key.clone(), id);
+                        }
+                    }
+                }
+            }
+            Some(_) => problems.push(format!("[{name}] must be a table")),
+        }
+        sections.insert(name, ids);
+    }
+    problems.extend(crate::dex::locker::parse_lockers(text).1);
+    for name in ["ledgers", "dex", "nfts", "ext_nfts"] {
+        let mut seen: BTreeMap<Principal, &String> = BTreeMap::new();
+        for (key, id) in &sections[name] {
+            if let Some(first) = seen.insert(*id, key) {
+                problems.push(format!("[{name}] {key}: duplicate of {first} ({id})"));
+            }
+        }
+    }
+    if !root.contains_key("ledgers") {
+        problems.push("missing [ledgers] section".into());
+    }
+    let controllers = &sections["dex_controllers"];
+    for key in sections["dex"].keys() {
+        if !controllers.contains_key(key) {
+            problems.push(format!("[dex] {key}: missing entry in [dex_controllers]"));
+        }
+    }
+    if let Some(toml::Value::Table(table)) = root.get("dex_controllers") {
+        for key in table.keys() {
+            if !sections["dex"].contains_key(key) {
+                problems.push(format!("[dex_controllers] {key}: unknown dex"));
+            }
+        }
+    }
+    if let Some(toml::Value::Table(table)) = root.get("indexes") {
+        for key in table.keys() {
+            if !sections["ledgers"].contains_key(key) {
+                problems.push(format!("[indexes] {key}: unknown ledger"));
+            }
+        }
+    }
+    problems
+}
+
+/// Line-oriented diff from `active` to `wanted`, e.g. `+ ledger ckBTC ...`.
+pub fn diff(active: &Config, wanted: &Config) -> Vec<String> {
+    fn changes<T: PartialEq + std::fmt::Debug>(
+        kind: &str,
+        active: BTreeMap<&String, &T>,
+        wanted: BTreeMap<&String, &T>,
+        out: &mut Vec<String>,
+    ) {
+        for (name, a) in &active {
+            match wanted.get(name) {
+                None => out.push(format!("- {kind} {name} {a:?}")),
+                Some(w) if w != a => out.push(format!("~ {kind} {name} {a:?} -> {w:?}")),
+                _ => {}
+            }
+        }
+        for (name, w) in &wanted {
+            if !active.contains_key(name) {
+                out.push(format!("+ {kind} {name} {w:?}"));
+            }
+        }
+    }
+    let mut out = Vec::new();
+    changes(
+        "ledger",
+        active.ledgers.iter().map(|e| (&e.name, e)).collect(),
+        wanted.ledgers.iter().map(|e| (&e.name, e)).collect(),
+        &mut out,
+    );
+    changes(
+        "dex",
+        active.dex.iter().map(|e| (&e.name, e)).collect(),
+        wanted.dex.iter().map(|e| (&e.name, e)).collect(),
+        &mut out,
+    );
+    out
+}
+
+#[cfg(target_arch = "wasm32")]
+fn seed() -> Config {
+    let mut cfg = match parse_toml(EMBEDDED) {
+        Ok((cfg, errors)) => {
+            record_errors(Source::Ledgers, errors.clone());
+            record_errors(Source::Dex, errors);
+            cfg
+        }
+        Err(e) => {
+            record_errors(Source::Ledgers, vec![e.clone()]);
+            record_errors(Source::Dex, vec![e]);
+            Config::default()
+        }
+    };
+    let overrides = [
+        option_env!("ICPSWAP_FACTORY"),
+        option_env!("ICPSWAP_FARM_INDEX"),
+        option_env!("SONIC_ROUTER"),
+        option_env!("INFINITY_VAULT"),
+        option_env!("KONGSWAP_BACKEND"),
+        option_env!("ICDEX_ROUTER"),
+        option_env!("LIQUID_STAKING_PROTOCOL"),
+        option_env!("CKBTC_MINTER"),
+        option_env!("BITCOIN_CANISTER"),
+        option_env!("CKETH_MINTER"),
+        option_env!("SNS_DISTRIBUTOR"),
+    ];
+    for (key, val) in DEX_ENV_KEYS.iter().zip(overrides) {
+        if let Some(id) = val.and_then(|s| Principal::from_text(s).ok()) {
+            apply_override(&mut cfg.dex, key, id);
+        }
+    }
+    cfg
+}
+
+// Natively DEX entries arrive through `utils::load_dex_config`, which also
+// applies environment overrides and sanity checks.
+#[cfg(not(target_arch = "wasm32"))]
+fn seed() -> Config {
+    Config {
+        ledgers: load_file(Source::Ledgers)
+            .map(|cfg| cfg.ledgers)
+            .unwrap_or_default(),
+        dex: Vec::new(),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn ledgers_path() -> String {
+    std::env::var("LEDGERS_FILE").unwrap_or_else(|_| "config/ledgers.toml".to_string())
+}
+
+/// Read and parse `LEDGERS_FILE`, recording problems against `source`.
+/// `None` means the file could not be used at all.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn load_file(source: Source) -> Option<Config> {
+    let path = ledgers_path();
+    let parsed = std::fs::read_to_string(&path)
+        .map_err(|e| ConfigError::Unreadable {
+            path,
+            reason: e.to_string(),
+        })
+        .and_then(|text| parse_toml(&text));
+    match parsed {
+        Ok((cfg, errors)) => {
+            record_errors(source, errors);
+            Some(cfg)
+        }
+        Err(e) => {
+            tracing::error!("{e}");
+            record_errors(source, vec![e]);
+            None
+        }
+    }
+}
+
+/// Re-read the ledger list from `LEDGERS_FILE`. On failure the current
+/// list is kept and the error is reported through `load_errors`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn reload_ledgers() {
+    if let Some(cfg) = load_file(Source::Ledgers) {
+        let count = cfg.ledgers.len();
+        update(|store| {
+            store.ledgers = cfg
+                .ledgers
+                .into_iter()
+                .map(|e| (e.name.clone(), e))
+                .collect()
+        });
+        tracing::info!(count, "ledgers reloaded");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Source {
+    Ledgers,
+    Dex,
+}
+
+pub(crate) fn record_errors(source: Source, mut errors: Vec<ConfigError>) {
+    let wanted: &[&str] = match source {
+        Source::Ledgers => &["ledgers"],
+        Source::Dex => &["dex", "dex_controllers"],
+    };
+    errors.retain(|e| match e {
+        ConfigError::InvalidPrincipal { section, .. } | ConfigError::NotAString { section, .. } => {
+            wanted.contains(&section.as_str())
+        }
+        _ => true,
+    });
+    LOAD_ERRORS.lock().unwrap().insert(source, errors);
+}
+
+/// Problems from the most recent ledger and DEX loads.
+pub fn load_errors() -> Vec<ConfigError> {
+    let mut out: Vec<ConfigError> = Vec::new();
+    for errors in LOAD_ERRORS.lock().unwrap().values() {
+        for e in errors {
+            if !out.contains(e) {
+                out.push(e.clone());
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn apply_override(dex: &mut Vec<DexEntry>, key: &str, id: Principal) {
+    if let Some(e) = dex.iter_mut().find(|e| e.name == key) {
+        e.id = id;
+        e.enabled = true;
+    } else {
+        dex.push(DexEntry {
+            name: key.to_string(),
+            id,
+            controller: None,
+            enabled: true,
+        });
+    }
+}
+
+/// Force seeding so a fresh install writes the embedded defaults.
+pub fn init() {
+    Lazy::force(&STORE);
+}
+
+fn config_of(store: &Store) -> Config {
+    Config {
+        ledgers: store.ledgers.values().cloned().collect(),
+        dex: store.dex.values().cloned().collect(),
+    }
+}
+
+/// Apply `f` to the store and write the result through to stable memory.
+fn update<R>(f: impl FnOnce(&mut Store) -> R) -> R {
+    let mut cell = STORE.write().unwrap();
+    let mut store = cell.get().clone();
+    let out = f(&mut store);
+    cell.set(store).expect("write config cell");
+    out
+}
+
+pub fn get() -> Config {
+    config_of(STORE.read().unwrap().get())
+}
+
+/// Enabled ledger canisters, sorted and deduplicated.
+pub fn ledger_ids() -> Vec<Principal> {
+    let mut ids: Vec<Principal> = STORE
+        .read()
+        .unwrap()
+        .get()
+        .ledgers
+        .values()
+        .filter(|e| e.enabled)
+        .map(|e| e.id)
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+pub fn dex_id(name: &str) -> Option<Principal> {
+    STORE
+        .read()
+        .unwrap()
+        .get()
+        .dex
+        .get(name)
+        .filter(|e| e.enabled)
+        .map(|e| e.id)
+}
+
+pub fn dex_ids() -> Vec<Principal> {
+    STORE
+        .read()
+        .unwrap()
+        .get()
+        .dex
+        .values()
+        .filter(|e| e.enabled)
+        .map(|e| e.id)
+        .collect()
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("empty name".into());
+    }
+    Ok(())
+}
+
+/// Insert or replace a ledger; new entries start enabled.
+pub fn add_ledger(name: String, id: Principal, index: Option<Principal>) -> Result<(), String> {
+    check_name(&name)?;
+    tracing::info!(%name, %id, ?index, "ledger added");
+    update(|store| {
+        store.ledgers.insert(
+            name.clone(),
+            LedgerEntry {
+                name,
+                id,
+                enabled: true,
+                index,
+            },
+        )
+    });
+    Ok(())
+}
+
+pub fn remove_ledger(name: &str) -> Result<(), String> {
+    update(|store| store.ledgers.remove(name))
+        .map(|_| tracing::info!(%name, "ledger removed"))
+        .ok_or_else(|| format!("unknown ledger {name}"))
+}
+
+pub fn set_ledger_enabled(name: &str, enabled: bool) -> Result<(), String> {
+    update(|store| {
+        let entry = store
+            .ledgers
+            .get_mut(name)
+            .ok_or_else(|| format!("unknown ledger {name}"))?;
+        entry.enabled = enabled;
+        Ok::<_, String>(())
+    })?;
+    tracing::info!(%name, enabled, "ledger toggled");
+    Ok(())
+}
+
+/// Insert or replace a DEX entry; new entries start enabled.
+pub fn add_dex(name: String, id: Principal, controller: Option<Principal>) -> Result<(), String> {
+    check_name(&name)?;
+    tracing::info!(%name, %id, "dex added");
+    update(|store| {
+        store.dex.insert(
+            name.clone(),
+            DexEntry {
+                name,
+                id,
+                controller,
+                enabled: true,
+            },
+        )
+    });
+    Ok(())
+}
+
+pub fn remove_dex(name: &str) -> Result<(), String> {
+    update(|store| store.dex.remove(name))
+        .map(|_| tracing::info!(%name, "dex removed"))
+        .ok_or_else(|| format!("unknown dex {name}"))
+}
+
+pub fn set_dex_enabled(name: &str, enabled: bool) -> Result<(), String> {
+    update(|store| {
+        let entry = store
+            .dex
+            .get_mut(name)
+            .ok_or_else(|| format!("unknown dex {name}"))?;
+        entry.enabled = enabled;
+        Ok::<_, String>(())
+    })?;
+    tracing::info!(%name, enabled, "dex toggled");
+    Ok(())
+}
+
+/// Swap in a freshly loaded DEX table, keeping the ledger list untouched.
+pub fn replace_dex(entries: Vec<DexEntry>) {
+    update(|store| store.dex = entries.into_iter().map(|e| (e.name.clone(), e)).collect());
+}
+
+/// Replace the store, e.g. with a config saved on the heap by an older
+/// release.
+pub fn stable_restore(cfg: Config) {
+    update(|store| *store = cfg.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    const SAMPLE: &str = r#"
+[ledgers]
+ICP = "rwlgt-iiaaa-aaaaa-aaaaa-cai"
+BAD = "not-a-principal"
+
+[dex]
+ICPSWAP_FACTORY = "oh4fn-kyaaa-aaaaq-aaega-cai"
+
+[dex_controllers]
+ICPSWAP_FACTORY = "aaaaa-aa"
+
+[indexes]
+ICP = "qhbym-qaaaa-aaaaa-aaafq-cai"
+"#;
+
+    #[test]
+    fn parse_skips_invalid_entries() {
+        let (cfg, errors) = parse_toml(SAMPLE).unwrap();
+        assert_eq!(
+            errors,
+            vec![ConfigError::InvalidPrincipal {
+                section: "ledgers".into(),
+                name: "BAD".into(),
+                value: "not-a-principal".into(),
+            }]
+        );
+        assert_eq!(cfg.ledgers.len(), 1);
+        assert_eq!(cfg.ledgers[0].name, "ICP");
+        assert_eq!(
+            cfg.ledgers[0].index,
+            Some(Principal::from_text("qhbym-qaaaa-aaaaa-aaafq-cai").unwrap())
+        );
+        assert_eq!(cfg.dex.len(), 1);
+        assert_eq!(
+            cfg.dex[0].controller,
+            Some(Principal::management_canister())
+        );
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let problems = validate_toml(
+            r#"
+[ledgers]
+ICP = "rwlgt-iiaaa-aaaaa-aaaaa-cai"
+COPY = "rwlgt-iiaaa-aaaaa-aaaaa-cai"
+BAD = "not-a-principal"
+
+[dex]
+SONIC_ROUTER = "xjngq-yaaaa-aaaaq-aabha-cai"
+
+[dex_controllers]
+ICPSWAP_FACTORY = "aaaaa-aa"
+
+[nfts]
+Punks = "aaaaa-aa"
+Broken = "nope"
+
+[indexes]
+ICP = "qhbym-qaaaa-aaaaa-aaafq-cai"
+GONE = "qhbym-qaaaa-aaaaa-aaafq-cai"
+
+[extra]
+"#,
+        );
+        assert_eq!(problems.len(), 7, "{problems:?}");
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("[indexes] GONE: unknown ledger")));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("Broken: invalid principal")));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("unknown section [extra]")));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("BAD: invalid principal")));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("ICP: duplicate of COPY")));
+        assert!(problems.iter().any(|p| p.contains("SONIC_ROUTER: missing")));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("ICPSWAP_FACTORY: unknown dex")));
+        assert!(validate_toml(include_str!("../../../config/ledgers.toml")).is_empty());
+    }
+
+    #[test]
+    fn parse_principals_keeps_valid_entries() {
+        let text = "[nfts]\nPunks = \"aaaaa-aa\"\nBroken = \"nope\"\n";
+        let nfts = parse_principals(text, "nfts");
+        assert_eq!(nfts.len(), 1);
+        assert_eq!(nfts["Punks"], Principal::management_canister());
+        assert!(parse_principals(text, "ext_nfts").is_empty());
+        assert!(parse_principals("not toml [", "nfts").is_empty());
+    }
+
+    #[test]
+    fn diff_lists_added_removed_and_changed_entries() {
+        let active = parse_toml(SAMPLE).unwrap().0;
+        let mut wanted = active.clone();
+        wanted.ledgers.clear();
+        wanted.dex[0].enabled = false;
+        wanted.ledgers.push(LedgerEntry {
+            name: "ckBTC".into(),
+            id: Principal::from_text("mxzaz-hqaaa-aaaar-qaada-cai").unwrap(),
+            enabled: true,
+            index: None,
+        });
+        let lines = diff(&active, &wanted);
+        assert_eq!(lines.len(), 3, "{lines:?}");
+        assert!(lines[0].starts_with("- ledger ICP"));
+        assert!(lines[1].starts_with("+ ledger ckBTC"));
+        assert!(lines[2].starts_with("~ dex ICPSWAP_FACTORY"));
+        assert!(diff(&active, &active).is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn admin_changes_are_reflected_in_lookups() {
+        let saved = get();
+        stable_restore(parse_toml(SAMPLE).unwrap().0);
+        let ckbtc = Principal::from_text("mxzaz-hqaaa-aaaar-qaada-cai").unwrap();
+        add_ledger("ckBTC".into(), ckbtc, None).unwrap();
+        assert!(ledger_ids().contains(&ckbtc));
+        set_ledger_enabled("ckBTC", false).unwrap();
+        assert!(!ledger_ids().contains(&ckbtc));
+        assert!(remove_ledger("ckBTC").is_ok());
+        assert!(remove_ledger("ckBTC").is_err());
+
+        assert!(dex_id("ICPSWAP_FACTORY").is_some());
+        set_dex_enabled("ICPSWAP_FACTORY", false).unwrap();
+        assert_eq!(dex_id("ICPSWAP_FACTORY"), None);
+        assert!(dex_ids().is_empty());
+        assert!(set_dex_enabled("MISSING", true).is_err());
+        assert!(add_dex(" ".into(), ckbtc, None).is_err());
+
+        let persisted = get();
+        let store = STORE.read().unwrap().get().clone();
+        assert_eq!(config_of(&Store::from_bytes(store.to_bytes())), persisted);
+        stable_restore(Config::default());
+        stable_restore(persisted.clone());
+        assert_eq!(get(), persisted);
+        stable_restore(saved);
+    }
+
+    #[test]
+    #[serial]
+    fn reload_keeps_last_good_ledgers_and_reports_errors() {
+        use std::io::Write;
+        let saved = get();
+        record_errors(Source::Dex, Vec::new());
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        writeln!(f, "[ledgers]\nICP = \"rwlgt-iiaaa-aaaaa-aaaaa-cai\"").unwrap();
+        std::env::set_var("LEDGERS_FILE", f.path());
+        reload_ledgers();
+        assert_eq!(ledger_ids().len(), 1);
+        assert!(load_errors().is_empty());
+
+        writeln!(f, "BAD = \"nope\"\nckBTC = \"mxzaz-hqaaa-aaaar-qaada-cai\"").unwrap();
+        reload_ledgers();
+        assert_eq!(ledger_ids().len(), 2);
+        assert!(matches!(
+            load_errors().as_slice(),
+            [ConfigError::InvalidPrincipal { name, .. }] if name == "BAD"
+        ));
+
+        writeln!(f, "[ledgers").unwrap();
+        reload_ledgers();
+        assert_eq!(ledger_ids().len(), 2);
+        assert!(matches!(load_errors().as_slice(), [ConfigError::Parse(_)]));
+
+        std::env::set_var("LEDGERS_FILE", "/nonexistent/ledgers.toml");
+        reload_ledgers();
+        assert_eq!(ledger_ids().len(), 2);
+        assert!(matches!(
+            load_errors().as_slice(),
+            [ConfigError::Unreadable { .. }]
+        ));
+        record_errors(Source::Ledgers, Vec::new());
+        stable_restore(saved);
+    }
+}
@@ -0,0 +1,666 @@
+use super::{call, DexAdapter};
+use crate::error::FetchError;
+use crate::lru::Lru;
+use crate::utils::format_amount;
+use async_trait::async_trait;
+use bx_core::Holding;
+use candid::{CandidType, Nat, Principal, Reserved};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// Chain-key tokens are minted when funds arrive on the native chain and
+// burned when they leave it. In between, the funds are in neither the
+// ckToken ledger nor the user's native wallet: deposits wait for
+// confirmations before the minter mints, and withdrawals are burned before
+// the minter has signed and sent them. This module asks the minters about
+// both so the funds are not missing from the total.
+
+const BTC_DECIMALS: u8 = 8;
+/// ckETH, and ckERC20 tokens whose ledger cannot be queried
+const ETH_DECIMALS: u8 = 18;
+
+/// Deposit addresses remembered at most
+const ADDRESS_CACHE_ENTRIES: usize = 10_000;
+
+/// A deposit or withdrawal known to the minter
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize)]
+pub struct PendingTransfer {
+    /// Native transaction id, or the ledger burn index for withdrawals
+    pub id: String,
+    /// `None` when the minter does not report it, as the ckBTC minter does
+    /// not for withdrawals
+    pub amount: Option<String>,
+    /// Minter state, e.g. "confirming" or "signing"
+    pub status: String,
+    /// Finished transfers are listed for reference but are no longer
+    /// missing from any balance
+    pub settled: bool,
+}
+
+/// Pending chain-key transfers of one principal for one token
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize)]
+pub struct ChainKeyPending {
+    pub source: String,
+    pub token: String,
+    /// Native address that mints to this principal
+    pub deposit_address: Option<String>,
+    pub deposits: Vec<PendingTransfer>,
+    pub withdrawals: Vec<PendingTransfer>,
+}
+
+impl ChainKeyPending {
+    /// Unsettled transfers with a known amount; the others are only listed
+    /// in `get_chain_key_pending` rather than counted as zero.
+    pub fn holdings(&self) -> Vec<Holding> {
+        let holding = |t: &PendingTransfer, status: &str| {
+            Some(Holding {
+                source: self.source.clone(),
+                token: self.token.clone(),
+                amount: t.amount.clone()?,
+                status: status.into(),
+            })
+        };
+        self.deposits
+            .iter()
+            .map(|t| (t, "pending_deposit"))
+            .chain(self.withdrawals.iter().map(|t| (t, "pending_withdrawal")))
+            .filter(|(t, _)| !t.settled)
+            .filter_map(|(t, status)| holding(t, status))
+            .collect()
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<serde_bytes::ByteBuf>,
+}
+
+/// `get_btc_address` and `get_known_utxos` take an optional owner
+#[derive(CandidType, Deserialize)]
+struct OwnerArg {
+    owner: Option<Principal>,
+    subaccount: Option<serde_bytes::ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Outpoint {
+    txid: serde_bytes::ByteBuf,
+    vout: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Utxo {
+    outpoint: Outpoint,
+    value: u64,
+    height: u32,
+}
+
+/// The part of `get_minter_info` used here
+#[derive(CandidType, Deserialize)]
+struct MinterInfo {
+    min_confirmations: u32,
+}
+
+/// Only mainnet is asked for; a single variant still decodes on the
+/// Bitcoin canister
+#[derive(CandidType, Deserialize, Clone, Copy)]
+enum BitcoinNetwork {
+    #[serde(rename = "mainnet")]
+    Mainnet,
+}
+
+#[derive(CandidType, Deserialize)]
+enum UtxosFilter {
+    #[serde(rename = "page")]
+    Page(serde_bytes::ByteBuf),
+}
+
+#[derive(CandidType, Deserialize)]
+struct GetUtxosRequest {
+    network: BitcoinNetwork,
+    address: String,
+    filter: Option<UtxosFilter>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct GetUtxosResponse {
+    utxos: Vec<Utxo>,
+    tip_height: u32,
+    next_page: Option<serde_bytes::ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum RetrieveBtcStatus {
+    Unknown,
+    Pending,
+    Signing,
+    Sending(Reserved),
+    Submitted(Reserved),
+    AmountTooLow,
+    Confirmed(Reserved),
+    Reimbursed(Reserved),
+    WillReimburse(Reserved),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct BtcRetrieval {
+    block_index: u64,
+    /// Burned amount; the ckBTC minter does not report it, so the
+    /// withdrawal is listed without one
+    amount: Option<u64>,
+    status_v2: Option<RetrieveBtcStatus>,
+}
+
+/// Only the search used here; a single variant still decodes on the minter
+#[derive(CandidType, Deserialize)]
+enum WithdrawalSearchParameter {
+    BySenderAccount(Account),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct EthTransaction {
+    transaction_hash: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum TxFinalizedStatus {
+    Success {
+        transaction_hash: String,
+        effective_transaction_fee: Option<Nat>,
+    },
+    Reimbursed {
+        transaction_hash: String,
+        reimbursed_amount: Nat,
+        reimbursed_in_block: Nat,
+    },
+    PendingReimbursement(EthTransaction),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum WithdrawalStatus {
+    Pending,
+    TxCreated,
+    TxSent(EthTransaction),
+    TxFinalized(TxFinalizedStatus),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct WithdrawalDetail {
+    /// Ledger burn index of the withdrawal
+    withdrawal_id: u64,
+    token_symbol: String,
+    withdrawal_amount: Nat,
+    status: WithdrawalStatus,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Erc20Token {
+    ckerc20_token_symbol: String,
+    ledger_canister_id: Principal,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct EthMinterInfo {
+    supported_ckerc20_tokens: Option<Vec<Erc20Token>>,
+}
+
+/// Deposit addresses never change for an account, so each is fetched once.
+/// `get_btc_address` is an update call, so only update paths fill this and
+/// queries read what they left behind.
+static BTC_ADDRESSES: Lazy<Lru<Principal, String>> =
+    Lazy::new(|| Lru::new(ADDRESS_CACHE_ENTRIES, usize::MAX));
+
+pub struct CkBtcAdapter;
+
+pub struct CkEthAdapter;
+
+/// Bitcoin canister asked for the UTXOs on deposit addresses; without one
+/// only withdrawals are reported
+fn bitcoin_canister() -> Option<Principal> {
+    crate::utils::env_principal("BITCOIN_CANISTER")
+}
+
+fn ckbtc_minter() -> Result<Principal, FetchError> {
+    crate::utils::env_principal("CKBTC_MINTER")
+        .ok_or_else(|| FetchError::InvalidConfig("ckBTC minter".into()))
+}
+
+fn cketh_minter() -> Result<Principal, FetchError> {
+    crate::utils::env_principal("CKETH_MINTER")
+        .ok_or_else(|| FetchError::InvalidConfig("ckETH minter".into()))
+}
+
+/// Bitcoin displays txids byte-reversed
+fn txid_hex(outpoint: &Outpoint) -> String {
+    let hex: String = outpoint
+        .txid
+        .iter()
+        .rev()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("{hex}:{}", outpoint.vout)
+}
+
+fn pending_deposit(outpoint: &Outpoint, value: u64, status: &str) -> PendingTransfer {
+    PendingTransfer {
+        id: txid_hex(outpoint),
+        amount: Some(format_amount(value.into(), BTC_DECIMALS)),
+        status: status.into(),
+        settled: false,
+    }
+}
+
+/// UTXOs on the deposit address that the minter does not know yet, i.e.
+/// has not minted. Only queries are used: `update_balance` would mint
+/// (and charge for) UTXOs with enough confirmations, which a read must not
+/// do. UTXOs the minter rejected are not among its known UTXOs either, so
+/// those stay listed as `ready_to_mint`.
+fn btc_deposits(
+    on_address: Vec<Utxo>,
+    known: &[Utxo],
+    tip_height: u32,
+    min_confirmations: u32,
+) -> Vec<PendingTransfer> {
+    let known: HashSet<(&[u8], u32)> = known
+        .iter()
+        .map(|u| (u.outpoint.txid.as_slice(), u.outpoint.vout))
+        .collect();
+    on_address
+        .iter()
+        .filter(|u| !known.contains(&(u.outpoint.txid.as_slice(), u.outpoint.vout)))
+        .map(|u| {
+            let confirmations = (tip_height + 1).saturating_sub(u.height);
+            let status = if confirmations < min_confirmations {
+                "confirming"
+            } else {
+                "ready_to_mint"
+            };
+            pending_deposit(&u.outpoint, u.value, status)
+        })
+        .collect()
+}
+
+fn btc_withdrawals(retrievals: Vec<BtcRetrieval>) -> Vec<PendingTransfer> {
+    retrievals
+        .into_iter()
+        .filter_map(|r| {
+            let status = match r.status_v2? {
+                RetrieveBtcStatus::Pending => "pending",
+                RetrieveBtcStatus::Signing => "signing",
+                RetrieveBtcStatus::Sending(_) => "sending",
+                RetrieveBtcStatus::Submitted(_) => "submitted",
+                RetrieveBtcStatus::WillReimburse(_) => "reimbursing",
+                RetrieveBtcStatus::Unknown
+                | RetrieveBtcStatus::AmountTooLow
+                | RetrieveBtcStatus::Confirmed(_)
+                | RetrieveBtcStatus::Reimbursed(_) => return None,
+            };
+            Some(PendingTransfer {
+                id: r.block_index.to_string(),
+                amount: r.amount.map(|a| format_amount(a.into(), BTC_DECIMALS)),
+                status: status.into(),
+                settled: false,
+            })
+        })
+        .collect()
+}
+
+/// The principal's deposit address; when it is not cached yet, only asks
+/// the minter if `fetch` is set.
+async fn btc_address(
+    minter: Principal,
+    principal: Principal,
+    fetch: bool,
+) -> Result<Option<String>, FetchError> {
+    if let Some(addr) = BTC_ADDRESSES.get(&principal) {
+        return Ok(Some(addr));
+    }
+    if !fetch {
+        return Ok(None);
+    }
+    let arg = OwnerArg {
+        owner: Some(principal),
+        subaccount: None,
+    };
+    let addr: String = call(minter, "get_btc_address", (arg,), true).await?;
+    BTC_ADDRESSES.insert(principal, addr.clone(), addr.len());
+    Ok(Some(addr))
+}
+
+/// Every UTXO on `address` and the Bitcoin tip height, following pages.
+async fn address_utxos(bitcoin: Principal, address: &str) -> Result<(Vec<Utxo>, u32), FetchError> {
+    let mut utxos = Vec::new();
+    let mut filter = None;
+    loop {
+        let req = GetUtxosRequest {
+            network: BitcoinNetwork::Mainnet,
+            address: address.to_string(),
+            filter,
+        };
+        let page: GetUtxosResponse =
+            call(bitcoin, "bitcoin_get_utxos_query", (req,), false).await?;
+        utxos.extend(page.utxos);
+        match page.next_page {
+            Some(next) => filter = Some(UtxosFilter::Page(next)),
+            None => return Ok((utxos, page.tip_height)),
+        }
+    }
+}
+
+/// Pending ckBTC transfers. Deposits need the deposit address, so callers
+/// in a query pass `fetch_address: false` and get deposits only for
+/// principals whose address an update such as `refresh_holdings` cached.
+async fn ckbtc_pending(
+    principal: Principal,
+    fetch_address: bool,
+) -> Result<ChainKeyPending, FetchError> {
+    let minter = ckbtc_minter()?;
+    let owner = OwnerArg {
+        owner: Some(principal),
+        subaccount: None,
+    };
+    let account = Account {
+        owner: principal,
+        subaccount: None,
+    };
+    let (address, known, info, withdrawals) = futures::try_join!(
+        btc_address(minter, principal, fetch_address),
+        call::<_, Vec<Utxo>>(minter, "get_known_utxos", (owner,), false),
+        call::<_, MinterInfo>(minter, "get_minter_info", (), false),
+        call::<_, Vec<BtcRetrieval>>(
+            minter,
+            "retrieve_btc_status_v2_by_account",
+            (Some(account),),
+            false
+        )
+    )?;
+    let deposits = match (bitcoin_canister(), &address) {
+        (Some(bitcoin), Some(address)) => {
+            let (on_address, tip) = address_utxos(bitcoin, address).await?;
+            btc_deposits(on_address, &known, tip, info.min_confirmations)
+        }
+        _ => Vec::new(),
+    };
+    Ok(ChainKeyPending {
+        source: "ckBTC".into(),
+        token: "ckBTC".into(),
+        deposit_address: address,
+        deposits,
+        withdrawals: btc_withdrawals(withdrawals),
+    })
+}
+
+#[async_trait]
+impl DexAdapter for CkBtcAdapter {
+    async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
+        Ok(ckbtc_pending(principal, true).await?.holdings())
+    }
+
+    async fn chain_key_pending(
+        &self,
+        principal: Principal,
+    ) -> Result<Vec<ChainKeyPending>, FetchError> {
+        Ok(vec![ckbtc_pending(principal, false).await?])
+    }
+}
+
+/// Minter state of a withdrawal and whether it has settled. A failed
+/// transaction stays unsettled until the reimbursement is minted.
+fn eth_status(status: &WithdrawalStatus) -> (&'static str, bool) {
+    match status {
+        WithdrawalStatus::Pending | WithdrawalStatus::TxCreated => ("pending", false),
+        WithdrawalStatus::TxSent(_) => ("sent", false),
+        WithdrawalStatus::TxFinalized(TxFinalizedStatus::Success { .. }) => ("finalized", true),
+        WithdrawalStatus::TxFinalized(TxFinalizedStatus::Reimbursed { .. }) => ("reimbursed", true),
+        WithdrawalStatus::TxFinalized(TxFinalizedStatus::PendingReimbursement(_)) => {
+            ("failed_reimbursing", false)
+        }
+    }
+}
+
+/// Withdrawals grouped per token, in token order.
+fn eth_withdrawals(
+    details: Vec<WithdrawalDetail>,
+    decimals: &HashMap<String, u8>,
+) -> Vec<ChainKeyPending> {
+    let mut by_token: BTreeMap<String, Vec<PendingTransfer>> = BTreeMap::new();
+    for d in details {
+        let (status, settled) = eth_status(&d.status);
+        let dec = decimals
+            .get(&d.token_symbol)
+            .copied()
+            .unwrap_or(ETH_DECIMALS);
+        by_token
+            .entry(d.token_symbol)
+            .or_default()
+            .push(PendingTransfer {
+                id: d.withdrawal_id.to_string(),
+                amount: Some(format_amount(d.withdrawal_amount, dec)),
+                status: status.into(),
+                settled,
+            });
+    }
+    by_token
+        .into_iter()
+        .map(|(token, withdrawals)| ChainKeyPending {
+            source: "ckETH".into(),
+            token,
+            deposit_address: None,
+            deposits: Vec::new(),
+            withdrawals,
+        })
+        .collect()
+}
+
+/// Decimals of the ckERC20 tokens among `symbols`, from their ledgers.
+async fn ckerc20_decimals(minter: Principal, symbols: &[&str]) -> HashMap<String, u8> {
+    let mut out = HashMap::new();
+    if symbols.iter().all(|s| *s == "ckETH") {
+        return out;
+    }
+    let info: EthMinterInfo = match call(minter, "get_minter_info", (), false).await {
+        Ok(info) => info,
+        Err(e) => {
+            tracing::debug!("ckETH minter info unavailable: {e}");
+            return out;
+        }
+    };
+    for token in info.supported_ckerc20_tokens.unwrap_or_default() {
+        if !symbols.contains(&token.ckerc20_token_symbol.as_str()) {
+            continue;
+        }
+        if let Some(t) = crate::utils::token_info(token.ledger_canister_id).await {
+            out.insert(token.ckerc20_token_symbol, t.decimals);
+        }
+    }
+    out
+}
+
+async fn cketh_pending(principal: Principal) -> Result<Vec<ChainKeyPending>, FetchError> {
+    let minter = cketh_minter()?;
+    let search = WithdrawalSearchParameter::BySenderAccount(Account {
+        owner: principal,
+        subaccount: None,
+    });
+    let details: Vec<WithdrawalDetail> =
+        call(minter, "withdrawal_status", (search,), false).await?;
+    let mut symbols: Vec<&str> = details.iter().map(|d| d.token_symbol.as_str()).collect();
+    symbols.sort_unstable();
+    symbols.dedup();
+    let decimals = ckerc20_decimals(minter, &symbols).await;
+    Ok(eth_withdrawals(details, &decimals))
+}
+
+#[async_trait]
+impl DexAdapter for CkEthAdapter {
+    async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
+        Ok(cketh_pending(principal)
+            .await?
+            .iter()
+            .flat_map(ChainKeyPending::holdings)
+            .collect())
+    }
+
+    async fn chain_key_pending(
+        &self,
+        principal: Principal,
+    ) -> Result<Vec<ChainKeyPending>, FetchError> {
+        cketh_pending(principal).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Decode;
+    use quickcheck_macros::quickcheck;
+
+    fn outpoint(first: u8) -> Outpoint {
+        let mut txid = vec![0u8; 32];
+        txid[0] = first;
+        Outpoint {
+            txid: serde_bytes::ByteBuf::from(txid),
+            vout: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_without_env() {
+        let res = CkBtcAdapter.fetch_positions(Principal::anonymous()).await;
+        assert!(matches!(res, Err(FetchError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn queries_only_read_cached_deposit_addresses() {
+        let principal = Principal::from_slice(&[3; 29]);
+        let minter = Principal::anonymous();
+        assert_eq!(btc_address(minter, principal, false).await.unwrap(), None);
+        BTC_ADDRESSES.insert(principal, "bc1qdeposit".into(), 11);
+        let cached = btc_address(minter, principal, false).await.unwrap();
+        assert_eq!(cached.as_deref(), Some("bc1qdeposit"));
+    }
+
+    #[test]
+    fn unminted_utxos_and_inflight_withdrawals_are_pending() {
+        let utxo = |first, height| Utxo {
+            outpoint: outpoint(first),
+            value: 150_000,
+            height,
+        };
+        let known = [utxo(1, 90)];
+        // tip 100: heights 99 and 95 have 2 and 6 confirmations
+        let deposits = btc_deposits(
+            vec![utxo(1, 90), utxo(0xab, 99), utxo(2, 95)],
+            &known,
+            100,
+            6,
+        );
+        assert_eq!(deposits.len(), 2);
+        let confirming = vec![deposits[0].clone()];
+        assert_eq!(confirming[0].amount.as_deref(), Some("0.00150000"));
+        assert_eq!(confirming[0].status, "confirming");
+        assert!(confirming[0].id.ends_with("ab:1"));
+        assert_eq!(deposits[1].status, "ready_to_mint");
+
+        let retrieval = |block_index, amount, status_v2| BtcRetrieval {
+            block_index,
+            amount,
+            status_v2,
+        };
+        let withdrawals = btc_withdrawals(vec![
+            retrieval(7, Some(20_000_000), Some(RetrieveBtcStatus::Signing)),
+            retrieval(
+                8,
+                Some(20_000_000),
+                Some(RetrieveBtcStatus::Confirmed(Reserved)),
+            ),
+            retrieval(9, Some(20_000_000), None),
+            retrieval(10, None, Some(RetrieveBtcStatus::Sending(Reserved))),
+        ]);
+        assert_eq!(withdrawals.len(), 2);
+        assert_eq!(
+            (withdrawals[0].id.as_str(), withdrawals[0].amount.as_deref()),
+            ("7", Some("0.20000000"))
+        );
+        // the minter does not report the amount, so none is made up
+        assert_eq!(
+            (
+                withdrawals[1].status.as_str(),
+                withdrawals[1].amount.as_deref()
+            ),
+            ("sending", None)
+        );
+
+        let pending = ChainKeyPending {
+            source: "ckBTC".into(),
+            token: "ckBTC".into(),
+            deposit_address: None,
+            deposits: confirming,
+            withdrawals,
+        };
+        // the withdrawal without an amount is not counted as a zero holding
+        let statuses: Vec<_> = pending.holdings().into_iter().map(|h| h.status).collect();
+        assert_eq!(statuses, ["pending_deposit", "pending_withdrawal"]);
+    }
+
+    #[test]
+    fn eth_withdrawals_grouped_with_settled_flag() {
+        let detail = |id, token: &str, amount: u64, status| WithdrawalDetail {
+            withdrawal_id: id,
+            token_symbol: token.into(),
+            withdrawal_amount: Nat::from(amount),
+            status,
+        };
+        let tx = || EthTransaction {
+            transaction_hash: "0xabc".into(),
+        };
+        let details = vec![
+            detail(
+                1,
+                "ckETH",
+                500_000_000_000_000_000,
+                WithdrawalStatus::Pending,
+            ),
+            detail(2, "ckUSDC", 25_000_000, WithdrawalStatus::TxSent(tx())),
+            detail(
+                3,
+                "ckETH",
+                1_000_000_000_000_000_000,
+                WithdrawalStatus::TxFinalized(TxFinalizedStatus::Success {
+                    transaction_hash: "0xdef".into(),
+                    effective_transaction_fee: None,
+                }),
+            ),
+            detail(
+                4,
+                "ckETH",
+                200_000_000_000_000_000,
+                WithdrawalStatus::TxFinalized(TxFinalizedStatus::PendingReimbursement(tx())),
+            ),
+        ];
+        let decimals = HashMap::from([("ckUSDC".to_string(), 6)]);
+        let pending = eth_withdrawals(details, &decimals);
+        assert_eq!(pending.len(), 2);
+        let (eth, usdc) = (&pending[0], &pending[1]);
+        assert_eq!(
+            (eth.token.as_str(), usdc.token.as_str()),
+            ("ckETH", "ckUSDC")
+        );
+        assert_eq!(usdc.withdrawals[0].amount.as_deref(), Some("25.000000"));
+        assert_eq!(usdc.withdrawals[0].status, "sent");
+        let statuses: Vec<_> = eth.withdrawals.iter().map(|w| w.status.as_str()).collect();
+        assert_eq!(statuses, ["pending", "finalized", "failed_reimbursing"]);
+        // the finalized withdrawal has landed and is not a holding
+        let amounts: Vec<_> = eth.holdings().into_iter().map(|h| h.amount).collect();
+        assert_eq!(amounts.len(), 2);
+        assert!(amounts[0].starts_with("0.5"));
+    }
+
+    #[quickcheck]
+    fn fuzz_decode_utxos(data: Vec<u8>) -> bool {
+        let _ = Decode!(&data, GetUtxosResponse);
+        true
+    }
+}
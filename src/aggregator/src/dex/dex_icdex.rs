@@ -0,0 +1,214 @@
+use super::{call, DexAdapter, OpenOrder, OrderSide};
+use crate::error::FetchError;
+use crate::utils::format_amount;
+use async_trait::async_trait;
+use bx_core::Holding;
+use candid::{CandidType, Nat, Principal};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+
+// ICDex is an order book: each trading pair is its own canister, listed by
+// a router. Funds sit either in open orders or idle in the trader's account
+// on the pair, so nothing here is an LP position.
+
+/// Pair canisters queried at once
+const PAIR_CONCURRENCY: usize = 8;
+
+#[derive(CandidType, Deserialize, Clone)]
+struct TokenInfo {
+    symbol: String,
+    decimals: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct PairInfo {
+    canister_id: Principal,
+    /// Base token; order quantities are in its units
+    token0: TokenInfo,
+    /// Quote token; prices are its units per whole base token
+    token1: TokenInfo,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Order {
+    id: u64,
+    side: OrderSide,
+    price: Nat,
+    remaining: Nat,
+}
+
+/// Idle funds in the trader's account on a pair
+#[derive(CandidType, Deserialize, Clone)]
+struct AccountBalance {
+    token0: Nat,
+    token1: Nat,
+}
+
+pub struct IcdexAdapter;
+
+fn router() -> Result<Principal, FetchError> {
+    crate::utils::env_principal("ICDEX_ROUTER")
+        .ok_or_else(|| FetchError::InvalidConfig("router".into()))
+}
+
+fn open_order(pair: &PairInfo, order: Order) -> OpenOrder {
+    let (base, quote) = (&pair.token0, &pair.token1);
+    let (locked_token, locked_amount) = match order.side {
+        OrderSide::Sell => (base, format_amount(order.remaining.clone(), base.decimals)),
+        OrderSide::Buy => {
+            let scale = Nat(num_bigint::BigUint::from(10u32).pow(base.decimals as u32));
+            let locked = order.remaining.clone() * order.price.clone() / scale;
+            (quote, format_amount(locked, quote.decimals))
+        }
+    };
+    OpenOrder {
+        source: "ICDex".into(),
+        pair: format!("{}/{}", base.symbol, quote.symbol),
+        side: order.side,
+        price: format_amount(order.price, quote.decimals),
+        remaining: format_amount(order.remaining, base.decimals),
+        locked_token: locked_token.symbol.clone(),
+        locked_amount,
+    }
+}
+
+fn balance_holdings(pair: &PairInfo, balance: AccountBalance) -> Vec<Holding> {
+    [
+        (&pair.token0, balance.token0),
+        (&pair.token1, balance.token1),
+    ]
+    .into_iter()
+    .filter(|(_, amount)| *amount > 0u64)
+    .map(|(token, amount)| Holding {
+        source: "ICDex".into(),
+        token: token.symbol.clone(),
+        amount: format_amount(amount, token.decimals),
+        status: "trading_balance".into(),
+    })
+    .collect()
+}
+
+async fn scan_pair(
+    pair: PairInfo,
+    principal: Principal,
+) -> Result<(Vec<OpenOrder>, Vec<Holding>), FetchError> {
+    let cid = pair.canister_id;
+    let (orders, balance) = futures::try_join!(
+        call::<_, Vec<Order>>(cid, "pending", (principal,), false),
+        call::<_, AccountBalance>(cid, "accountBalance", (principal,), false)
+    )?;
+    let orders = orders.into_iter().map(|o| open_order(&pair, o)).collect();
+    Ok((orders, balance_holdings(&pair, balance)))
+}
+
+/// Open orders and idle balances across every pair. Pairs that cannot be
+/// reached are skipped so one broken pair does not hide the rest.
+async fn scan(principal: Principal) -> Result<(Vec<OpenOrder>, Vec<Holding>), FetchError> {
+    let pairs: Vec<PairInfo> = call(router()?, "getPairs", (), false).await?;
+    let scans: Vec<_> = pairs
+        .into_iter()
+        .map(|pair| async move {
+            let cid = pair.canister_id;
+            (cid, scan_pair(pair, principal).await)
+        })
+        .collect();
+    let results: Vec<_> = stream::iter(scans)
+        .buffered(PAIR_CONCURRENCY)
+        .collect()
+        .await;
+    let mut orders = Vec::new();
+    let mut balances = Vec::new();
+    for (cid, res) in results {
+        match res {
+            Ok((o, b)) => {
+                orders.extend(o);
+                balances.extend(b);
+            }
+            Err(e) => tracing::warn!(pair = %cid, "ICDex pair scan failed: {e}"),
+        }
+    }
+    Ok((orders, balances))
+}
+
+#[async_trait]
+impl DexAdapter for IcdexAdapter {
+    async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
+        let (orders, balances) = scan(principal).await?;
+        let mut out: Vec<Holding> = orders.iter().map(OpenOrder::holding).collect();
+        out.extend(balances);
+        Ok(out)
+    }
+
+    async fn open_orders(&self, principal: Principal) -> Result<Vec<OpenOrder>, FetchError> {
+        Ok(scan(principal).await?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Decode;
+    use quickcheck_macros::quickcheck;
+
+    fn pair() -> PairInfo {
+        PairInfo {
+            canister_id: Principal::anonymous(),
+            token0: TokenInfo {
+                symbol: "ICP".into(),
+                decimals: 8,
+            },
+            token1: TokenInfo {
+                symbol: "ckUSDT".into(),
+                decimals: 6,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_without_env() {
+        let res = IcdexAdapter.fetch_positions(Principal::anonymous()).await;
+        assert!(matches!(res, Err(FetchError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn orders_lock_base_or_quote_by_side() {
+        // 2.5 ICP at 8 ckUSDT each
+        let order = |side| Order {
+            id: 1,
+            side,
+            price: Nat::from(8_000_000u64),
+            remaining: Nat::from(250_000_000u64),
+        };
+        let sell = open_order(&pair(), order(OrderSide::Sell));
+        assert_eq!(sell.pair, "ICP/ckUSDT");
+        assert_eq!(sell.price, "8.000000");
+        assert_eq!(sell.remaining, "2.50000000");
+        assert_eq!(
+            (sell.locked_token.as_str(), sell.locked_amount.as_str()),
+            ("ICP", "2.50000000")
+        );
+        let buy = open_order(&pair(), order(OrderSide::Buy));
+        assert_eq!(
+            (buy.locked_token.as_str(), buy.locked_amount.as_str()),
+            ("ckUSDT", "20.000000")
+        );
+        assert_eq!(buy.holding().status, "open_order");
+
+        let idle = balance_holdings(
+            &pair(),
+            AccountBalance {
+                token0: Nat::from(0u64),
+                token1: Nat::from(1_500_000u64),
+            },
+        );
+        assert_eq!(idle.len(), 1);
+        assert_eq!(idle[0].amount, "1.500000");
+        assert_eq!(idle[0].status, "trading_balance");
+    }
+
+    #[quickcheck]
+    fn fuzz_decode_orders(data: Vec<u8>) -> bool {
+        let _ = Decode!(&data, Vec<Order>);
+        true
+    }
+}
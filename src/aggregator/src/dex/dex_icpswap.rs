@@ -1,10 +1,11 @@
-use super::DexAdapter;
+use super::{DexAdapter, DiscoveredPool, PoolReserves};
 use crate::error::FetchError;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::{
     lp_cache,
-    utils::{format_amount, get_agent, now},
+    utils::{get_agent, now},
 };
+use crate::{position_index, utils::format_amount};
 use async_trait::async_trait;
 use bx_core::Holding;
 use candid::{CandidType, Nat, Principal};
@@ -12,7 +13,8 @@ use candid::{CandidType, Nat, Principal};
 use candid::{Decode, Encode};
 #[cfg(not(target_arch = "wasm32"))]
 use dashmap::DashMap;
-#[cfg(not(target_arch = "wasm32"))]
+use futures::stream::{self, StreamExt};
+use num_traits::ToPrimitive;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 
@@ -50,17 +52,43 @@ struct PoolMetadata {
     token1_decimals: u8,
 }
 
+/// Per-token amounts returned by a pool's `get_reserves` and `get_volume_24h`
+#[derive(CandidType, Deserialize)]
+struct TokenAmounts {
+    token0: Nat,
+    token1: Nat,
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 static META_CACHE: Lazy<DashMap<Principal, (PoolMetadata, u64)>> = Lazy::new(DashMap::new);
 #[cfg(not(target_arch = "wasm32"))]
+static META_FLIGHT: Lazy<crate::singleflight::Group<Principal, Option<PoolMetadata>>> =
+    Lazy::new(crate::singleflight::Group::new);
+#[cfg(not(target_arch = "wasm32"))]
 const META_TTL_NS: u64 = crate::utils::DAY_NS; // 24h
 
+/// Pools queried at once when scanning positions or claiming.
+static ICPSWAP_CONCURRENCY: Lazy<usize> = Lazy::new(|| {
+    option_env!("ICPSWAP_CONCURRENCY")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(16)
+        .max(1)
+});
+
 #[async_trait]
 impl DexAdapter for IcpswapAdapter {
     async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
         fetch_positions_impl(principal).await
     }
 
+    async fn list_pools(&self) -> Result<Vec<DiscoveredPool>, FetchError> {
+        list_pools_impl().await
+    }
+
+    async fn pool_reserves(&self) -> Result<Vec<PoolReserves>, FetchError> {
+        pool_reserves_impl().await
+    }
+
     #[cfg(feature = "claim")]
     async fn claim_rewards(&self, principal: Principal) -> Result<u64, String> {
         claim_rewards_impl(principal).await
@@ -75,67 +103,154 @@ pub fn clear_cache() {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-async fn fetch_positions_impl(principal: Principal) -> Result<Vec<Holding>, FetchError> {
-    let factory_id = match crate::utils::env_principal("ICPSWAP_FACTORY") {
-        Some(p) => p,
-        None => return Err(FetchError::InvalidConfig("factory".into())),
-    };
-    let agent = get_agent().await;
-    let arg = Encode!().map_err(|_| FetchError::InvalidResponse)?;
-    let bytes = match agent
-        .query(&factory_id, "getPools")
-        .with_arg(arg)
-        .call()
+async fn scan_pool(
+    agent: &ic_agent::Agent,
+    pool: &PoolData,
+    principal: Principal,
+) -> (Principal, Vec<Holding>) {
+    let height = crate::utils::dex_block_height(agent, pool.canister_id)
         .await
-    {
-        Ok(b) => b,
-        Err(e) => return Err(FetchError::from(e)),
+        .unwrap_or(0);
+    let holdings = lp_cache::get_or_fetch(principal, "ICPSwap", &pool.key, height, || async {
+        let (positions, meta) = futures::join!(
+            query_positions(agent, pool.canister_id, principal),
+            fetch_meta(agent, pool.canister_id)
+        );
+        let Some(meta) = meta else {
+            return Vec::new();
+        };
+        position_holdings(pool, positions.unwrap_or_default(), &meta)
+    })
+    .await;
+    (pool.canister_id, holdings)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn scan_pool(pool: &PoolData, principal: Principal) -> (Principal, Vec<Holding>) {
+    use ic_cdk::api::call::call;
+    let (positions, meta) = futures::join!(
+        call::<_, (Vec<UserPositionInfoWithTokenAmount>,)>(
+            pool.canister_id,
+            "get_user_positions_by_principal",
+            (principal,)
+        ),
+        call::<_, (PoolMetadata,)>(pool.canister_id, "metadata", ())
+    );
+    let Ok((meta,)) = meta else {
+        return (pool.canister_id, Vec::new());
     };
-    let pools: Vec<PoolData> =
-        Decode!(&bytes, Vec<PoolData>).map_err(|_| FetchError::InvalidResponse)?;
-    let mut out = Vec::with_capacity(pools.len() * 3);
-    for pool in pools.iter() {
-        let height = crate::utils::dex_block_height(&agent, pool.canister_id)
-            .await
-            .unwrap_or(0);
-        let pool_key = pool.key.clone();
-        let holdings = lp_cache::get_or_fetch(principal, &pool_key, height, || async {
-            let positions: Vec<UserPositionInfoWithTokenAmount> =
-                query_positions(&agent, pool.canister_id, principal)
-                    .await
-                    .unwrap_or_default();
-            let meta = match fetch_meta(&agent, pool.canister_id).await {
-                Some(m) => m,
-                None => return Vec::new(),
-            };
-            let mut temp = Vec::with_capacity(positions.len() * 3);
-            for pos in positions {
-                let a0 = format_amount(pos.token0_amount, meta.token0_decimals);
-                temp.push(Holding {
-                    source: "ICPSwap".into(),
-                    token: pool.token0.address.clone(),
-                    amount: a0,
-                    status: "lp_escrow".into(),
-                });
-                let a1 = format_amount(pos.token1_amount, meta.token1_decimals);
-                temp.push(Holding {
-                    source: "ICPSwap".into(),
-                    token: pool.token1.address.clone(),
-                    amount: a1,
-                    status: "lp_escrow".into(),
-                });
-            }
-            temp
-        })
-        .await;
-        out.extend(holdings);
+    let positions = positions.map(|(p,)| p).unwrap_or_default();
+    (pool.canister_id, position_holdings(pool, positions, &meta))
+}
+
+fn position_holdings(
+    pool: &PoolData,
+    positions: Vec<UserPositionInfoWithTokenAmount>,
+    meta: &PoolMetadata,
+) -> Vec<Holding> {
+    let mut out = Vec::with_capacity(positions.len() * 2);
+    for pos in positions {
+        let a0 = format_amount(pos.token0_amount, meta.token0_decimals);
+        out.push(Holding {
+            source: "ICPSwap".into(),
+            token: pool.token0.address.clone(),
+            amount: a0,
+            status: "lp_escrow".into(),
+        });
+        let a1 = format_amount(pos.token1_amount, meta.token1_decimals);
+        out.push(Holding {
+            source: "ICPSwap".into(),
+            token: pool.token1.address.clone(),
+            amount: a1,
+            status: "lp_escrow".into(),
+        });
     }
-    Ok(out)
+    out
+}
+
+/// The principal's indexed pools, or every pool when the index has no
+/// usable entry or `full` is set, with the index lookup that chose them.
+fn pools_to_scan(
+    pools: &[PoolData],
+    principal: Principal,
+    full: bool,
+) -> (Vec<&PoolData>, Option<Vec<Principal>>) {
+    let indexed = if full {
+        None
+    } else {
+        position_index::lookup(principal)
+    };
+    let scan: Vec<&PoolData> = match &indexed {
+        Some(ids) => pools
+            .iter()
+            .filter(|p| ids.contains(&p.canister_id))
+            .collect(),
+        None => pools.iter().collect(),
+    };
+    if !full {
+        crate::metrics::record_position_scan(scan.len());
+    }
+    (scan, indexed)
+}
+
+/// Update the index with the pools that held positions and return the
+/// holdings found.
+fn record_scan(
+    principal: Principal,
+    indexed: Option<Vec<Principal>>,
+    results: Vec<(Principal, Vec<Holding>)>,
+) -> Vec<Holding> {
+    let held = results
+        .iter()
+        .filter(|(_, h)| !h.is_empty())
+        .map(|(cid, _)| *cid)
+        .collect();
+    match indexed {
+        Some(_) => position_index::record_partial_scan(principal, held),
+        None => position_index::record_full_scan(principal, held),
+    }
+    results.into_iter().flat_map(|(_, h)| h).collect()
+}
+
+/// Scan the principal's pools and update the index with the result.
+#[cfg(not(target_arch = "wasm32"))]
+async fn scan_positions(principal: Principal, full: bool) -> Result<Vec<Holding>, FetchError> {
+    let agent = get_agent().await;
+    let pools = fetch_pools(&agent).await?;
+    let (scan, indexed) = pools_to_scan(&pools, principal, full);
+    let scans: Vec<_> = scan
+        .into_iter()
+        .map(|pool| scan_pool(&agent, pool, principal))
+        .collect();
+    let results = stream::iter(scans)
+        .buffered(*ICPSWAP_CONCURRENCY)
+        .collect()
+        .await;
+    Ok(record_scan(principal, indexed, results))
 }
 
 #[cfg(target_arch = "wasm32")]
-async fn fetch_positions_impl(_principal: Principal) -> Result<Vec<Holding>, FetchError> {
-    Ok(Vec::new())
+async fn scan_positions(principal: Principal, full: bool) -> Result<Vec<Holding>, FetchError> {
+    let pools = fetch_pools().await?;
+    let (scan, indexed) = pools_to_scan(&pools, principal, full);
+    let scans: Vec<_> = scan
+        .into_iter()
+        .map(|pool| scan_pool(pool, principal))
+        .collect();
+    let results = stream::iter(scans)
+        .buffered(*ICPSWAP_CONCURRENCY)
+        .collect()
+        .await;
+    Ok(record_scan(principal, indexed, results))
+}
+
+async fn fetch_positions_impl(principal: Principal) -> Result<Vec<Holding>, FetchError> {
+    scan_positions(principal, false).await
+}
+
+/// Refresh the principal's position index entry from a scan of every pool.
+pub async fn rescan(principal: Principal) -> Result<(), FetchError> {
+    scan_positions(principal, true).await.map(|_| ())
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -161,6 +276,11 @@ async fn fetch_meta(agent: &ic_agent::Agent, cid: Principal) -> Option<PoolMetad
             return Some(entry.value().0.clone());
         }
     }
+    META_FLIGHT.run(cid, || load_meta(agent, cid)).await
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn load_meta(agent: &ic_agent::Agent, cid: Principal) -> Option<PoolMetadata> {
     let arg = Encode!().ok()?;
     let bytes = agent
         .query(&cid, "metadata")
@@ -173,6 +293,132 @@ async fn fetch_meta(agent: &ic_agent::Agent, cid: Principal) -> Option<PoolMetad
     Some(meta)
 }
 
+fn discovered(pool: PoolData, meta: Option<PoolMetadata>) -> DiscoveredPool {
+    DiscoveredPool {
+        id: pool.canister_id.to_text(),
+        source: "ICPSwap".into(),
+        token_a: pool.token0.address,
+        token_b: pool.token1.address,
+        decimals_a: meta.as_ref().map(|m| m.token0_decimals),
+        decimals_b: meta.as_ref().map(|m| m.token1_decimals),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn fetch_pools(agent: &ic_agent::Agent) -> Result<Vec<PoolData>, FetchError> {
+    let factory_id = crate::utils::env_principal("ICPSWAP_FACTORY")
+        .ok_or_else(|| FetchError::InvalidConfig("factory".into()))?;
+    let arg = Encode!().map_err(|_| FetchError::InvalidResponse)?;
+    let bytes = agent
+        .query(&factory_id, "getPools")
+        .with_arg(arg)
+        .call()
+        .await?;
+    Decode!(&bytes, Vec<PoolData>).map_err(|_| FetchError::InvalidResponse)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_pools() -> Result<Vec<PoolData>, FetchError> {
+    let factory_id = crate::utils::env_principal("ICPSWAP_FACTORY")
+        .ok_or_else(|| FetchError::InvalidConfig("factory".into()))?;
+    let (pools,): (Vec<PoolData>,) = ic_cdk::api::call::call(factory_id, "getPools", ())
+        .await
+        .map_err(|(_, e)| FetchError::Network(e))?;
+    Ok(pools)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn list_pools_impl() -> Result<Vec<DiscoveredPool>, FetchError> {
+    let agent = get_agent().await;
+    let pools = fetch_pools(&agent).await?;
+    let mut out = Vec::with_capacity(pools.len());
+    for pool in pools {
+        let meta = fetch_meta(&agent, pool.canister_id).await;
+        out.push(discovered(pool, meta));
+    }
+    Ok(out)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn list_pools_impl() -> Result<Vec<DiscoveredPool>, FetchError> {
+    use ic_cdk::api::call::call;
+    let pools = fetch_pools().await?;
+    let mut out = Vec::with_capacity(pools.len());
+    for pool in pools {
+        let meta: Option<PoolMetadata> = call(pool.canister_id, "metadata", ())
+            .await
+            .ok()
+            .map(|(m,)| m);
+        out.push(discovered(pool, meta));
+    }
+    Ok(out)
+}
+
+fn reserves(pool: &PoolData, held: TokenAmounts, volume: Option<TokenAmounts>) -> PoolReserves {
+    let (volume_a, volume_b) = match volume {
+        Some(v) => (Some(v.token0), Some(v.token1)),
+        None => (None, None),
+    };
+    PoolReserves {
+        id: pool.canister_id.to_text(),
+        reserve_a: held.token0,
+        reserve_b: held.token1,
+        volume_a,
+        volume_b,
+        fee_ppm: pool.fee.0.to_u32(),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn query_amounts(
+    agent: &ic_agent::Agent,
+    cid: Principal,
+    method: &str,
+) -> Option<TokenAmounts> {
+    let arg = Encode!().ok()?;
+    let bytes = agent.query(&cid, method).with_arg(arg).call().await.ok()?;
+    Decode!(&bytes, TokenAmounts).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn pool_reserves_impl() -> Result<Vec<PoolReserves>, FetchError> {
+    let agent = get_agent().await;
+    let pools = fetch_pools(&agent).await?;
+    let mut out = Vec::with_capacity(pools.len());
+    for pool in pools {
+        let Some(held) = query_amounts(&agent, pool.canister_id, "get_reserves").await else {
+            tracing::debug!(pool = %pool.canister_id, "pool reserves unavailable");
+            continue;
+        };
+        // older pools have no volume endpoint; analytics fall back to TVL only
+        let volume = query_amounts(&agent, pool.canister_id, "get_volume_24h").await;
+        out.push(reserves(&pool, held, volume));
+    }
+    Ok(out)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn pool_reserves_impl() -> Result<Vec<PoolReserves>, FetchError> {
+    use ic_cdk::api::call::call;
+    let pools = fetch_pools().await?;
+    let mut out = Vec::with_capacity(pools.len());
+    for pool in pools {
+        let held: TokenAmounts = match call(pool.canister_id, "get_reserves", ()).await {
+            Ok((h,)) => h,
+            Err(_) => {
+                tracing::debug!(pool = %pool.canister_id, "pool reserves unavailable");
+                continue;
+            }
+        };
+        let volume: Option<TokenAmounts> = call(pool.canister_id, "get_volume_24h", ())
+            .await
+            .ok()
+            .map(|(v,)| v);
+        out.push(reserves(&pool, held, volume));
+    }
+    Ok(out)
+}
+
 #[cfg(all(feature = "claim", not(target_arch = "wasm32")))]
 async fn claim_rewards_impl(principal: Principal) -> Result<u64, String> {
     use crate::cache;
@@ -180,7 +426,7 @@ async fn claim_rewards_impl(principal: Principal) -> Result<u64, String> {
         Some(p) => p,
         None => return Err("factory".into()),
     };
-    let ledger = crate::ledger_fetcher::LEDGERS
+    let ledger = crate::config::ledger_ids()
         .first()
         .cloned()
         .ok_or("ledger")?;
@@ -193,24 +439,34 @@ async fn claim_rewards_impl(principal: Principal) -> Result<u64, String> {
         .await
         .map_err(|e| e.to_string())?;
     let pools: Vec<PoolData> = Decode!(&bytes, Vec<PoolData>).map_err(|_| "invalid response")?;
-    let mut total: u64 = 0;
-    for pool in pools {
-        let arg = Encode!(&principal, &ledger).map_err(|e| e.to_string())?;
-        let bytes = agent
-            .update(&pool.canister_id, "claim")
-            .with_arg(arg)
-            .call_and_wait()
-            .await
-            .map_err(|e| e.to_string())?;
-        let spent: u64 = Decode!(&bytes, u64).map_err(|_| "invalid response")?;
-        total = total.checked_add(spent).ok_or("overflow")?;
+    let agent = &agent;
+    let claims: Vec<_> = pools
+        .into_iter()
+        .map(|pool| async move {
+            let res = async {
+                let arg = Encode!(&principal, &ledger).map_err(|e| e.to_string())?;
+                let bytes = agent
+                    .update(&pool.canister_id, "claim")
+                    .with_arg(arg)
+                    .call_and_wait()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Decode!(&bytes, u64).map_err(|_| "invalid response".to_string())
+            };
+            (pool.canister_id, res.await)
+        })
+        .collect();
+    let claims: Vec<(Principal, Result<u64, String>)> = stream::iter(claims)
+        .buffer_unordered(*ICPSWAP_CONCURRENCY)
+        .collect()
+        .await;
+    let total = settle_claims(claims);
+    // refresh cache even when some pools failed, since others have paid out
+    match fetch_positions_impl(principal).await {
+        Ok(holdings) => cache::store(principal, "ICPSwap", holdings),
+        Err(e) => tracing::warn!("ICPSwap refresh after claim failed: {e:?}"),
     }
-    // refresh cache
-    let holdings = fetch_positions_impl(principal)
-        .await
-        .map_err(|e| format!("{:?}", e))?;
-    cache::get().insert(principal, (holdings, now()));
-    Ok(total)
+    total
 }
 
 #[cfg(all(feature = "claim", target_arch = "wasm32"))]
@@ -221,23 +477,58 @@ async fn claim_rewards_impl(principal: Principal) -> Result<u64, String> {
         Some(p) => p,
         None => return Err("factory".into()),
     };
-    let ledger = crate::ledger_fetcher::LEDGERS
+    let ledger = crate::config::ledger_ids()
         .first()
         .cloned()
         .ok_or("ledger")?;
     let (pools,): (Vec<PoolData>,) = call(factory_id, "getPools", ()).await.map_err(|(_, e)| e)?;
+    let claims: Vec<_> = pools
+        .into_iter()
+        .map(|pool| async move {
+            let res: Result<(u64,), _> = call(pool.canister_id, "claim", (principal, ledger)).await;
+            (
+                pool.canister_id,
+                res.map(|(spent,)| spent).map_err(|(_, e)| e),
+            )
+        })
+        .collect();
+    let claims: Vec<(Principal, Result<u64, String>)> = stream::iter(claims)
+        .buffer_unordered(*ICPSWAP_CONCURRENCY)
+        .collect()
+        .await;
+    let total = settle_claims(claims);
+    match fetch_positions_impl(principal).await {
+        Ok(holdings) => cache::store(principal, "ICPSwap", holdings),
+        Err(e) => tracing::warn!("ICPSwap refresh after claim failed: {e:?}"),
+    }
+    total
+}
+
+/// Sum what the pools paid out. Pools that failed are logged rather than
+/// discarding the claims that already went through; the result is only an
+/// error when no pool paid.
+#[cfg(feature = "claim")]
+fn settle_claims(claims: Vec<(Principal, Result<u64, String>)>) -> Result<u64, String> {
     let mut total: u64 = 0;
-    for pool in pools {
-        let (spent,): (u64,) = call(pool.canister_id, "claim", (principal, ledger))
-            .await
-            .map_err(|(_, e)| e)?;
-        total = total.checked_add(spent).ok_or("overflow")?;
+    let mut paid = false;
+    let mut failures = Vec::new();
+    for (pool, claim) in claims {
+        match claim {
+            Ok(spent) => {
+                total = total.saturating_add(spent);
+                paid = true;
+            }
+            Err(e) => {
+                tracing::warn!(%pool, "ICPSwap claim failed: {e}");
+                failures.push(format!("{pool}: {e}"));
+            }
+        }
+    }
+    if paid || failures.is_empty() {
+        Ok(total)
+    } else {
+        Err(failures.join("; "))
     }
-    let holdings = fetch_positions_impl(principal)
-        .await
-        .map_err(|e| format!("{:?}", e))?;
-    cache::get().insert(principal, (holdings, now()));
-    Ok(total)
 }
 
 #[cfg(test)]
@@ -260,6 +551,21 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[cfg(feature = "claim")]
+    #[test]
+    fn failed_claims_keep_paid_pools() {
+        let pool = |b: u8| Principal::from_slice(&[b]);
+        let claims = vec![
+            (pool(1), Ok(5)),
+            (pool(2), Err("rejected".to_string())),
+            (pool(3), Ok(7)),
+        ];
+        assert_eq!(settle_claims(claims), Ok(12));
+        let err = settle_claims(vec![(pool(2), Err("rejected".to_string()))]).unwrap_err();
+        assert!(err.contains("rejected"), "{err}");
+        assert_eq!(settle_claims(Vec::new()), Ok(0));
+    }
+
     #[quickcheck]
     fn fuzz_decode_pool(data: Vec<u8>) -> bool {
         let _ = Decode!(&data, Vec<PoolData>);
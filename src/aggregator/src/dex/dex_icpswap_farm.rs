@@ -0,0 +1,289 @@
+use super::{call, DexAdapter, RewardInfo};
+use crate::error::FetchError;
+use crate::utils::format_amount;
+use async_trait::async_trait;
+use bx_core::Holding;
+use candid::{CandidType, Nat, Principal};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+
+// ICPSwap farms hold staked LP position NFTs and pay a reward token; staking
+// pools take a single token instead. Both are separate canisters listed by
+// the farm index, and both accrue rewards until harvested.
+
+/// Farm canisters queried at once
+const FARM_CONCURRENCY: usize = 8;
+
+#[derive(CandidType, Deserialize, Clone)]
+struct TokenInfo {
+    symbol: String,
+    decimals: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum FarmKind {
+    /// Stakes LP position NFTs
+    Farm,
+    /// Stakes a single token
+    Stake,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct FarmInfo {
+    canister_id: Principal,
+    kind: FarmKind,
+    reward_token: TokenInfo,
+    /// Staked token of a staking pool; farms report tokens per deposit
+    stake_token: Option<TokenInfo>,
+}
+
+/// An LP position staked in a farm, with its underlying amounts
+#[derive(CandidType, Deserialize, Clone)]
+struct Deposit {
+    position_id: Nat,
+    token0: TokenInfo,
+    token0_amount: Nat,
+    token1: TokenInfo,
+    token1_amount: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct UserStake {
+    staked: Nat,
+}
+
+/// Staked funds and pending reward in one farm
+struct FarmPosition {
+    staked: Vec<Holding>,
+    reward: Option<RewardInfo>,
+}
+
+pub struct IcpswapFarmAdapter;
+
+fn farm_index() -> Result<Principal, FetchError> {
+    crate::utils::env_principal("ICPSWAP_FARM_INDEX")
+        .ok_or_else(|| FetchError::InvalidConfig("farm index".into()))
+}
+
+async fn farms() -> Result<Vec<FarmInfo>, FetchError> {
+    call(farm_index()?, "getFarms", (), false).await
+}
+
+fn staked(token: &TokenInfo, amount: Nat) -> Option<Holding> {
+    (amount > 0u64).then(|| Holding {
+        source: "ICPSwapFarm".into(),
+        token: token.symbol.clone(),
+        amount: format_amount(amount, token.decimals),
+        status: "staked".into(),
+    })
+}
+
+fn deposit_holdings(deposits: Vec<Deposit>) -> Vec<Holding> {
+    let mut out = Vec::with_capacity(deposits.len() * 2);
+    for d in deposits {
+        out.extend(staked(&d.token0, d.token0_amount));
+        out.extend(staked(&d.token1, d.token1_amount));
+    }
+    out
+}
+
+fn pending_reward(farm: &FarmInfo, pending: Nat) -> Option<RewardInfo> {
+    (pending > 0u64).then(|| RewardInfo {
+        token: farm.reward_token.symbol.clone(),
+        amount: format_amount(pending, farm.reward_token.decimals),
+    })
+}
+
+async fn scan_farm(farm: &FarmInfo, principal: Principal) -> Result<FarmPosition, FetchError> {
+    let cid = farm.canister_id;
+    let staked_fut = async {
+        match (farm.kind, &farm.stake_token) {
+            (FarmKind::Farm, _) => {
+                let deposits: Vec<Deposit> =
+                    call(cid, "getUserDeposits", (principal,), false).await?;
+                Ok(deposit_holdings(deposits))
+            }
+            (FarmKind::Stake, Some(token)) => {
+                let stake: UserStake = call(cid, "getUserInfo", (principal,), false).await?;
+                Ok(staked(token, stake.staked).into_iter().collect())
+            }
+            (FarmKind::Stake, None) => Err(FetchError::InvalidResponse),
+        }
+    };
+    let (staked, pending) = futures::try_join!(
+        staked_fut,
+        call::<_, Nat>(cid, "pendingReward", (principal,), false)
+    )?;
+    Ok(FarmPosition {
+        staked,
+        reward: pending_reward(farm, pending),
+    })
+}
+
+/// Positions in every listed farm and staking pool. Farms that cannot be
+/// reached are skipped so one broken farm does not hide the rest.
+async fn scan(principal: Principal) -> Result<Vec<(FarmInfo, FarmPosition)>, FetchError> {
+    let scans: Vec<_> = farms()
+        .await?
+        .into_iter()
+        .map(|farm| async move {
+            let res = scan_farm(&farm, principal).await;
+            (farm, res)
+        })
+        .collect();
+    let results: Vec<_> = stream::iter(scans)
+        .buffered(FARM_CONCURRENCY)
+        .collect()
+        .await;
+    let mut out = Vec::with_capacity(results.len());
+    for (farm, res) in results {
+        match res {
+            Ok(pos) => out.push((farm, pos)),
+            Err(e) => tracing::warn!(farm = %farm.canister_id, "ICPSwap farm scan failed: {e}"),
+        }
+    }
+    Ok(out)
+}
+
+async fn fetch_positions_impl(principal: Principal) -> Result<Vec<Holding>, FetchError> {
+    let mut out = Vec::new();
+    for (_, pos) in scan(principal).await? {
+        out.extend(pos.staked);
+        out.extend(pos.reward.map(|r| Holding {
+            source: "ICPSwapFarm".into(),
+            token: r.token,
+            amount: r.amount,
+            status: "claimable".into(),
+        }));
+    }
+    Ok(out)
+}
+
+/// Harvest every farm with a pending reward; returns the raw amount paid.
+#[cfg(feature = "claim")]
+async fn claim_impl(principal: Principal) -> Result<u64, String> {
+    let positions = scan(principal).await.map_err(|e| e.to_string())?;
+    let mut harvests = Vec::new();
+    for (farm, _) in positions.iter().filter(|(_, p)| p.reward.is_some()) {
+        let res: Result<Nat, String> = call(farm.canister_id, "harvest", (principal,), true)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r);
+        harvests.push((farm.canister_id, res));
+    }
+    let total = settle_harvests(harvests);
+    // refresh the cache even when some farms failed, since others have paid out
+    match fetch_positions_impl(principal).await {
+        Ok(holdings) => crate::cache::store(principal, "ICPSwapFarm", holdings),
+        Err(e) => tracing::warn!("ICPSwap farm refresh after claim failed: {e:?}"),
+    }
+    Ok(total)
+}
+
+/// Sum what the farms paid out. Rejected and failed harvests are logged
+/// rather than discarding the rewards that already went through.
+#[cfg(feature = "claim")]
+fn settle_harvests(harvests: Vec<(Principal, Result<Nat, String>)>) -> u64 {
+    use num_traits::ToPrimitive;
+    let mut total: u64 = 0;
+    for (farm, res) in harvests {
+        match res {
+            Ok(paid) => total = total.saturating_add(paid.0.to_u64().unwrap_or(u64::MAX)),
+            Err(e) => tracing::warn!(%farm, "harvest failed: {e}"),
+        }
+    }
+    total
+}
+
+#[async_trait]
+impl DexAdapter for IcpswapFarmAdapter {
+    async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
+        fetch_positions_impl(principal).await
+    }
+
+    async fn claimable_rewards(&self, principal: Principal) -> Result<Vec<RewardInfo>, FetchError> {
+        Ok(scan(principal)
+            .await?
+            .into_iter()
+            .filter_map(|(_, pos)| pos.reward)
+            .collect())
+    }
+
+    #[cfg(feature = "claim")]
+    async fn claim_rewards(&self, principal: Principal) -> Result<u64, String> {
+        claim_impl(principal).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Decode;
+    use quickcheck_macros::quickcheck;
+
+    fn token(symbol: &str, decimals: u8) -> TokenInfo {
+        TokenInfo {
+            symbol: symbol.into(),
+            decimals,
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_without_env() {
+        let res = IcpswapFarmAdapter
+            .fetch_positions(Principal::anonymous())
+            .await;
+        assert!(matches!(res, Err(FetchError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn deposits_report_underlying_amounts() {
+        let deposit = |a0: u64, a1: u64| Deposit {
+            position_id: Nat::from(7u64),
+            token0: token("ICP", 8),
+            token0_amount: Nat::from(a0),
+            token1: token("ckUSDC", 6),
+            token1_amount: Nat::from(a1),
+        };
+        let out = deposit_holdings(vec![deposit(150_000_000, 2_500_000), deposit(0, 1)]);
+        assert_eq!(out.len(), 3);
+        assert_eq!(
+            (out[0].token.as_str(), out[0].amount.as_str()),
+            ("ICP", "1.50000000")
+        );
+        assert_eq!(out[1].amount, "2.500000");
+        assert!(out.iter().all(|h| h.status == "staked"));
+
+        let farm = FarmInfo {
+            canister_id: Principal::anonymous(),
+            kind: FarmKind::Farm,
+            reward_token: token("ICS", 8),
+            stake_token: None,
+        };
+        assert!(pending_reward(&farm, Nat::from(0u64)).is_none());
+        let reward = pending_reward(&farm, Nat::from(12_000_000u64)).unwrap();
+        assert_eq!(
+            (reward.token.as_str(), reward.amount.as_str()),
+            ("ICS", "0.12000000")
+        );
+    }
+
+    #[cfg(feature = "claim")]
+    #[test]
+    fn failed_harvests_keep_paid_farms() {
+        let farm = |b: u8| Principal::from_slice(&[b]);
+        let harvests = vec![
+            (farm(1), Ok(Nat::from(5u64))),
+            (farm(2), Err("canister trapped".to_string())),
+            (farm(3), Ok(Nat::from(7u64))),
+        ];
+        assert_eq!(settle_harvests(harvests), 12);
+        assert_eq!(settle_harvests(vec![(farm(2), Err("rejected".into()))]), 0);
+    }
+
+    #[quickcheck]
+    fn fuzz_decode_farms(data: Vec<u8>) -> bool {
+        let _ = Decode!(&data, Vec<FarmInfo>);
+        true
+    }
+}
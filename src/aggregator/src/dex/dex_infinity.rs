@@ -1,39 +1,35 @@
-use super::DexAdapter;
+use super::{call, DexAdapter};
 use crate::error::FetchError;
+use crate::utils::now;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::{
     lp_cache,
-    utils::{format_amount, get_agent, now},
+    utils::{format_amount, get_agent},
 };
 use async_trait::async_trait;
 use bx_core::Holding;
-#[cfg(not(target_arch = "wasm32"))]
-use candid::Nat;
-use candid::{CandidType, Principal};
+use candid::{CandidType, Nat, Principal};
 #[cfg(not(target_arch = "wasm32"))]
 use candid::{Decode, Encode};
-#[cfg(not(target_arch = "wasm32"))]
 use dashmap::DashMap;
-#[cfg(not(target_arch = "wasm32"))]
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 
 pub struct InfinityAdapter;
 
-#[cfg(not(target_arch = "wasm32"))]
 pub fn clear_cache() {
     META_CACHE.clear();
 }
 
 #[derive(CandidType, Deserialize)]
-struct VaultPosition {
-    ledger: Principal,
-    subaccount: Vec<u8>,
+pub(super) struct VaultPosition {
+    pub(super) ledger: Principal,
+    pub(super) subaccount: Vec<u8>,
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 static META_CACHE: Lazy<DashMap<Principal, (String, u8, u64)>> = Lazy::new(DashMap::new);
-#[cfg(not(target_arch = "wasm32"))]
+static META_FLIGHT: Lazy<crate::singleflight::Group<Principal, Option<(String, u8)>>> =
+    Lazy::new(crate::singleflight::Group::new);
 const META_TTL_NS: u64 = crate::utils::DAY_NS; // 24h
 
 #[async_trait]
@@ -67,27 +63,28 @@ async fn fetch_positions_impl(principal: Principal) -> Result<Vec<Holding>, Fetc
     let height = crate::utils::dex_block_height(&agent, vault_id)
         .await
         .unwrap_or(0);
-    let holdings = lp_cache::get_or_fetch(principal, "infinity", height, || async {
-        let mut temp = Vec::with_capacity(positions.len() * 3);
-        for pos in positions {
-            let (symbol, decimals) = match fetch_meta(&agent, pos.ledger).await {
-                Some(v) => v,
-                None => continue,
-            };
-            let bal = match balance_of(&agent, pos.ledger, vault_id, pos.subaccount.clone()).await {
-                Some(n) => n,
-                None => continue,
-            };
-            temp.push(Holding {
-                source: "InfinitySwap".into(),
-                token: symbol,
-                amount: format_amount(bal, decimals),
-                status: "lp_escrow".into(),
-            });
-        }
-        temp
-    })
-    .await;
+    let holdings =
+        lp_cache::get_or_fetch(principal, "InfinitySwap", "infinity", height, || async {
+            let mut temp = Vec::with_capacity(positions.len() * 3);
+            for pos in positions {
+                let (symbol, decimals) = match fetch_meta(pos.ledger).await {
+                    Some(v) => v,
+                    None => continue,
+                };
+                let bal = match balance_of(pos.ledger, vault_id, pos.subaccount.clone()).await {
+                    Some(n) => n,
+                    None => continue,
+                };
+                temp.push(Holding {
+                    source: "InfinitySwap".into(),
+                    token: symbol,
+                    amount: format_amount(bal, decimals),
+                    status: "lp_escrow".into(),
+                });
+            }
+            temp
+        })
+        .await;
     Ok(holdings)
 }
 
@@ -96,48 +93,36 @@ async fn fetch_positions_impl(_principal: Principal) -> Result<Vec<Holding>, Fet
     Ok(Vec::new())
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-async fn balance_of(
-    agent: &ic_agent::Agent,
-    ledger: Principal,
-    owner: Principal,
-    sub: Vec<u8>,
-) -> Option<Nat> {
+/// Balance of `owner`'s subaccount `sub` on an ICRC-1 ledger. Shared with
+/// the generic locker adapter, which holds funds the same way.
+pub(super) async fn balance_of(ledger: Principal, owner: Principal, sub: Vec<u8>) -> Option<Nat> {
     #[derive(CandidType)]
     struct Account {
         owner: Principal,
         subaccount: Option<Vec<u8>>,
     }
-    let arg = Encode!(&Account {
+    let account = Account {
         owner,
         subaccount: Some(sub),
-    })
-    .ok()?;
-    let bytes = agent
-        .query(&ledger, "icrc1_balance_of")
-        .with_arg(arg)
-        .call()
+    };
+    call(ledger, "icrc1_balance_of", (account,), false)
         .await
-        .ok()?;
-    Decode!(&bytes, Nat).ok()
+        .ok()
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-async fn fetch_meta(agent: &ic_agent::Agent, ledger: Principal) -> Option<(String, u8)> {
+/// Symbol and decimals of an ICRC-1 ledger, cached for a day.
+pub(super) async fn fetch_meta(ledger: Principal) -> Option<(String, u8)> {
     if let Some(e) = META_CACHE.get(&ledger) {
         if e.value().2 > now() {
             return Some((e.value().0.clone(), e.value().1));
         }
     }
-    let arg = Encode!().ok()?;
-    let bytes = agent
-        .query(&ledger, "icrc1_metadata")
-        .with_arg(arg)
-        .call()
-        .await
-        .ok()?;
+    META_FLIGHT.run(ledger, || load_meta(ledger)).await
+}
+
+async fn load_meta(ledger: Principal) -> Option<(String, u8)> {
     let items: Vec<(String, candid::types::value::IDLValue)> =
-        Decode!(&bytes, Vec<(String, candid::types::value::IDLValue)>).ok()?;
+        call(ledger, "icrc1_metadata", (), false).await.ok()?;
     let mut symbol = String::new();
     let mut decimals = 0u8;
     for (k, v) in items {
@@ -0,0 +1,261 @@
+use super::{call, DexAdapter, RewardInfo};
+use crate::error::FetchError;
+use crate::utils::format_amount;
+use async_trait::async_trait;
+use bx_core::Holding;
+use candid::{CandidType, Nat, Principal};
+use serde::Deserialize;
+
+// KongSwap keeps every pool, LP balance and pending claim in one backend
+// canister. Users are identified by principal text, and replies carry more
+// fields than decoded here.
+
+/// Decimals assumed when a claim's ledger cannot be queried
+const DEFAULT_DECIMALS: u8 = 8;
+
+#[derive(CandidType, Deserialize, Clone)]
+struct LpReply {
+    /// Pool symbol, e.g. "ckBTC_ckUSDT"
+    symbol: String,
+    symbol_0: String,
+    /// Whole tokens, already scaled by the backend
+    amount_0: f64,
+    symbol_1: String,
+    amount_1: f64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum UserBalancesReply {
+    LP(LpReply),
+}
+
+/// A payout the backend could not deliver, waiting to be claimed
+#[derive(CandidType, Deserialize, Clone)]
+struct ClaimsReply {
+    claim_id: u64,
+    status: String,
+    symbol: String,
+    canister_id: Option<String>,
+    amount: Nat,
+}
+
+#[cfg(feature = "claim")]
+#[derive(CandidType, Deserialize, Clone)]
+struct ClaimReply {
+    status: String,
+    amount: Nat,
+}
+
+pub struct KongSwapAdapter;
+
+fn backend() -> Result<Principal, FetchError> {
+    crate::utils::env_principal("KONGSWAP_BACKEND")
+        .ok_or_else(|| FetchError::InvalidConfig("backend".into()))
+}
+
+/// Unwrap a backend result; users the backend has never seen have nothing.
+fn known_user<T>(res: Result<Vec<T>, String>) -> Result<Vec<T>, FetchError> {
+    match res {
+        Ok(v) => Ok(v),
+        Err(e) if e.contains("not found") => Ok(Vec::new()),
+        Err(e) => Err(FetchError::Network(e)),
+    }
+}
+
+async fn user_balances(
+    backend: Principal,
+    principal: Principal,
+) -> Result<Vec<LpReply>, FetchError> {
+    let res: Result<Vec<UserBalancesReply>, String> =
+        call(backend, "user_balances", (principal.to_text(),), false).await?;
+    Ok(known_user(res)?
+        .into_iter()
+        .map(|UserBalancesReply::LP(lp)| lp)
+        .collect())
+}
+
+async fn unclaimed(
+    backend: Principal,
+    principal: Principal,
+) -> Result<Vec<ClaimsReply>, FetchError> {
+    let res: Result<Vec<ClaimsReply>, String> =
+        call(backend, "claims", (principal.to_text(),), false).await?;
+    Ok(known_user(res)?
+        .into_iter()
+        .filter(|c| c.status == "Unclaimed")
+        .collect())
+}
+
+fn lp_holdings(balances: Vec<LpReply>) -> Vec<Holding> {
+    let mut out = Vec::with_capacity(balances.len() * 2);
+    for lp in balances {
+        for (token, amount) in [(lp.symbol_0, lp.amount_0), (lp.symbol_1, lp.amount_1)] {
+            if amount.is_finite() && amount > 0.0 {
+                out.push(Holding {
+                    source: "KongSwap".into(),
+                    token,
+                    amount: amount.to_string(),
+                    status: "lp_escrow".into(),
+                });
+            }
+        }
+    }
+    out
+}
+
+async fn claim_amount(claim: &ClaimsReply) -> String {
+    let ledger = claim
+        .canister_id
+        .as_deref()
+        .and_then(|id| Principal::from_text(id).ok());
+    let decimals = match ledger {
+        Some(id) => crate::utils::token_info(id).await.map(|t| t.decimals),
+        None => None,
+    };
+    format_amount(claim.amount.clone(), decimals.unwrap_or(DEFAULT_DECIMALS))
+}
+
+async fn fetch_positions_impl(principal: Principal) -> Result<Vec<Holding>, FetchError> {
+    let backend = backend()?;
+    let (balances, claims) = futures::try_join!(
+        user_balances(backend, principal),
+        unclaimed(backend, principal)
+    )?;
+    let mut out = lp_holdings(balances);
+    for c in claims {
+        out.push(Holding {
+            source: "KongSwap".into(),
+            amount: claim_amount(&c).await,
+            token: c.symbol,
+            status: "claimable".into(),
+        });
+    }
+    Ok(out)
+}
+
+/// Settle every unclaimed payout; returns the raw amount claimed.
+#[cfg(feature = "claim")]
+async fn claim_impl(principal: Principal) -> Result<u64, String> {
+    let backend = backend().map_err(|e| e.to_string())?;
+    let claims = unclaimed(backend, principal)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut replies = Vec::with_capacity(claims.len());
+    for c in claims {
+        let res: Result<ClaimReply, String> = call(backend, "claim", (c.claim_id,), true)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r);
+        replies.push((c.claim_id, res));
+    }
+    let total = settle_claims(replies);
+    // refresh the cache even when some claims failed, since others have paid out
+    match fetch_positions_impl(principal).await {
+        Ok(holdings) => crate::cache::store(principal, "KongSwap", holdings),
+        Err(e) => tracing::warn!("KongSwap refresh after claim failed: {e:?}"),
+    }
+    Ok(total)
+}
+
+/// Sum the claims the backend settled. Pending, rejected and failed calls
+/// are logged rather than discarding the claims that already went through.
+#[cfg(feature = "claim")]
+fn settle_claims(replies: Vec<(u64, Result<ClaimReply, String>)>) -> u64 {
+    use num_traits::ToPrimitive;
+    let mut total: u64 = 0;
+    for (claim, res) in replies {
+        match res {
+            Ok(reply) if reply.status == "Success" => {
+                total = total.saturating_add(reply.amount.0.to_u64().unwrap_or(u64::MAX));
+            }
+            Ok(reply) => tracing::warn!(claim, status = reply.status, "claim pending"),
+            Err(e) => tracing::warn!(claim, "claim failed: {e}"),
+        }
+    }
+    total
+}
+
+#[async_trait]
+impl DexAdapter for KongSwapAdapter {
+    async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
+        fetch_positions_impl(principal).await
+    }
+
+    async fn claimable_rewards(&self, principal: Principal) -> Result<Vec<RewardInfo>, FetchError> {
+        let backend = backend()?;
+        let mut out = Vec::new();
+        for c in unclaimed(backend, principal).await? {
+            out.push(RewardInfo {
+                amount: claim_amount(&c).await,
+                token: c.symbol,
+            });
+        }
+        Ok(out)
+    }
+
+    #[cfg(feature = "claim")]
+    async fn claim_rewards(&self, principal: Principal) -> Result<u64, String> {
+        claim_impl(principal).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Decode;
+    use quickcheck_macros::quickcheck;
+
+    #[tokio::test]
+    async fn empty_without_env() {
+        std::env::remove_var("KONGSWAP_BACKEND");
+        let adapter = KongSwapAdapter;
+        let res = adapter.fetch_positions(Principal::anonymous()).await;
+        assert!(matches!(res, Err(FetchError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn lp_balances_become_holdings() {
+        let lp = |a0: f64, a1: f64| LpReply {
+            symbol: "ICP_ckUSDT".into(),
+            symbol_0: "ICP".into(),
+            amount_0: a0,
+            symbol_1: "ckUSDT".into(),
+            amount_1: a1,
+        };
+        let out = lp_holdings(vec![lp(1.5, 12.25), lp(0.0, f64::NAN)]);
+        assert_eq!(out.len(), 2);
+        assert_eq!(
+            (out[0].token.as_str(), out[0].amount.as_str()),
+            ("ICP", "1.5")
+        );
+        assert_eq!(out[1].amount, "12.25");
+        assert!(out.iter().all(|h| h.source == "KongSwap"));
+        assert!(known_user::<u8>(Err("User not found".into()))
+            .unwrap()
+            .is_empty());
+        assert!(known_user::<u8>(Err("paused".into())).is_err());
+    }
+
+    #[cfg(feature = "claim")]
+    #[test]
+    fn failed_claims_keep_paid_claims() {
+        let reply = |status: &str, amount: u64| ClaimReply {
+            status: status.into(),
+            amount: Nat::from(amount),
+        };
+        let replies = vec![
+            (1, Ok(reply("Success", 5))),
+            (2, Err("canister trapped".to_string())),
+            (3, Ok(reply("Pending", 9))),
+            (4, Ok(reply("Success", 7))),
+        ];
+        assert_eq!(settle_claims(replies), 12);
+        assert_eq!(settle_claims(vec![(2, Err("rejected".to_string()))]), 0);
+    }
+
+    #[quickcheck]
+    fn fuzz_decode_balances(data: Vec<u8>) -> bool {
+        let _ = Decode!(&data, Result<Vec<UserBalancesReply>, String>);
+        true
+    }
+}
@@ -1,7 +1,5 @@
-use super::DexAdapter;
+use super::{DexAdapter, DiscoveredPool, PoolReserves};
 use crate::error::FetchError;
-#[cfg(all(feature = "claim", not(target_arch = "wasm32")))]
-use crate::utils::now;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::{
     lp_cache,
@@ -33,6 +31,19 @@ struct PositionInfo {
     auto_compound: bool,
 }
 
+/// Entry returned by the swap canister's `getAllPairs`
+#[derive(CandidType, Deserialize, Clone)]
+struct PairInfo {
+    id: String,
+    token0: String,
+    token1: String,
+    reserve0: Nat,
+    reserve1: Nat,
+}
+
+/// Sonic charges a flat 0.3% on every swap.
+const SONIC_FEE_PPM: u32 = 3000;
+
 pub struct SonicAdapter;
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -60,7 +71,7 @@ async fn fetch_positions_impl(principal: Principal) -> Result<Vec<Holding>, Fetc
     let height = crate::utils::dex_block_height(&agent, router_id)
         .await
         .unwrap_or(0);
-    let holdings = lp_cache::get_or_fetch(principal, "sonic", height, || async {
+    let holdings = lp_cache::get_or_fetch(principal, "Sonic", "sonic", height, || async {
         let mut temp = Vec::with_capacity(positions.len() * 3);
         for pos in positions {
             let a0 = format_amount(pos.token_a_amount, pos.token_a.decimals);
@@ -100,12 +111,15 @@ async fn fetch_positions_impl(_principal: Principal) -> Result<Vec<Holding>, Fet
 
 #[cfg(all(feature = "claim", not(target_arch = "wasm32")))]
 async fn claim_impl(principal: Principal) -> Result<u64, String> {
-    use crate::{cache, ledger_fetcher::LEDGERS};
+    use crate::cache;
     let router_id = match crate::utils::env_principal("SONIC_ROUTER") {
         Some(p) => p,
         None => return Err("router".into()),
     };
-    let ledger = LEDGERS.first().cloned().ok_or("ledger")?;
+    let ledger = crate::config::ledger_ids()
+        .first()
+        .cloned()
+        .ok_or("ledger")?;
     let agent = get_agent().await;
     let arg = Encode!(&principal, &ledger).map_err(|e| e.to_string())?;
     let bytes = agent
@@ -118,16 +132,70 @@ async fn claim_impl(principal: Principal) -> Result<u64, String> {
     let holdings = fetch_positions_impl(principal)
         .await
         .map_err(|e| format!("{:?}", e))?;
-    cache::get().insert(principal, (holdings, now()));
+    cache::store(principal, "Sonic", holdings);
     Ok(spent)
 }
 
+fn discovered(pair: PairInfo) -> DiscoveredPool {
+    DiscoveredPool {
+        id: pair.id,
+        source: "Sonic".into(),
+        token_a: pair.token0,
+        token_b: pair.token1,
+        decimals_a: None,
+        decimals_b: None,
+    }
+}
+
+fn reserves(pair: PairInfo) -> PoolReserves {
+    PoolReserves {
+        id: pair.id,
+        reserve_a: pair.reserve0,
+        reserve_b: pair.reserve1,
+        volume_a: None,
+        volume_b: None,
+        fee_ppm: Some(SONIC_FEE_PPM),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn fetch_pairs() -> Result<Vec<PairInfo>, FetchError> {
+    let router_id = crate::utils::env_principal("SONIC_ROUTER")
+        .ok_or_else(|| FetchError::InvalidConfig("router".into()))?;
+    let agent = get_agent().await;
+    let arg = Encode!().map_err(|_| FetchError::InvalidResponse)?;
+    let bytes = agent
+        .query(&router_id, "getAllPairs")
+        .with_arg(arg)
+        .call()
+        .await?;
+    Decode!(&bytes, Vec<PairInfo>).map_err(|_| FetchError::InvalidResponse)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_pairs() -> Result<Vec<PairInfo>, FetchError> {
+    let router_id = crate::utils::env_principal("SONIC_ROUTER")
+        .ok_or_else(|| FetchError::InvalidConfig("router".into()))?;
+    let (pairs,): (Vec<PairInfo>,) = ic_cdk::api::call::call(router_id, "getAllPairs", ())
+        .await
+        .map_err(|(_, e)| FetchError::Network(e))?;
+    Ok(pairs)
+}
+
 #[async_trait]
 impl DexAdapter for SonicAdapter {
     async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
         fetch_positions_impl(principal).await
     }
 
+    async fn list_pools(&self) -> Result<Vec<DiscoveredPool>, FetchError> {
+        Ok(fetch_pairs().await?.into_iter().map(discovered).collect())
+    }
+
+    async fn pool_reserves(&self) -> Result<Vec<PoolReserves>, FetchError> {
+        Ok(fetch_pairs().await?.into_iter().map(reserves).collect())
+    }
+
     #[cfg(feature = "claim")]
     async fn claim_rewards(&self, principal: Principal) -> Result<u64, String> {
         claim_impl(principal).await
@@ -153,4 +221,10 @@ mod tests {
         let _ = Decode!(&data, Vec<PositionInfo>);
         true
     }
+
+    #[quickcheck]
+    fn fuzz_decode_pairs(data: Vec<u8>) -> bool {
+        let _ = Decode!(&data, Vec<PairInfo>);
+        true
+    }
 }
@@ -0,0 +1,262 @@
+use super::{call, DexAdapter};
+use crate::error::FetchError;
+use crate::utils::{format_amount, now};
+use async_trait::async_trait;
+use bx_core::Holding;
+use candid::{CandidType, Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+// Liquid staking protocols mint a derivative token against staked ICP. The
+// derivative's ledger balance alone says nothing about its ICP value or
+// about unstake requests still waiting for their neurons to dissolve, so
+// each protocol canister is asked for its exchange rate and the user's
+// withdrawal requests. Keep derivative ledgers out of `[ledgers]`, or the
+// balance is reported twice.
+//
+// The protocol canister is expected to expose
+//   get_info : () -> (ProtocolInfo) query
+//   get_withdrawal_requests : (principal) -> (vec WithdrawalRequest) query
+// as in `candid/mock_liquid_staking.did`. Protocols with a different
+// interface, such as WaterNeuron, need their own adapter.
+
+/// Config key and display name of each supported protocol
+const PROTOCOLS: [(&str, &str); 1] = [("LIQUID_STAKING_PROTOCOL", "LiquidStaking")];
+
+/// Scale of `ProtocolInfo::exchange_rate`
+const RATE_DECIMALS: u8 = 8;
+
+#[derive(CandidType, Deserialize, Clone)]
+struct TokenInfo {
+    symbol: String,
+    decimals: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct ProtocolInfo {
+    derivative: TokenInfo,
+    derivative_ledger: Principal,
+    underlying: TokenInfo,
+    /// Underlying tokens per whole derivative token, scaled by 1e8
+    exchange_rate: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct WithdrawalRequest {
+    id: u64,
+    /// Underlying tokens due, in raw units
+    amount: Nat,
+    /// When the request can be withdrawn, in ns since the epoch
+    maturity: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+/// An unstake request waiting for, or past, its maturity time
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize)]
+pub struct Unstake {
+    pub id: u64,
+    /// Underlying tokens due
+    pub amount: String,
+    pub matures_at: u64,
+    pub withdrawable: bool,
+}
+
+/// A principal's position in one liquid staking protocol
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize)]
+pub struct LiquidStake {
+    pub source: String,
+    pub token: String,
+    pub balance: String,
+    pub underlying_token: String,
+    /// Underlying tokens per whole derivative token
+    pub exchange_rate: String,
+    /// `balance` converted at `exchange_rate`
+    pub underlying_value: String,
+    pub unstakes: Vec<Unstake>,
+}
+
+impl LiquidStake {
+    /// The derivative balance as `liquid_staked`, then each unstake request
+    /// as `unstaking` or, once mature, `withdrawable`.
+    pub fn holdings(&self) -> Vec<Holding> {
+        let mut out = Vec::with_capacity(self.unstakes.len() + 1);
+        out.push(Holding {
+            source: self.source.clone(),
+            token: self.token.clone(),
+            amount: self.balance.clone(),
+            status: "liquid_staked".into(),
+        });
+        for u in &self.unstakes {
+            out.push(Holding {
+                source: self.source.clone(),
+                token: self.underlying_token.clone(),
+                amount: u.amount.clone(),
+                status: if u.withdrawable {
+                    "withdrawable"
+                } else {
+                    "unstaking"
+                }
+                .into(),
+            });
+        }
+        out
+    }
+}
+
+pub struct LiquidStakingAdapter;
+
+fn pow10(exp: u8) -> Nat {
+    Nat(num_bigint::BigUint::from(10u32).pow(exp as u32))
+}
+
+fn position(
+    name: &str,
+    info: ProtocolInfo,
+    balance: Nat,
+    requests: Vec<WithdrawalRequest>,
+    now: u64,
+) -> LiquidStake {
+    let value = balance.clone() * info.exchange_rate.clone() * pow10(info.underlying.decimals)
+        / (pow10(RATE_DECIMALS) * pow10(info.derivative.decimals));
+    LiquidStake {
+        source: name.into(),
+        token: info.derivative.symbol,
+        balance: format_amount(balance, info.derivative.decimals),
+        underlying_token: info.underlying.symbol,
+        exchange_rate: format_amount(info.exchange_rate, RATE_DECIMALS),
+        underlying_value: format_amount(value, info.underlying.decimals),
+        unstakes: requests
+            .into_iter()
+            .map(|r| Unstake {
+                id: r.id,
+                amount: format_amount(r.amount, info.underlying.decimals),
+                matures_at: r.maturity,
+                withdrawable: r.maturity <= now,
+            })
+            .collect(),
+    }
+}
+
+async fn fetch_protocol(
+    name: &str,
+    protocol: Principal,
+    principal: Principal,
+) -> Result<Option<LiquidStake>, FetchError> {
+    let info: ProtocolInfo = call(protocol, "get_info", (), false).await?;
+    let account = Account {
+        owner: principal,
+        subaccount: None,
+    };
+    let (balance, requests) = futures::try_join!(
+        call::<_, Nat>(
+            info.derivative_ledger,
+            "icrc1_balance_of",
+            (account,),
+            false
+        ),
+        call::<_, Vec<WithdrawalRequest>>(protocol, "get_withdrawal_requests", (principal,), false)
+    )?;
+    if balance == 0u64 && requests.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(position(name, info, balance, requests, now())))
+}
+
+/// Positions in every configured protocol. Fails only when none is
+/// configured; unreachable protocols are skipped with a warning.
+async fn positions(principal: Principal) -> Result<Vec<LiquidStake>, FetchError> {
+    let configured: Vec<_> = PROTOCOLS
+        .iter()
+        .filter_map(|(key, name)| Some((*name, crate::utils::env_principal(key)?)))
+        .collect();
+    if configured.is_empty() {
+        return Err(FetchError::InvalidConfig("liquid staking".into()));
+    }
+    let results = futures::future::join_all(
+        configured
+            .iter()
+            .map(|(name, id)| fetch_protocol(name, *id, principal)),
+    )
+    .await;
+    let mut out = Vec::new();
+    for ((name, _), res) in configured.iter().zip(results) {
+        match res {
+            Ok(pos) => out.extend(pos),
+            Err(e) => tracing::warn!(protocol = name, "liquid staking fetch failed: {e}"),
+        }
+    }
+    Ok(out)
+}
+
+#[async_trait]
+impl DexAdapter for LiquidStakingAdapter {
+    async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
+        Ok(positions(principal)
+            .await?
+            .iter()
+            .flat_map(LiquidStake::holdings)
+            .collect())
+    }
+
+    async fn liquid_stakes(&self, principal: Principal) -> Result<Vec<LiquidStake>, FetchError> {
+        positions(principal).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Decode;
+    use quickcheck_macros::quickcheck;
+
+    #[tokio::test]
+    async fn empty_without_env() {
+        let res = LiquidStakingAdapter
+            .fetch_positions(Principal::anonymous())
+            .await;
+        assert!(matches!(res, Err(FetchError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn converts_balance_and_splits_unstakes() {
+        let token = |symbol: &str| TokenInfo {
+            symbol: symbol.into(),
+            decimals: 8,
+        };
+        let info = ProtocolInfo {
+            derivative: token("stICP"),
+            derivative_ledger: Principal::anonymous(),
+            underlying: token("ICP"),
+            // 1 stICP = 1.05 ICP
+            exchange_rate: Nat::from(105_000_000u64),
+        };
+        let request = |id, maturity| WithdrawalRequest {
+            id,
+            amount: Nat::from(300_000_000u64),
+            maturity,
+        };
+        let pos = position(
+            "LiquidStaking",
+            info,
+            Nat::from(200_000_000u64),
+            vec![request(1, 50), request(2, 500)],
+            100,
+        );
+        assert_eq!(pos.exchange_rate, "1.05000000");
+        assert_eq!(pos.underlying_value, "2.10000000");
+        assert!(pos.unstakes[0].withdrawable);
+        assert!(!pos.unstakes[1].withdrawable);
+        let statuses: Vec<_> = pos.holdings().into_iter().map(|h| h.status).collect();
+        assert_eq!(statuses, ["liquid_staked", "withdrawable", "unstaking"]);
+    }
+
+    #[quickcheck]
+    fn fuzz_decode_requests(data: Vec<u8>) -> bool {
+        let _ = Decode!(&data, Vec<WithdrawalRequest>);
+        true
+    }
+}
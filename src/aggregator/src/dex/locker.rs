@@ -0,0 +1,307 @@
+use super::dex_infinity::{balance_of, fetch_meta, VaultPosition};
+use super::{call, DexAdapter};
+use crate::error::FetchError;
+use crate::utils::format_amount;
+use async_trait::async_trait;
+use bx_core::Holding;
+use candid::{CandidType, Principal};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+// Many lockers and vaults keep each user's tokens in a subaccount of their
+// own canister, the way InfinitySwap does. Such protocols are described in
+// the `[lockers]` section of `ledgers.toml` (or added by a controller) and
+// read here with the InfinitySwap balance and metadata helpers:
+//
+//   [lockers.Example]
+//   canister = "aaaaa-aa"
+//   subaccount = "principal"   # "positions", "principal" or "sha256"
+//   ledgers = ["ryjl3-tyaaa-aaaaa-aaaba-cai"]
+//   status = "locked"
+//
+// "positions" asks `method` on the canister for the user's
+// `vec record { ledger; subaccount }`; the other rules derive the
+// subaccount from the principal and check every listed ledger.
+
+/// Status of locker holdings when the config names none
+const DEFAULT_STATUS: &str = "locked";
+/// Method asked for positions when the config names none
+const DEFAULT_METHOD: &str = "get_user_positions";
+
+/// How a locker derives a user's subaccount on its own canister
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum SubaccountRule {
+    /// The locker reports ledger and subaccount pairs itself
+    Positions,
+    /// Principal bytes prefixed by their length, zero-padded to 32 bytes
+    PrincipalPadded,
+    /// SHA-256 of the principal bytes
+    PrincipalHash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct LockerConfig {
+    pub name: String,
+    pub canister: Principal,
+    pub rule: SubaccountRule,
+    /// Queried with the user's principal under `Positions`
+    pub method: Option<String>,
+    /// Ledgers checked under the derived rules
+    pub ledgers: Vec<Principal>,
+    pub status: String,
+}
+
+impl LockerConfig {
+    fn check(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("empty name".into());
+        }
+        if self.status.trim().is_empty() {
+            return Err(format!("{}: empty status", self.name));
+        }
+        if self.rule != SubaccountRule::Positions && self.ledgers.is_empty() {
+            return Err(format!("{}: no ledgers to check", self.name));
+        }
+        Ok(())
+    }
+}
+
+fn parse_rule(rule: &str) -> Option<SubaccountRule> {
+    match rule {
+        "positions" => Some(SubaccountRule::Positions),
+        "principal" => Some(SubaccountRule::PrincipalPadded),
+        "sha256" => Some(SubaccountRule::PrincipalHash),
+        _ => None,
+    }
+}
+
+fn parse_locker(name: &str, value: &toml::Value) -> Result<LockerConfig, String> {
+    let table = value.as_table().ok_or("expected a table")?;
+    let text = |key: &str| table.get(key).and_then(|v| v.as_str());
+    let principal =
+        |s: &str| Principal::from_text(s).map_err(|e| format!("invalid principal: {e}"));
+    let canister = principal(text("canister").ok_or("missing canister")?)?;
+    let rule = text("subaccount").unwrap_or("positions");
+    let rule = parse_rule(rule).ok_or_else(|| format!("unknown subaccount rule {rule}"))?;
+    let ledgers = match table.get("ledgers") {
+        None => Vec::new(),
+        Some(v) => v
+            .as_array()
+            .ok_or("ledgers must be a list")?
+            .iter()
+            .map(|l| principal(l.as_str().ok_or("ledgers must be principals")?))
+            .collect::<Result<_, _>>()?,
+    };
+    let cfg = LockerConfig {
+        name: name.to_string(),
+        canister,
+        rule,
+        method: text("method").map(str::to_string),
+        ledgers,
+        status: text("status").unwrap_or(DEFAULT_STATUS).to_string(),
+    };
+    cfg.check()?;
+    Ok(cfg)
+}
+
+/// Lockers described in a `ledgers.toml` document, with a message for each
+/// entry that had to be skipped.
+pub fn parse_lockers(text: &str) -> (Vec<LockerConfig>, Vec<String>) {
+    let Ok(value) = toml::from_str::<toml::Value>(text) else {
+        return (Vec::new(), Vec::new());
+    };
+    let Some(section) = value.get("lockers") else {
+        return (Vec::new(), Vec::new());
+    };
+    let Some(table) = section.as_table() else {
+        return (Vec::new(), vec!["[lockers] must be a table".into()]);
+    };
+    let mut lockers = Vec::new();
+    let mut problems = Vec::new();
+    for (name, v) in table {
+        match parse_locker(name, v) {
+            Ok(cfg) => lockers.push(cfg),
+            Err(e) => problems.push(format!("[lockers] {name}: {e}")),
+        }
+    }
+    (lockers, problems)
+}
+
+static LOCKERS: Lazy<RwLock<BTreeMap<String, LockerConfig>>> = Lazy::new(|| {
+    let text = crate::config::seed_text().unwrap_or_default();
+    let (lockers, _) = parse_lockers(&text);
+    RwLock::new(lockers.into_iter().map(|l| (l.name.clone(), l)).collect())
+});
+
+pub fn lockers() -> Vec<LockerConfig> {
+    LOCKERS.read().unwrap().values().cloned().collect()
+}
+
+/// Insert or replace a locker.
+pub fn add_locker(cfg: LockerConfig) -> Result<(), String> {
+    cfg.check()?;
+    tracing::info!(name = %cfg.name, canister = %cfg.canister, "locker added");
+    LOCKERS.write().unwrap().insert(cfg.name.clone(), cfg);
+    Ok(())
+}
+
+pub fn remove_locker(name: &str) -> Result<(), String> {
+    LOCKERS
+        .write()
+        .unwrap()
+        .remove(name)
+        .map(|_| tracing::info!(%name, "locker removed"))
+        .ok_or_else(|| format!("unknown locker {name}"))
+}
+
+pub fn stable_save() -> Vec<LockerConfig> {
+    lockers()
+}
+
+pub fn stable_restore(saved: Vec<LockerConfig>) {
+    *LOCKERS.write().unwrap() = saved.into_iter().map(|l| (l.name.clone(), l)).collect();
+}
+
+fn derive_subaccount(rule: SubaccountRule, principal: Principal) -> Vec<u8> {
+    let bytes = principal.as_slice();
+    match rule {
+        SubaccountRule::PrincipalHash => Sha256::digest(bytes).to_vec(),
+        _ => {
+            let mut sub = vec![0u8; 32];
+            sub[0] = bytes.len() as u8;
+            sub[1..=bytes.len()].copy_from_slice(bytes);
+            sub
+        }
+    }
+}
+
+async fn fetch_locker(
+    locker: &LockerConfig,
+    principal: Principal,
+) -> Result<Vec<Holding>, FetchError> {
+    let positions: Vec<VaultPosition> = match locker.rule {
+        SubaccountRule::Positions => {
+            let method = locker.method.as_deref().unwrap_or(DEFAULT_METHOD);
+            call(locker.canister, method, (principal,), false).await?
+        }
+        rule => {
+            let subaccount = derive_subaccount(rule, principal);
+            locker
+                .ledgers
+                .iter()
+                .map(|ledger| VaultPosition {
+                    ledger: *ledger,
+                    subaccount: subaccount.clone(),
+                })
+                .collect()
+        }
+    };
+    let mut out = Vec::with_capacity(positions.len());
+    for pos in positions {
+        let Some((symbol, decimals)) = fetch_meta(pos.ledger).await else {
+            continue;
+        };
+        let Some(bal) = balance_of(pos.ledger, locker.canister, pos.subaccount).await else {
+            continue;
+        };
+        if bal == 0u64 {
+            continue;
+        }
+        out.push(Holding {
+            source: locker.name.clone(),
+            token: symbol,
+            amount: format_amount(bal, decimals),
+            status: locker.status.clone(),
+        });
+    }
+    Ok(out)
+}
+
+pub struct LockerAdapter;
+
+#[async_trait]
+impl DexAdapter for LockerAdapter {
+    // unreachable lockers are skipped so one broken locker does not hide the rest
+    async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError> {
+        let lockers = lockers();
+        if lockers.is_empty() {
+            return Err(FetchError::InvalidConfig("lockers".into()));
+        }
+        let results =
+            futures::future::join_all(lockers.iter().map(|l| fetch_locker(l, principal))).await;
+        let mut out = Vec::new();
+        for (locker, res) in lockers.iter().zip(results) {
+            match res {
+                Ok(holdings) => out.extend(holdings),
+                Err(e) => tracing::warn!(locker = %locker.name, "locker fetch failed: {e}"),
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lockers_and_reports_bad_entries() {
+        let (lockers, problems) = parse_lockers(
+            r#"
+[lockers.Vault]
+canister = "aaaaa-aa"
+method = "positions_of"
+
+[lockers.Gold]
+canister = "aaaaa-aa"
+subaccount = "sha256"
+ledgers = ["ryjl3-tyaaa-aaaaa-aaaba-cai"]
+status = "staked"
+
+[lockers.NoLedgers]
+canister = "aaaaa-aa"
+subaccount = "principal"
+
+[lockers.Odd]
+canister = "aaaaa-aa"
+subaccount = "xor"
+"#,
+        );
+        assert_eq!(lockers.len(), 2);
+        let gold = lockers.iter().find(|l| l.name == "Gold").unwrap();
+        assert_eq!(gold.rule, SubaccountRule::PrincipalHash);
+        assert_eq!(gold.status, "staked");
+        let vault = lockers.iter().find(|l| l.name == "Vault").unwrap();
+        assert_eq!(vault.rule, SubaccountRule::Positions);
+        assert_eq!(vault.method.as_deref(), Some("positions_of"));
+        assert_eq!(vault.status, DEFAULT_STATUS);
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems.iter().any(|p| p.contains("NoLedgers: ")));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("unknown subaccount rule xor")));
+    }
+
+    #[test]
+    fn derives_subaccounts_from_the_principal() {
+        let p = Principal::from_slice(&[1, 2, 3]);
+        let padded = derive_subaccount(SubaccountRule::PrincipalPadded, p);
+        assert_eq!(padded.len(), 32);
+        assert_eq!(&padded[..4], &[3, 1, 2, 3]);
+        assert!(padded[4..].iter().all(|b| *b == 0));
+        let hashed = derive_subaccount(SubaccountRule::PrincipalHash, p);
+        assert_eq!(hashed, Sha256::digest([1, 2, 3]).to_vec());
+    }
+
+    #[tokio::test]
+    async fn empty_without_lockers() {
+        let saved = stable_save();
+        stable_restore(Vec::new());
+        let res = LockerAdapter.fetch_positions(Principal::anonymous()).await;
+        stable_restore(saved);
+        assert!(matches!(res, Err(FetchError::InvalidConfig(_))));
+    }
+}
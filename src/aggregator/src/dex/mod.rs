@@ -1,7 +1,10 @@
 use crate::error::FetchError;
 use async_trait::async_trait;
 use bx_core::Holding;
-use candid::Principal;
+use candid::utils::ArgumentEncoder;
+use candid::{CandidType, Nat, Principal};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RewardInfo {
@@ -9,24 +12,157 @@ pub struct RewardInfo {
     pub amount: String,
 }
 
+/// A pool advertised by a DEX factory, before token metadata is resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredPool {
+    pub id: String,
+    pub source: String,
+    /// Token ledger ids as reported by the DEX
+    pub token_a: String,
+    pub token_b: String,
+    /// Decimals reported by the pool itself, used when the ledger cannot be queried
+    pub decimals_a: Option<u8>,
+    pub decimals_b: Option<u8>,
+}
+
+/// Liquidity and trading activity of one pool, in raw token units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolReserves {
+    /// Same id as the matching [`DiscoveredPool`]
+    pub id: String,
+    pub reserve_a: Nat,
+    pub reserve_b: Nat,
+    /// Amounts swapped into the pool over the last 24h, if the DEX reports them
+    pub volume_a: Option<Nat>,
+    pub volume_b: Option<Nat>,
+    /// Swap fee in millionths, e.g. 3000 for 0.3%
+    pub fee_ppm: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+/// A resting order on an order-book DEX. The funds it locks are also
+/// reported as a holding with status `open_order`.
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize)]
+pub struct OpenOrder {
+    pub source: String,
+    /// Base and quote symbols, e.g. "ICP/ckUSDT"
+    pub pair: String,
+    pub side: OrderSide,
+    /// Quote tokens per whole base token
+    pub price: String,
+    /// Base tokens still to be filled
+    pub remaining: String,
+    pub locked_token: String,
+    pub locked_amount: String,
+}
+
+impl OpenOrder {
+    pub fn holding(&self) -> Holding {
+        Holding {
+            source: self.source.clone(),
+            token: self.locked_token.clone(),
+            amount: self.locked_amount.clone(),
+            status: "open_order".into(),
+        }
+    }
+}
+
 #[async_trait]
 pub trait DexAdapter: Send + Sync {
     async fn fetch_positions(&self, principal: Principal) -> Result<Vec<Holding>, FetchError>;
+    async fn list_pools(&self) -> Result<Vec<DiscoveredPool>, FetchError> {
+        Ok(Vec::new())
+    }
+    async fn pool_reserves(&self) -> Result<Vec<PoolReserves>, FetchError> {
+        Ok(Vec::new())
+    }
     async fn claimable_rewards(
         &self,
         _principal: Principal,
     ) -> Result<Vec<RewardInfo>, FetchError> {
         Ok(Vec::new())
     }
+    /// Open orders on order-book DEXes; LP-only DEXes have none.
+    async fn open_orders(&self, _principal: Principal) -> Result<Vec<OpenOrder>, FetchError> {
+        Ok(Vec::new())
+    }
+    /// Liquid staking positions with exchange rates and unstake requests.
+    async fn liquid_stakes(
+        &self,
+        _principal: Principal,
+    ) -> Result<Vec<liquid_staking::LiquidStake>, FetchError> {
+        Ok(Vec::new())
+    }
+    /// Chain-key deposits and withdrawals the minter has not finished.
+    async fn chain_key_pending(
+        &self,
+        _principal: Principal,
+    ) -> Result<Vec<chain_key::ChainKeyPending>, FetchError> {
+        Ok(Vec::new())
+    }
     #[cfg(feature = "claim")]
     async fn claim_rewards(&self, _principal: Principal) -> Result<u64, String> {
         Ok(0)
     }
 }
 
+/// Call `method` on a DEX canister and decode its single return value.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn call<A, R>(
+    canister: Principal,
+    method: &str,
+    args: A,
+    update: bool,
+) -> Result<R, FetchError>
+where
+    A: ArgumentEncoder + Send,
+    R: CandidType + DeserializeOwned,
+{
+    let agent = crate::utils::get_agent().await;
+    let arg = candid::encode_args(args).map_err(|_| FetchError::InvalidResponse)?;
+    let bytes = if update {
+        agent
+            .update(&canister, method)
+            .with_arg(arg)
+            .call_and_wait()
+            .await?
+    } else {
+        agent.query(&canister, method).with_arg(arg).call().await?
+    };
+    candid::decode_one(&bytes).map_err(|_| FetchError::InvalidResponse)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn call<A, R>(
+    canister: Principal,
+    method: &str,
+    args: A,
+    _update: bool,
+) -> Result<R, FetchError>
+where
+    A: ArgumentEncoder + Send,
+    R: CandidType + DeserializeOwned,
+{
+    let (res,): (R,) = ic_cdk::api::call::call(canister, method, args)
+        .await
+        .map_err(|(_, e)| FetchError::Network(e))?;
+    Ok(res)
+}
+
+pub mod chain_key;
+pub mod dex_icdex;
 pub mod dex_icpswap;
+pub mod dex_icpswap_farm;
 pub mod dex_infinity;
+pub mod dex_kongswap;
 pub mod dex_sonic;
+pub mod liquid_staking;
+pub mod locker;
 pub mod sns_adapter;
 
 /// Clear cached metadata for all adapters
@@ -35,7 +171,8 @@ pub fn clear_all_caches() {
     {
         dex_icpswap::clear_cache();
         dex_sonic::clear_cache();
-        dex_infinity::clear_cache();
     }
+    dex_infinity::clear_cache();
     sns_adapter::clear_cache();
+    crate::position_index::clear();
 }
@@ -1,12 +1,17 @@
+use crate::dex::chain_key::{ChainKeyPending, CkBtcAdapter, CkEthAdapter};
+use crate::dex::dex_icdex::IcdexAdapter;
 use crate::dex::dex_icpswap::IcpswapAdapter;
+use crate::dex::dex_icpswap_farm::IcpswapFarmAdapter;
 use crate::dex::dex_infinity::InfinityAdapter;
+use crate::dex::dex_kongswap::KongSwapAdapter;
 use crate::dex::dex_sonic::SonicAdapter;
+use crate::dex::liquid_staking::{LiquidStake, LiquidStakingAdapter};
+use crate::dex::locker::LockerAdapter;
 use crate::dex::sns_adapter::SnsAdapter;
-use crate::dex::DexAdapter;
+use crate::dex::{DexAdapter, OpenOrder};
 use crate::error::FetchError;
 use bx_core::Holding;
 use candid::Principal;
-use futures::future::join_all;
 #[cfg(not(target_arch = "wasm32"))]
 use once_cell::sync::Lazy;
 #[cfg(not(target_arch = "wasm32"))]
@@ -47,30 +52,88 @@ where
     fut.await
 }
 
-pub async fn fetch(principal: Principal) -> Result<Vec<Holding>, FetchError> {
-    // allow other tasks to start before launching adapter queries
+/// Holding sources reported by the DEX adapters, one cache entry each.
+pub const SOURCES: [&str; 11] = [
+    "ICPSwap",
+    "ICPSwapFarm",
+    "Sonic",
+    "InfinitySwap",
+    "KongSwap",
+    "ICDex",
+    "LiquidStaking",
+    "ckBTC",
+    "ckETH",
+    "Lockers",
+    "SNS",
+];
+
+fn adapter(source: &str) -> Option<Box<dyn DexAdapter>> {
+    Some(match source {
+        "ICPSwap" => Box::new(IcpswapAdapter),
+        "ICPSwapFarm" => Box::new(IcpswapFarmAdapter),
+        "Sonic" => Box::new(SonicAdapter),
+        "InfinitySwap" => Box::new(InfinityAdapter),
+        "KongSwap" => Box::new(KongSwapAdapter),
+        "ICDex" => Box::new(IcdexAdapter),
+        "LiquidStaking" => Box::new(LiquidStakingAdapter),
+        "ckBTC" => Box::new(CkBtcAdapter),
+        "ckETH" => Box::new(CkEthAdapter),
+        "Lockers" => Box::new(LockerAdapter),
+        "SNS" => Box::new(SnsAdapter),
+        _ => return None,
+    })
+}
+
+pub async fn fetch_source(source: &str, principal: Principal) -> Result<Vec<Holding>, FetchError> {
     pause().await;
-    let adapters: Vec<Box<dyn DexAdapter>> = vec![
-        Box::new(IcpswapAdapter),
-        Box::new(SonicAdapter),
-        Box::new(InfinityAdapter),
-        Box::new(SnsAdapter),
-    ];
-    let tasks = adapters
-        .into_iter()
-        .map(|a| async move { with_timeout(a.fetch_positions(principal)).await });
-    let results = join_all(tasks).await;
-    let capacity: usize = results
-        .iter()
-        .filter_map(|r| r.as_ref().ok())
-        .map(|v| v.len())
-        .sum();
-    let mut out = Vec::with_capacity(capacity);
-    for r in results {
-        match r {
-            Ok(v) => out.extend(v),
-            Err(e) => return Err(e),
+    match adapter(source) {
+        Some(a) => with_timeout(a.fetch_positions(principal)).await,
+        None => Err(FetchError::InvalidConfig(source.into())),
+    }
+}
+
+/// Adapters for every source in `SOURCES`.
+fn adapters() -> impl Iterator<Item = (&'static str, Box<dyn DexAdapter>)> {
+    SOURCES.iter().filter_map(|s| Some((*s, adapter(s)?)))
+}
+
+/// Open orders across order-book DEXes. Unconfigured or unreachable DEXes
+/// contribute nothing.
+pub async fn open_orders(principal: Principal) -> Vec<OpenOrder> {
+    let mut out = Vec::new();
+    for (source, a) in adapters() {
+        match a.open_orders(principal).await {
+            Ok(orders) => out.extend(orders),
+            Err(FetchError::InvalidConfig(_)) => {}
+            Err(e) => tracing::warn!(%principal, %source, "open orders fetch failed: {e}"),
+        }
+    }
+    out
+}
+
+/// Liquid staking positions across configured protocols; empty when none is
+/// configured.
+pub async fn liquid_stakes(principal: Principal) -> Vec<LiquidStake> {
+    let mut out = Vec::new();
+    for (source, a) in adapters() {
+        match a.liquid_stakes(principal).await {
+            Ok(stakes) => out.extend(stakes),
+            Err(FetchError::InvalidConfig(_)) => {}
+            Err(e) => tracing::warn!(%principal, %source, "liquid staking fetch failed: {e}"),
+        }
+    }
+    out
+}
+
+/// Unfinished chain-key deposits and withdrawals across configured minters.
+pub async fn chain_key_pending(principal: Principal) -> Vec<ChainKeyPending> {
+    let mut out = Vec::new();
+    for (source, a) in adapters() {
+        match a.chain_key_pending(principal).await {
+            Ok(pending) => out.extend(pending),
+            Err(FetchError::InvalidConfig(_)) => {}
+            Err(e) => tracing::warn!(%principal, %source, "chain-key minter fetch failed: {e}"),
         }
     }
-    Ok(out)
+    out
 }
@@ -1,3 +1,6 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FetchError {
     Network(String),
@@ -23,3 +26,41 @@ impl From<ic_agent::AgentError> for FetchError {
         FetchError::Network(e.to_string())
     }
 }
+
+/// Problems found while loading `ledgers.toml`; invalid entries are skipped.
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum ConfigError {
+    Unreadable {
+        path: String,
+        reason: String,
+    },
+    Parse(String),
+    InvalidPrincipal {
+        section: String,
+        name: String,
+        value: String,
+    },
+    NotAString {
+        section: String,
+        name: String,
+    },
+}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Unreadable { path, reason } => write!(f, "cannot read {path}: {reason}"),
+            Self::Parse(e) => write!(f, "invalid toml: {e}"),
+            Self::InvalidPrincipal {
+                section,
+                name,
+                value,
+            } => write!(f, "[{section}] {name}: invalid principal {value}"),
+            Self::NotAString { section, name } => {
+                write!(f, "[{section}] {name}: expected a string")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
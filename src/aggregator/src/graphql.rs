@@ -0,0 +1,787 @@
+use crate::pool_registry::PoolMeta;
+use crate::pool_stats::PoolStats;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+// A small GraphQL subset for the pool registry: a single anonymous or named
+// query with field selection, aliases and literal arguments. Fragments,
+// directives, variables and mutations are rejected with a spec-style error.
+//
+//   type Query {
+//     pools(token: String, first: Int, after: String): [Pool]
+//     pool(id: String!): Pool
+//   }
+//   type Pool {
+//     id: String  token_a: String  token_b: String  decimals_a: Int
+//     decimals_b: Int  image_a: String  image_b: String  source: String
+//     tokens: [Token]  stats: PoolStats
+//   }
+//   type Token { symbol: String  decimals: Int  image: String }
+//   type PoolStats {
+//     reserve_a: Float  reserve_b: Float  quote: String  tvl: Float
+//     volume_24h: Float  fee_apr: Float  updated_at: Int
+//   }
+//
+// Pools are ordered by id and `after` takes the id of the last pool seen.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug)]
+struct Error {
+    message: String,
+    pos: Pos,
+}
+
+impl Error {
+    fn new(message: impl Into<String>, pos: Pos) -> Self {
+        Error {
+            message: message.into(),
+            pos,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "message": self.message,
+            "locations": [{"line": self.pos.line, "column": self.pos.column}],
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Name(String),
+    Int(i64),
+    Str(String),
+    Punct(char),
+    Spread,
+    Eof,
+}
+
+fn lex(src: &str) -> Result<Vec<(Tok, Pos)>, Error> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut out = Vec::new();
+    let (mut i, mut line, mut col) = (0, 1, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = Pos { line, column: col };
+        match c {
+            '\n' => {
+                i += 1;
+                line += 1;
+                col = 1;
+                continue;
+            }
+            ' ' | '\t' | '\r' | ',' | '\u{feff}' => {}
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '{' | '}' | '(' | ')' | ':' | '$' | '@' | '!' | '[' | ']' | '=' => {
+                out.push((Tok::Punct(c), pos))
+            }
+            '.' => {
+                if chars.get(i + 1) != Some(&'.') || chars.get(i + 2) != Some(&'.') {
+                    return Err(Error::new("Syntax Error: Unexpected \".\".", pos));
+                }
+                out.push((Tok::Spread, pos));
+                i += 3;
+                col += 3;
+                continue;
+            }
+            '"' => {
+                let mut s = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None | Some('\n') => {
+                            return Err(Error::new("Syntax Error: Unterminated string.", pos))
+                        }
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = match chars.get(j + 1) {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(&e @ ('"' | '\\' | '/')) => e,
+                                _ => {
+                                    return Err(Error::new(
+                                        "Syntax Error: Invalid character escape sequence.",
+                                        Pos {
+                                            line,
+                                            column: col + j - i,
+                                        },
+                                    ))
+                                }
+                            };
+                            s.push(escaped);
+                            j += 2;
+                        }
+                        Some(&ch) => {
+                            s.push(ch);
+                            j += 1;
+                        }
+                    }
+                }
+                out.push((Tok::Str(s), pos));
+                col += j + 1 - i;
+                i = j + 1;
+                continue;
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j].is_ascii_digit() {
+                    j += 1;
+                }
+                let text: String = chars[i..j].iter().collect();
+                let n = text.parse::<i64>().map_err(|_| {
+                    Error::new(format!("Syntax Error: Invalid number {text}."), pos)
+                })?;
+                out.push((Tok::Int(n), pos));
+                col += j - i;
+                i = j;
+                continue;
+            }
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let mut j = i + 1;
+                while j < chars.len() && (chars[j] == '_' || chars[j].is_ascii_alphanumeric()) {
+                    j += 1;
+                }
+                out.push((Tok::Name(chars[i..j].iter().collect()), pos));
+                col += j - i;
+                i = j;
+                continue;
+            }
+            other => {
+                return Err(Error::new(
+                    format!("Syntax Error: Unexpected character \"{other}\"."),
+                    pos,
+                ))
+            }
+        }
+        i += 1;
+        col += 1;
+    }
+    out.push((Tok::Eof, Pos { line, column: col }));
+    Ok(out)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug)]
+struct Field {
+    alias: Option<String>,
+    name: String,
+    args: Vec<(String, Arg, Pos)>,
+    selection: Option<Vec<Field>>,
+    pos: Pos,
+}
+
+impl Field {
+    fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+
+    fn arg(&self, name: &str) -> Option<&Arg> {
+        self.args
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .map(|(_, a, _)| a)
+    }
+}
+
+/// Deepest selection set a query may nest; bounds the parser's recursion
+const MAX_DEPTH: usize = 16;
+
+struct Parser {
+    toks: Vec<(Tok, Pos)>,
+    at: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Tok, Pos) {
+        &self.toks[self.at]
+    }
+
+    fn next(&mut self) -> (Tok, Pos) {
+        let t = self.toks[self.at].clone();
+        if self.at + 1 < self.toks.len() {
+            self.at += 1;
+        }
+        t
+    }
+
+    fn unexpected(&self) -> Error {
+        let (tok, pos) = self.peek();
+        let what = match tok {
+            Tok::Name(n) => format!("Name \"{n}\""),
+            Tok::Int(n) => format!("Int \"{n}\""),
+            Tok::Str(s) => format!("String \"{s}\""),
+            Tok::Punct(c) => format!("\"{c}\""),
+            Tok::Spread => "\"...\"".into(),
+            Tok::Eof => "<EOF>".into(),
+        };
+        Error::new(format!("Syntax Error: Unexpected {what}."), *pos)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.peek().0 == Tok::Punct(c) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn name(&mut self) -> Result<(String, Pos), Error> {
+        match self.peek().clone() {
+            (Tok::Name(n), pos) => {
+                self.next();
+                Ok((n, pos))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn document(&mut self) -> Result<Vec<Field>, Error> {
+        if let (Tok::Name(keyword), pos) = self.peek().clone() {
+            match keyword.as_str() {
+                "query" => {
+                    self.next();
+                    if matches!(self.peek().0, Tok::Name(_)) {
+                        self.next();
+                    }
+                    if self.peek().0 == Tok::Punct('(') {
+                        return Err(Error::new("Variables are not supported.", self.peek().1));
+                    }
+                }
+                "mutation" | "subscription" | "fragment" => {
+                    return Err(Error::new(
+                        format!("Operation \"{keyword}\" is not supported."),
+                        pos,
+                    ))
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+        let fields = self.selection_set()?;
+        if self.peek().0 != Tok::Eof {
+            return Err(Error::new(
+                "Only a single operation is supported.",
+                self.peek().1,
+            ));
+        }
+        Ok(fields)
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Field>, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::new(
+                format!("Query is nested deeper than {MAX_DEPTH} levels."),
+                self.peek().1,
+            ));
+        }
+        self.expect('{')?;
+        self.depth += 1;
+        let fields = self.selections();
+        self.depth -= 1;
+        fields
+    }
+
+    fn selections(&mut self) -> Result<Vec<Field>, Error> {
+        let mut fields = Vec::new();
+        loop {
+            match self.peek() {
+                (Tok::Punct('}'), _) => {
+                    self.next();
+                    break;
+                }
+                (Tok::Spread, pos) => {
+                    return Err(Error::new("Fragments are not supported.", *pos));
+                }
+                _ => fields.push(self.field()?),
+            }
+        }
+        if fields.is_empty() {
+            return Err(Error::new(
+                "Syntax Error: Expected Name, found \"}\".",
+                self.toks[self.at - 1].1,
+            ));
+        }
+        Ok(fields)
+    }
+
+    fn field(&mut self) -> Result<Field, Error> {
+        let (first, pos) = self.name()?;
+        let (alias, name) = if self.peek().0 == Tok::Punct(':') {
+            self.next();
+            (Some(first), self.name()?.0)
+        } else {
+            (None, first)
+        };
+        let mut args = Vec::new();
+        if self.peek().0 == Tok::Punct('(') {
+            self.next();
+            while self.peek().0 != Tok::Punct(')') {
+                let (arg, arg_pos) = self.name()?;
+                self.expect(':')?;
+                args.push((arg, self.value()?, arg_pos));
+            }
+            self.next();
+        }
+        if self.peek().0 == Tok::Punct('@') {
+            return Err(Error::new("Directives are not supported.", self.peek().1));
+        }
+        let selection = if self.peek().0 == Tok::Punct('{') {
+            Some(self.selection_set()?)
+        } else {
+            None
+        };
+        Ok(Field {
+            alias,
+            name,
+            args,
+            selection,
+            pos,
+        })
+    }
+
+    fn value(&mut self) -> Result<Arg, Error> {
+        let arg = match self.peek() {
+            (Tok::Int(n), _) => Arg::Int(*n),
+            (Tok::Str(s), _) => Arg::Str(s.clone()),
+            (Tok::Name(n), _) if n == "true" => Arg::Bool(true),
+            (Tok::Name(n), _) if n == "false" => Arg::Bool(false),
+            (Tok::Name(n), _) if n == "null" => Arg::Null,
+            (Tok::Punct('$'), pos) => return Err(Error::new("Variables are not supported.", *pos)),
+            _ => return Err(self.unexpected()),
+        };
+        self.next();
+        Ok(arg)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Ty {
+    Query,
+    Pool,
+    Token,
+    Stats,
+    Str,
+    Int,
+    Float,
+}
+
+impl Ty {
+    fn name(self) -> &'static str {
+        match self {
+            Ty::Query => "Query",
+            Ty::Pool => "Pool",
+            Ty::Token => "Token",
+            Ty::Stats => "PoolStats",
+            Ty::Str => "String",
+            Ty::Int => "Int",
+            Ty::Float => "Float",
+        }
+    }
+
+    fn is_object(self) -> bool {
+        matches!(self, Ty::Query | Ty::Pool | Ty::Token | Ty::Stats)
+    }
+}
+
+/// Accepted arguments as `(name, type, required)`.
+type ArgDefs = &'static [(&'static str, Ty, bool)];
+
+fn field_def(parent: Ty, field: &str) -> Option<(Ty, ArgDefs)> {
+    Some(match (parent, field) {
+        (_, "__typename") => (Ty::Str, &[]),
+        (Ty::Query, "pools") => (
+            Ty::Pool,
+            &[
+                ("token", Ty::Str, false),
+                ("first", Ty::Int, false),
+                ("after", Ty::Str, false),
+            ],
+        ),
+        (Ty::Query, "pool") => (Ty::Pool, &[("id", Ty::Str, true)]),
+        (Ty::Pool, "id" | "token_a" | "token_b" | "image_a" | "image_b" | "source") => {
+            (Ty::Str, &[])
+        }
+        (Ty::Pool, "decimals_a" | "decimals_b") => (Ty::Int, &[]),
+        (Ty::Pool, "tokens") => (Ty::Token, &[]),
+        (Ty::Pool, "stats") => (Ty::Stats, &[]),
+        (Ty::Token, "symbol" | "image") => (Ty::Str, &[]),
+        (Ty::Token, "decimals") => (Ty::Int, &[]),
+        (Ty::Stats, "reserve_a" | "reserve_b" | "tvl" | "volume_24h" | "fee_apr") => {
+            (Ty::Float, &[])
+        }
+        (Ty::Stats, "quote") => (Ty::Str, &[]),
+        (Ty::Stats, "updated_at") => (Ty::Int, &[]),
+        _ => return None,
+    })
+}
+
+fn validate(fields: &[Field], parent: Ty, errors: &mut Vec<Error>) {
+    for f in fields {
+        let Some((ty, args)) = field_def(parent, &f.name) else {
+            errors.push(Error::new(
+                format!(
+                    "Cannot query field \"{}\" on type \"{}\".",
+                    f.name,
+                    parent.name()
+                ),
+                f.pos,
+            ));
+            continue;
+        };
+        for (name, value, pos) in &f.args {
+            match args.iter().find(|(n, _, _)| n == name) {
+                None => errors.push(Error::new(
+                    format!("Unknown argument \"{name}\" on field \"{}\".", f.name),
+                    *pos,
+                )),
+                Some((_, want, required)) => {
+                    let ok = match (value, want) {
+                        (Arg::Null, _) => !required,
+                        (Arg::Str(_), Ty::Str) => true,
+                        (Arg::Int(n), Ty::Int) => *n >= 0,
+                        _ => false,
+                    };
+                    if !ok {
+                        errors.push(Error::new(
+                            format!(
+                                "Argument \"{name}\" has invalid value; expected {}.",
+                                want.name()
+                            ),
+                            *pos,
+                        ));
+                    }
+                }
+            }
+        }
+        for (name, want, required) in args.iter() {
+            if *required && f.arg(name).is_none() {
+                errors.push(Error::new(
+                    format!(
+                        "Field \"{}\" argument \"{name}\" of type \"{}!\" is required.",
+                        f.name,
+                        want.name()
+                    ),
+                    f.pos,
+                ));
+            }
+        }
+        match (&f.selection, ty.is_object()) {
+            (Some(sel), true) => validate(sel, ty, errors),
+            (None, true) => errors.push(Error::new(
+                format!(
+                    "Field \"{}\" of type \"{}\" must have a selection of subfields.",
+                    f.name,
+                    ty.name()
+                ),
+                f.pos,
+            )),
+            (Some(_), false) => errors.push(Error::new(
+                format!(
+                    "Field \"{}\" must not have a selection since type \"{}\" has no subfields.",
+                    f.name,
+                    ty.name()
+                ),
+                f.pos,
+            )),
+            (None, false) => {}
+        }
+    }
+}
+
+fn str_arg<'a>(f: &'a Field, name: &str) -> Option<&'a str> {
+    match f.arg(name) {
+        Some(Arg::Str(s)) => Some(s),
+        _ => None,
+    }
+}
+
+fn resolve_query(
+    fields: &[Field],
+    pools: &[&PoolMeta],
+    stats: &HashMap<String, PoolStats>,
+) -> Value {
+    let mut out = Map::new();
+    for f in fields {
+        let value = match f.name.as_str() {
+            "pools" => {
+                let token = str_arg(f, "token");
+                let after = str_arg(f, "after");
+                let first = match f.arg("first") {
+                    Some(Arg::Int(n)) => *n as usize,
+                    _ => usize::MAX,
+                };
+                let sel = f.selection.as_deref().unwrap_or_default();
+                Value::Array(
+                    pools
+                        .iter()
+                        .filter(|p| {
+                            token.is_none()
+                                || token == Some(p.token_a.as_str())
+                                || token == Some(p.token_b.as_str())
+                        })
+                        .filter(|p| after.is_none() || after < Some(p.id.as_str()))
+                        .take(first)
+                        .map(|p| resolve_pool(sel, p, stats))
+                        .collect(),
+                )
+            }
+            "pool" => {
+                let id = str_arg(f, "id");
+                let sel = f.selection.as_deref().unwrap_or_default();
+                pools
+                    .iter()
+                    .find(|p| Some(p.id.as_str()) == id)
+                    .map_or(Value::Null, |p| resolve_pool(sel, p, stats))
+            }
+            _ => json!("Query"),
+        };
+        out.insert(f.key().to_string(), value);
+    }
+    Value::Object(out)
+}
+
+fn resolve_pool(fields: &[Field], pool: &PoolMeta, stats: &HashMap<String, PoolStats>) -> Value {
+    let mut out = Map::new();
+    for f in fields {
+        let value = match f.name.as_str() {
+            "id" => json!(pool.id),
+            "token_a" => json!(pool.token_a),
+            "token_b" => json!(pool.token_b),
+            "decimals_a" => json!(pool.decimals_a),
+            "decimals_b" => json!(pool.decimals_b),
+            "image_a" => json!(pool.image_a),
+            "image_b" => json!(pool.image_b),
+            "source" => json!(pool.source),
+            "tokens" => {
+                let sel = f.selection.as_deref().unwrap_or_default();
+                json!([
+                    resolve_token(sel, &pool.token_a, pool.decimals_a, &pool.image_a),
+                    resolve_token(sel, &pool.token_b, pool.decimals_b, &pool.image_b),
+                ])
+            }
+            "stats" => {
+                let sel = f.selection.as_deref().unwrap_or_default();
+                stats
+                    .get(&pool.id)
+                    .map_or(Value::Null, |s| resolve_stats(sel, s))
+            }
+            _ => json!("Pool"),
+        };
+        out.insert(f.key().to_string(), value);
+    }
+    Value::Object(out)
+}
+
+fn resolve_token(fields: &[Field], symbol: &str, decimals: u8, image: &Option<String>) -> Value {
+    let mut out = Map::new();
+    for f in fields {
+        let value = match f.name.as_str() {
+            "symbol" => json!(symbol),
+            "decimals" => json!(decimals),
+            "image" => json!(image),
+            _ => json!("Token"),
+        };
+        out.insert(f.key().to_string(), value);
+    }
+    Value::Object(out)
+}
+
+fn resolve_stats(fields: &[Field], stats: &PoolStats) -> Value {
+    let mut out = Map::new();
+    for f in fields {
+        let value = match f.name.as_str() {
+            "reserve_a" => json!(stats.reserve_a),
+            "reserve_b" => json!(stats.reserve_b),
+            "quote" => json!(stats.quote),
+            "tvl" => json!(stats.tvl),
+            "volume_24h" => json!(stats.volume_24h),
+            "fee_apr" => json!(stats.fee_apr),
+            "updated_at" => json!(stats.updated_at),
+            _ => json!("PoolStats"),
+        };
+        out.insert(f.key().to_string(), value);
+    }
+    Value::Object(out)
+}
+
+/// Execute `query` against `pools` and return a JSON response with either
+/// `data` or `errors`, as described in the GraphQL over HTTP spec.
+pub fn execute(query: &str, pools: &[PoolMeta], stats: &HashMap<String, PoolStats>) -> String {
+    let fields = match lex(query).and_then(|toks| {
+        Parser {
+            toks,
+            at: 0,
+            depth: 0,
+        }
+        .document()
+    }) {
+        Ok(f) => f,
+        Err(e) => return json!({ "errors": [e.to_json()] }).to_string(),
+    };
+    let mut errors = Vec::new();
+    validate(&fields, Ty::Query, &mut errors);
+    if !errors.is_empty() {
+        let errors: Vec<Value> = errors.iter().map(Error::to_json).collect();
+        return json!({ "errors": errors }).to_string();
+    }
+    let mut sorted: Vec<&PoolMeta> = pools.iter().collect();
+    sorted.sort_by(|a, b| a.id.cmp(&b.id));
+    json!({ "data": resolve_query(&fields, &sorted, stats) }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(id: &str, a: &str, b: &str) -> PoolMeta {
+        PoolMeta {
+            id: id.into(),
+            token_a: a.into(),
+            token_b: b.into(),
+            decimals_a: 8,
+            decimals_b: 6,
+            image_a: None,
+            image_b: Some(format!("https://example.com/{b}.png")),
+            source: "manual".into(),
+        }
+    }
+
+    fn run(query: &str) -> Value {
+        let pools = vec![
+            pool("p3", "ICP", "ckBTC"),
+            pool("p1", "ICP", "XTC"),
+            pool("p2", "XTC", "ckETH"),
+        ];
+        let stats = HashMap::from([(
+            "p1".to_string(),
+            PoolStats {
+                pool_id: "p1".into(),
+                reserve_a: 10.0,
+                reserve_b: 20.0,
+                quote: "ICP".into(),
+                tvl: Some(20.0),
+                volume_24h: None,
+                fee_apr: None,
+                updated_at: 1,
+            },
+        )]);
+        serde_json::from_str(&execute(query, &pools, &stats)).unwrap()
+    }
+
+    #[test]
+    fn selects_fields_in_order_with_aliases() {
+        let out = execute(
+            "query Pools { all: pools { token_b id } }",
+            &[pool("p1", "ICP", "XTC")],
+            &HashMap::new(),
+        );
+        assert_eq!(out, r#"{"data":{"all":[{"token_b":"XTC","id":"p1"}]}}"#);
+    }
+
+    #[test]
+    fn filters_and_paginates_by_id() {
+        let out = run(r#"{ pools(token: "XTC", first: 1) { id } }"#);
+        assert_eq!(out, json!({"data": {"pools": [{"id": "p1"}]}}));
+        let out = run(r#"{ pools(token: "XTC", after: "p1") { id } }"#);
+        assert_eq!(out, json!({"data": {"pools": [{"id": "p2"}]}}));
+        let out = run("{ pools(first: 0) { id } }");
+        assert_eq!(out, json!({"data": {"pools": []}}));
+    }
+
+    #[test]
+    fn resolves_single_pool_with_nested_tokens() {
+        let out = run(r#"{
+                pool(id: "p3") { id tokens { symbol decimals image __typename } }
+                missing: pool(id: "nope") { id }
+            }"#);
+        assert_eq!(
+            out,
+            json!({"data": {
+                "pool": {"id": "p3", "tokens": [
+                    {"symbol": "ICP", "decimals": 8, "image": null, "__typename": "Token"},
+                    {"symbol": "ckBTC", "decimals": 6,
+                     "image": "https://example.com/ckBTC.png", "__typename": "Token"},
+                ]},
+                "missing": null,
+            }})
+        );
+    }
+
+    #[test]
+    fn stats_are_null_when_missing_or_unpriced() {
+        let out = run(r#"{ pools(first: 2) { id stats { tvl volume_24h fee_apr quote } } }"#);
+        assert_eq!(
+            out,
+            json!({"data": {"pools": [
+                {"id": "p1", "stats": {"tvl": 20.0, "volume_24h": null, "fee_apr": null, "quote": "ICP"}},
+                {"id": "p2", "stats": null},
+            ]}})
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_locations() {
+        let out = run("{ pools { id\n  colour } }");
+        assert!(out.get("data").is_none());
+        assert_eq!(
+            out["errors"][0],
+            json!({
+                "message": "Cannot query field \"colour\" on type \"Pool\".",
+                "locations": [{"line": 2, "column": 3}],
+            })
+        );
+        let out = run("{ pool { id } pools(first: \"x\") { tokens } }");
+        let messages: Vec<&str> = out["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["message"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Field \"pool\" argument \"id\" of type \"String!\" is required.",
+                "Argument \"first\" has invalid value; expected Int.",
+                "Field \"tokens\" of type \"Token\" must have a selection of subfields.",
+            ]
+        );
+        let out = run("{ pools { id }");
+        assert_eq!(
+            out["errors"][0]["message"],
+            "Syntax Error: Unexpected <EOF>."
+        );
+        let out = run("{ ...PoolFields }");
+        assert_eq!(out["errors"][0]["locations"][0]["column"], 3);
+    }
+
+    #[test]
+    fn rejects_deeply_nested_queries() {
+        let query = format!("{}{}", "{ pools ".repeat(10_000), "}".repeat(10_000));
+        let out = run(&query);
+        assert!(out.get("data").is_none());
+        assert_eq!(
+            out["errors"][0]["message"],
+            "Query is nested deeper than 16 levels."
+        );
+        assert_eq!(out["errors"][0]["locations"][0]["column"], 16 * 8 + 1);
+    }
+}
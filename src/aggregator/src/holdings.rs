@@ -0,0 +1,226 @@
+use crate::cache::{self, Freshness};
+use crate::error::FetchError;
+use crate::singleflight::Group;
+use bx_core::Holding;
+use candid::{CandidType, Principal};
+use futures::future::join_all;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+// Holdings are cached per source so each can have its own TTL. Sources past
+// their TTL are served with `stale` set while a refresh runs in the
+// background; data past the cache's max staleness is fetched again first.
+
+type SourceResult = Result<Vec<Holding>, FetchError>;
+
+/// Concurrent misses for one principal and source share a single fetch.
+static FLIGHT: Lazy<Group<(Principal, &'static str), SourceResult>> = Lazy::new(Group::new);
+
+#[cfg(target_arch = "wasm32")]
+static REVALIDATE_INTERVAL_SECS: Lazy<u64> = Lazy::new(|| {
+    option_env!("CACHE_REVALIDATE_INTERVAL_SECS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(10)
+        .max(1)
+});
+
+#[cfg(target_arch = "wasm32")]
+const REVALIDATE_PER_TICK: usize = 10;
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct SourceStatus {
+    pub source: String,
+    /// When the served data was fetched; `None` if nothing could be served
+    pub fetched_at: Option<u64>,
+    pub stale: bool,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct HoldingsStatus {
+    pub holdings: Vec<Holding>,
+    /// Set when any source was served past its TTL
+    pub stale: bool,
+    pub sources: Vec<SourceStatus>,
+}
+
+/// Every source in the order holdings are merged.
+pub fn sources() -> Vec<&'static str> {
+    let mut out = vec!["ledger", "neuron"];
+    out.extend(crate::dex_fetchers::SOURCES);
+    out
+}
+
+#[cfg(target_arch = "wasm32")]
+fn source_name(source: &str) -> Option<&'static str> {
+    sources().into_iter().find(|s| *s == source)
+}
+
+async fn fetch_source(principal: Principal, source: &'static str) -> SourceResult {
+    let res = match source {
+        "ledger" => crate::ledger_fetcher::fetch(principal).await,
+        "neuron" => Ok(crate::neuron_fetcher::fetch(principal).await),
+        dex => crate::dex_fetchers::fetch_source(dex, principal).await,
+    };
+    match res {
+        // an unconfigured source simply has nothing to report
+        Err(FetchError::InvalidConfig(_)) => Ok(Vec::new()),
+        other => other,
+    }
+}
+
+/// Fetch one source and cache the result if it succeeded.
+pub async fn refresh_source(principal: Principal, source: &'static str) -> SourceResult {
+    FLIGHT
+        .run((principal, source), || async move {
+            let res = fetch_source(principal, source).await;
+            match &res {
+                Ok(h) => cache::store(principal, source, h.clone()),
+                Err(e) => tracing::warn!(%principal, source, "holdings fetch failed: {e}"),
+            }
+            res
+        })
+        .await
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn revalidate(principal: Principal, source: &'static str) {
+    tokio::spawn(async move {
+        let _ = refresh_source(principal, source).await;
+    });
+}
+
+// Queries cannot persist state on the IC, so stale entries are picked up by
+// a timer instead of being refreshed from the read path.
+#[cfg(target_arch = "wasm32")]
+fn revalidate(_principal: Principal, _source: &'static str) {}
+
+#[cfg(target_arch = "wasm32")]
+pub fn schedule_revalidation() {
+    use std::time::Duration;
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(*REVALIDATE_INTERVAL_SECS), || {
+        ic_cdk::spawn(async {
+            for (principal, source) in cache::stale_entries(REVALIDATE_PER_TICK) {
+                if let Some(source) = source_name(&source) {
+                    let _ = refresh_source(principal, source).await;
+                }
+            }
+        });
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn schedule_revalidation() {}
+
+async fn resolve(principal: Principal, source: &'static str) -> (SourceStatus, Vec<Holding>) {
+    let mut status = SourceStatus {
+        source: source.to_string(),
+        fetched_at: None,
+        stale: false,
+        error: None,
+    };
+    match cache::lookup(principal, source) {
+        Some((entry, Freshness::Fresh)) => {
+            status.fetched_at = Some(entry.fetched_at);
+            (status, entry.holdings)
+        }
+        Some((entry, Freshness::Stale)) => {
+            revalidate(principal, source);
+            status.fetched_at = Some(entry.fetched_at);
+            status.stale = true;
+            (status, entry.holdings)
+        }
+        _ => match refresh_source(principal, source).await {
+            Ok(holdings) => {
+                status.fetched_at = Some(crate::utils::now());
+                (status, holdings)
+            }
+            Err(e) => {
+                status.error = Some(e.to_string());
+                (status, Vec::new())
+            }
+        },
+    }
+}
+
+fn merge(results: Vec<(SourceStatus, Vec<Holding>)>) -> HoldingsStatus {
+    let capacity = results.iter().map(|(_, h)| h.len()).sum();
+    let mut out = HoldingsStatus {
+        holdings: Vec::with_capacity(capacity),
+        stale: results.iter().any(|(s, _)| s.stale),
+        sources: Vec::with_capacity(results.len()),
+    };
+    for (status, holdings) in results {
+        out.holdings.extend(holdings);
+        out.sources.push(status);
+    }
+    out
+}
+
+/// Holdings for `principal`, served from cache where the TTLs allow.
+pub async fn get(principal: Principal) -> HoldingsStatus {
+    let results = join_all(sources().into_iter().map(|s| resolve(principal, s))).await;
+    merge(results)
+}
+
+/// Fetch every source regardless of cache state.
+pub async fn refresh_all(principal: Principal) -> Vec<Holding> {
+    let results = join_all(
+        sources()
+            .into_iter()
+            .map(|s| async move { refresh_source(principal, s).await.unwrap_or_default() }),
+    )
+    .await;
+    results.into_iter().flatten().collect()
+}
+
+/// Cached holdings in merge order, ignoring TTLs but not max staleness.
+pub fn cached(principal: Principal) -> Vec<Holding> {
+    sources()
+        .into_iter()
+        .filter_map(|s| cache::lookup(principal, s))
+        .filter(|(_, state)| *state != Freshness::Expired)
+        .flat_map(|(entry, _)| entry.holdings)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn stale_sources_are_served_then_revalidated() {
+        let principal = Principal::from_slice(&[9; 29]);
+        let old = vec![Holding {
+            source: "neuron".into(),
+            token: "ICP".into(),
+            amount: "1".into(),
+            status: "locked".into(),
+        }];
+        cache::store(principal, "neuron", old.clone());
+        cache::backdate(principal, "neuron", 601);
+
+        let (status, holdings) = resolve(principal, "neuron").await;
+        assert!(status.stale);
+        assert_eq!(holdings, old);
+        // the spawned refresh replaces the entry once it completes
+        let entry = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                match cache::lookup(principal, "neuron") {
+                    Some((entry, Freshness::Fresh)) => return entry,
+                    _ => tokio::task::yield_now().await,
+                }
+            }
+        })
+        .await
+        .expect("stale entry was not revalidated");
+        assert_ne!(entry.holdings, old);
+
+        cache::backdate(principal, "neuron", 7200);
+        assert!(cached(principal).is_empty());
+        let (status, holdings) = resolve(principal, "neuron").await;
+        assert!(!status.stale);
+        assert_eq!(holdings.len(), 1);
+        assert_eq!(holdings[0].amount, "1200");
+    }
+}
@@ -11,8 +11,8 @@ use candid::{Decode, Encode};
 use dashmap::DashMap;
 #[cfg(not(target_arch = "wasm32"))]
 use futures::future::join_all;
+#[cfg(not(target_arch = "wasm32"))]
 use once_cell::sync::Lazy;
-use serde::Deserialize;
 #[cfg(not(target_arch = "wasm32"))]
 use sha2::{Digest, Sha256};
 #[cfg(not(target_arch = "wasm32"))]
@@ -44,40 +44,6 @@ fn now() -> u64 {
     *TEST_NOW.lock().unwrap()
 }
 
-#[derive(Deserialize)]
-struct LedgersConfig {
-    ledgers: std::collections::HashMap<String, String>,
-}
-
-#[cfg(target_arch = "wasm32")]
-pub static LEDGERS: Lazy<Vec<Principal>> = Lazy::new(|| {
-    let cfg: LedgersConfig =
-        toml::from_str(include_str!("../../../config/ledgers.toml")).expect("invalid config");
-    let mut ids: Vec<Principal> = cfg
-        .ledgers
-        .values()
-        .map(|id| Principal::from_text(id).expect("invalid principal"))
-        .collect();
-    ids.sort();
-    ids.dedup();
-    ids
-});
-
-#[cfg(not(target_arch = "wasm32"))]
-pub static LEDGERS: Lazy<Vec<Principal>> = Lazy::new(|| {
-    let path = std::env::var("LEDGERS_FILE").unwrap_or_else(|_| "config/ledgers.toml".to_string());
-    let text = std::fs::read_to_string(path).expect("cannot read ledgers.toml");
-    let cfg: LedgersConfig = toml::from_str(&text).expect("invalid config");
-    let mut ids: Vec<Principal> = cfg
-        .ledgers
-        .values()
-        .map(|id| Principal::from_text(id).expect("invalid principal"))
-        .collect();
-    ids.sort();
-    ids.dedup();
-    ids
-});
-
 /// Duration that cached metadata remains valid (default 24h)
 #[cfg(not(target_arch = "wasm32"))]
 static META_TTL_NS: Lazy<u64> = Lazy::new(|| {
@@ -300,8 +266,8 @@ async fn icrc1_balance_of(
 #[cfg(not(target_arch = "wasm32"))]
 pub async fn fetch(principal: Principal) -> Result<Vec<Holding>, FetchError> {
     let agent = get_agent().await;
-    let mut ids: Vec<Principal> = LEDGERS.iter().cloned().collect();
-    ids.sort();
+    let ids = crate::config::ledger_ids();
+    let count = ids.len();
     let futures = ids.into_iter().map(|cid| {
         let agent = agent.clone();
         async move {
@@ -318,7 +284,7 @@ pub async fn fetch(principal: Principal) -> Result<Vec<Holding>, FetchError> {
         }
     });
     let results = join_all(futures).await;
-    let mut holdings = Vec::with_capacity(LEDGERS.len());
+    let mut holdings = Vec::with_capacity(count);
     for r in results {
         match r {
             Ok(h) => holdings.push(h),
@@ -334,12 +300,24 @@ pub async fn fetch(_principal: Principal) -> Result<Vec<Holding>, FetchError> {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-async fn fetch_metadata(agent: &Agent, cid: Principal) -> Result<(String, u8, u64), FetchError> {
+type MetaResult = Result<(String, u8, u64), FetchError>;
+
+#[cfg(not(target_arch = "wasm32"))]
+static META_FLIGHT: Lazy<crate::singleflight::Group<Principal, MetaResult>> =
+    Lazy::new(crate::singleflight::Group::new);
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn fetch_metadata(agent: &Agent, cid: Principal) -> MetaResult {
     if let Some(meta) = META_CACHE.get(&cid) {
         if meta.expires > now() {
             return Ok((meta.symbol.clone(), meta.decimals, meta.fee));
         }
     }
+    META_FLIGHT.run(cid, || load_metadata(agent, cid)).await
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn load_metadata(agent: &Agent, cid: Principal) -> MetaResult {
     let items = with_retry(|| icrc1_metadata(agent, cid))
         .await
         .map_err(FetchError::from)?;
@@ -426,6 +404,12 @@ mod tests {
     use super::*;
     use candid::types::value::IDLValue;
 
+    fn use_single_ledger() {
+        let (cfg, _) =
+            crate::config::parse_toml(include_str!("../tests/ledgers_single.toml")).unwrap();
+        crate::config::stable_restore(cfg);
+    }
+
     #[test]
     fn format_amount_basic() {
         assert_eq!(format_amount(Nat::from(1000u64), 0), "1000");
@@ -500,8 +484,7 @@ mod tests {
     #[tokio::test(flavor = "current_thread")]
     #[serial_test::serial]
     async fn fetch_happy_path() {
-        std::env::set_var("LEDGERS_FILE", "tests/ledgers_single.toml");
-        once_cell::sync::Lazy::force(&LEDGERS);
+        use_single_ledger();
         set_mock_metadata(Ok(vec![
             ("icrc1:symbol".into(), IDLValue::Text("AAA".into())),
             ("icrc1:decimals".into(), IDLValue::Nat8(2)),
@@ -519,8 +502,7 @@ mod tests {
     #[tokio::test(flavor = "current_thread")]
     #[serial_test::serial]
     async fn fetch_balance_error() {
-        std::env::set_var("LEDGERS_FILE", "tests/ledgers_single.toml");
-        once_cell::sync::Lazy::force(&LEDGERS);
+        use_single_ledger();
         set_mock_metadata(Ok(vec![
             ("icrc1:symbol".into(), IDLValue::Text("AAA".into())),
             ("icrc1:decimals".into(), IDLValue::Nat8(2)),
@@ -535,8 +517,7 @@ mod tests {
     #[tokio::test(flavor = "current_thread")]
     #[serial_test::serial]
     async fn fetch_metadata_error() {
-        std::env::set_var("LEDGERS_FILE", "tests/ledgers_single.toml");
-        once_cell::sync::Lazy::force(&LEDGERS);
+        use_single_ledger();
         set_mock_metadata(Err("bad".into()));
         set_mock_balance(Ok(Nat::from(10u64)));
         META_CACHE.clear();
@@ -1,19 +1,32 @@
 pub mod cache;
 pub mod cert;
+pub mod config;
 pub mod cycles;
 pub mod dex;
 pub mod dex_fetchers;
 pub mod error;
+pub mod graphql;
+pub mod holdings;
 pub mod ledger_fetcher;
+pub mod log_store;
 pub mod logging;
 pub mod lp_cache;
+pub mod lru;
 pub mod metrics;
 pub mod neuron_fetcher;
+pub mod nft;
 pub mod pool_registry;
+pub mod pool_stats;
+pub mod position_index;
+pub mod singleflight;
+pub mod stable;
+pub mod transactions;
+pub mod upgrade;
 pub mod utils;
 pub mod warm;
 
-use crate::utils::{now, MINUTE_NS};
+#[cfg(feature = "claim")]
+use crate::utils::now;
 use bx_core::Holding;
 use candid::Principal;
 use once_cell::sync::Lazy;
@@ -94,18 +107,7 @@ static CLAIM_ADAPTER_TIMEOUT_SECS: Lazy<u64> = Lazy::new(|| {
 });
 
 async fn calculate_holdings(principal: Principal) -> Vec<Holding> {
-    let (ledger, neuron, dex) = futures::join!(
-        ledger_fetcher::fetch(principal),
-        neuron_fetcher::fetch(principal),
-        dex_fetchers::fetch(principal)
-    );
-
-    let capacity =
-        ledger.as_ref().map_or(0, |v| v.len()) + neuron.len() + dex.as_ref().map_or(0, |v| v.len());
-    let mut holdings = Vec::with_capacity(capacity);
-    holdings.extend(ledger.unwrap_or_default());
-    holdings.extend(neuron);
-    holdings.extend(dex.unwrap_or_default());
+    let mut holdings = holdings::refresh_all(principal).await;
     if holdings.len() > *MAX_HOLDINGS {
         holdings.truncate(*MAX_HOLDINGS);
     }
@@ -122,48 +124,106 @@ fn instructions() -> u64 {
     0
 }
 
-#[ic_cdk_macros::query]
-pub async fn get_holdings(principal: Principal) -> Vec<Holding> {
-    metrics::inc_query();
-    let start = instructions();
-    let now = now();
-    {
-        let cache = cache::get();
-        if let Some(v) = cache.get(&principal) {
-            let (cached, ts) = v.value().clone();
-            if now - ts < MINUTE_NS {
-                let used = instructions().saturating_sub(start);
-                tracing::info!(
-                    "get_holdings took {used} instructions ({:.2} B)",
-                    used as f64 / 1_000_000_000f64
-                );
-                return cached;
-            }
-        }
+#[cfg(target_arch = "wasm32")]
+fn require_controller() {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        ic_cdk::api::trap("unauthorized");
     }
+}
 
-    let (ledger, neuron, dex) = futures::join!(
-        ledger_fetcher::fetch(principal),
-        neuron_fetcher::fetch(principal),
-        dex_fetchers::fetch(principal)
-    );
+#[cfg(not(target_arch = "wasm32"))]
+fn require_controller() {}
 
-    let capacity =
-        ledger.as_ref().map_or(0, |v| v.len()) + neuron.len() + dex.as_ref().map_or(0, |v| v.len());
-    let mut holdings = Vec::with_capacity(capacity);
-    holdings.extend(ledger.unwrap_or_default());
-    holdings.extend(neuron);
-    holdings.extend(dex.unwrap_or_default());
+#[ic_cdk_macros::query]
+pub async fn get_holdings(principal: Principal) -> Vec<Holding> {
+    get_holdings_with_status(principal).await.holdings
+}
 
-    {
-        cache::get().insert(principal, (holdings.clone(), now));
-    }
+#[ic_cdk_macros::query]
+pub async fn get_holdings_with_status(principal: Principal) -> holdings::HoldingsStatus {
+    metrics::inc_query();
+    let start = instructions();
+    let status = holdings::get(principal).await;
     let used = instructions().saturating_sub(start);
     tracing::info!(
         "get_holdings took {used} instructions ({:.2} B)",
         used as f64 / 1_000_000_000f64
     );
-    holdings
+    status
+}
+
+#[ic_cdk_macros::query]
+pub fn get_cache_config() -> cache::CacheConfig {
+    metrics::inc_query();
+    cache::config()
+}
+
+#[ic_cdk_macros::update]
+pub fn set_cache_config(cfg: cache::CacheConfig) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    cache::set_config(cfg)
+}
+
+#[ic_cdk_macros::query(composite = true)]
+pub async fn get_open_orders(principal: Principal) -> Vec<dex::OpenOrder> {
+    metrics::inc_query();
+    dex_fetchers::open_orders(principal).await
+}
+
+#[ic_cdk_macros::query(composite = true)]
+pub async fn get_liquid_staking(principal: Principal) -> Vec<dex::liquid_staking::LiquidStake> {
+    metrics::inc_query();
+    dex_fetchers::liquid_stakes(principal).await
+}
+
+/// Pending chain-key transfers of `principal`. ckBTC deposits are listed
+/// once an update such as `refresh_holdings` has cached the deposit address.
+#[ic_cdk_macros::query(composite = true)]
+pub async fn get_chain_key_pending(principal: Principal) -> Vec<dex::chain_key::ChainKeyPending> {
+    metrics::inc_query();
+    dex_fetchers::chain_key_pending(principal).await
+}
+
+/// ICRC-7 NFTs owned by `principal`, `limit` at a time (at most 100). Pass
+/// the returned `next` cursor to continue.
+#[ic_cdk_macros::query(composite = true)]
+pub async fn get_nfts(
+    principal: Principal,
+    after: Option<nft::NftCursor>,
+    limit: Option<u32>,
+) -> nft::NftPage {
+    metrics::inc_query();
+    nft::get_nfts(principal, after, limit).await
+}
+
+/// Transactions of `principal` on one configured ledger, newest first. Pass
+/// the returned `next` as `cursor` to continue.
+#[ic_cdk_macros::query(composite = true)]
+pub async fn get_transactions(
+    principal: Principal,
+    ledger: Principal,
+    cursor: Option<candid::Nat>,
+) -> Result<transactions::TransactionPage, String> {
+    metrics::inc_query();
+    transactions::get_transactions(principal, ledger, cursor).await
+}
+
+/// Drop cached holdings and LP positions for a principal, a source, both,
+/// or everything when neither is given.
+#[ic_cdk_macros::update]
+pub fn flush_cache(principal: Option<Principal>, source: Option<String>) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    if let Some(s) = &source {
+        if !holdings::sources().contains(&s.as_str()) {
+            return Err(format!("unknown source {s}"));
+        }
+    }
+    let holdings = cache::flush(principal, source.as_deref());
+    let lp = lp_cache::flush(principal, source.as_deref());
+    tracing::info!(holdings, lp, "cache flushed");
+    Ok(())
 }
 
 #[cfg(feature = "claim")]
@@ -212,13 +272,16 @@ pub async fn claim_all_rewards(principal: Principal) -> Vec<u64> {
     }
     let _guard = Guard(principal);
     use dex::{
-        dex_icpswap::IcpswapAdapter, dex_infinity::InfinityAdapter, dex_sonic::SonicAdapter,
+        dex_icpswap::IcpswapAdapter, dex_icpswap_farm::IcpswapFarmAdapter,
+        dex_infinity::InfinityAdapter, dex_kongswap::KongSwapAdapter, dex_sonic::SonicAdapter,
         sns_adapter::SnsAdapter, DexAdapter,
     };
     let mut adapters: Vec<Box<dyn DexAdapter>> = vec![
         Box::new(IcpswapAdapter),
+        Box::new(IcpswapFarmAdapter),
         Box::new(SonicAdapter),
         Box::new(InfinityAdapter),
+        Box::new(KongSwapAdapter),
         Box::new(SnsAdapter),
     ];
     if *MAX_CLAIM_PER_CALL < adapters.len() {
@@ -280,6 +343,12 @@ pub fn pools_graphql(query: String) -> String {
     pool_registry::graphql(query)
 }
 
+#[ic_cdk_macros::query]
+pub fn get_pool_stats() -> Vec<pool_stats::PoolStats> {
+    metrics::inc_query();
+    pool_stats::list()
+}
+
 #[derive(candid::CandidType, serde::Serialize)]
 pub struct CertifiedHoldings {
     pub holdings: Vec<Holding>,
@@ -292,19 +361,15 @@ pub struct CertifiedHoldings {
 #[ic_cdk_macros::update]
 pub async fn refresh_holdings(principal: Principal) {
     metrics::inc_query();
-    let now = now();
     let holdings = calculate_holdings(principal).await;
-    cache::get().insert(principal, (holdings.clone(), now));
     cert::update(principal, &holdings);
 }
 
 #[ic_cdk_macros::query]
 pub fn get_holdings_cert(principal: Principal) -> CertifiedHoldings {
     metrics::inc_query();
-    let holdings = cache::get()
-        .get(&principal)
-        .map(|v| v.value().0.clone())
-        .unwrap_or_default();
+    let mut holdings = holdings::cached(principal);
+    holdings.truncate(*MAX_HOLDINGS);
     let certificate = ic_cdk::api::data_certificate().unwrap_or_default();
     let witness = cert::witness(principal);
     CertifiedHoldings {
@@ -335,6 +400,137 @@ pub fn get_cycles_log() -> Vec<String> {
     cycles::log()
 }
 
+#[ic_cdk_macros::query]
+pub fn get_logs(filter: log_store::LogFilter) -> Vec<log_store::LogRecord> {
+    metrics::inc_query();
+    require_controller();
+    log_store::query(&filter).unwrap_or_else(|e| ic_cdk::api::trap(&e))
+}
+
+#[ic_cdk_macros::query]
+pub fn get_log_config() -> logging::LogConfig {
+    metrics::inc_query();
+    logging::config()
+}
+
+#[ic_cdk_macros::update]
+pub fn set_log_config(config: logging::LogConfig) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    logging::set_config(config)
+}
+
+#[ic_cdk_macros::query]
+pub fn get_config() -> config::Config {
+    metrics::inc_query();
+    config::get()
+}
+
+/// Drop state derived from the old config so lookups pick up the change.
+fn config_changed(result: Result<(), String>) -> Result<(), String> {
+    if result.is_ok() {
+        dex::clear_all_caches();
+        warm::init();
+    }
+    result
+}
+
+#[ic_cdk_macros::update]
+pub fn add_ledger(name: String, id: Principal, index: Option<Principal>) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    config_changed(config::add_ledger(name, id, index))
+}
+
+#[ic_cdk_macros::update]
+pub fn remove_ledger(name: String) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    config_changed(config::remove_ledger(&name))
+}
+
+#[ic_cdk_macros::query]
+pub fn get_lockers() -> Vec<dex::locker::LockerConfig> {
+    metrics::inc_query();
+    dex::locker::lockers()
+}
+
+#[ic_cdk_macros::update]
+pub fn add_locker(locker: dex::locker::LockerConfig) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    dex::locker::add_locker(locker)
+}
+
+#[ic_cdk_macros::update]
+pub fn remove_locker(name: String) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    dex::locker::remove_locker(&name)
+}
+
+#[ic_cdk_macros::query]
+pub fn get_nft_collections() -> Vec<nft::NftCollection> {
+    metrics::inc_query();
+    nft::collections()
+}
+
+/// Add or replace an NFT collection; `standard` defaults to ICRC-7.
+#[ic_cdk_macros::update]
+pub fn add_nft_collection(
+    name: String,
+    id: Principal,
+    standard: Option<nft::NftStandard>,
+) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    nft::add_collection(name, id, standard.unwrap_or(nft::NftStandard::Icrc7))
+}
+
+#[ic_cdk_macros::update]
+pub fn remove_nft_collection(name: String) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    nft::remove_collection(&name)
+}
+
+#[ic_cdk_macros::update]
+pub fn set_ledger_enabled(name: String, enabled: bool) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    config_changed(config::set_ledger_enabled(&name, enabled))
+}
+
+#[ic_cdk_macros::update]
+pub async fn add_dex(
+    name: String,
+    id: Principal,
+    controller: Option<Principal>,
+) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    if let Some(expected) = controller {
+        if !utils::verify_controller(id, expected).await {
+            return Err(format!("{expected} does not control {id}"));
+        }
+    }
+    config_changed(config::add_dex(name, id, controller))
+}
+
+#[ic_cdk_macros::update]
+pub fn remove_dex(name: String) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    config_changed(config::remove_dex(&name))
+}
+
+#[ic_cdk_macros::update]
+pub fn set_dex_enabled(name: String, enabled: bool) -> Result<(), String> {
+    metrics::inc_query();
+    require_controller();
+    config_changed(config::set_dex_enabled(&name, enabled))
+}
+
 #[cfg(feature = "claim")]
 #[derive(candid::CandidType, serde::Serialize)]
 pub struct ClaimStatus {
@@ -371,3 +567,24 @@ pub fn health_check() -> &'static str {
     metrics::inc_query();
     "ok"
 }
+
+#[derive(candid::CandidType, serde::Serialize)]
+pub struct HealthStatus {
+    /// False when the last config load skipped entries or failed outright
+    pub ok: bool,
+    pub ledgers: u32,
+    pub dex: u32,
+    pub config_errors: Vec<error::ConfigError>,
+}
+
+#[ic_cdk_macros::query]
+pub fn health_status() -> HealthStatus {
+    metrics::inc_query();
+    let config_errors = config::load_errors();
+    HealthStatus {
+        ok: config_errors.is_empty(),
+        ledgers: config::ledger_ids().len() as u32,
+        dex: config::dex_ids().len() as u32,
+        config_errors,
+    }
+}
@@ -0,0 +1,261 @@
+use crate::stable::{self, Memory};
+use crate::utils::now;
+use candid::CandidType;
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+// Structured log records are kept in a bounded ring buffer in stable memory,
+// keyed by a sequence number, so recent history survives upgrades and
+// replica log rotation without counting against the heap. The oldest records
+// are dropped past `LOG_BUFFER_SIZE`.
+
+static LOG_BUFFER_SIZE: Lazy<usize> = Lazy::new(|| {
+    option_env!("LOG_BUFFER_SIZE")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(1000)
+        .max(1)
+});
+
+static RECORDS: Lazy<Mutex<StableBTreeMap<u64, LogRecord, Memory>>> =
+    Lazy::new(|| Mutex::new(StableBTreeMap::init(stable::memory(stable::LOGS))));
+
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub timestamp: u64,
+    pub level: String,
+    pub module: String,
+    pub message: String,
+    pub fields: Vec<(String, String)>,
+}
+
+impl Storable for LogRecord {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        stable::to_bytes(self)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        stable::from_bytes(bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(Debug, Clone, Default, CandidType, Deserialize)]
+pub struct LogFilter {
+    /// Minimum severity, e.g. `warn` returns warnings and errors
+    pub level: Option<String>,
+    /// Module path prefix such as `aggregator::dex`
+    pub module: Option<String>,
+    /// Inclusive lower bound in nanoseconds since the epoch
+    pub since: Option<u64>,
+    /// Inclusive upper bound in nanoseconds since the epoch
+    pub until: Option<u64>,
+    /// Return at most this many of the newest matching records
+    pub limit: Option<u32>,
+}
+
+/// `tracing` layer that copies every enabled event into the ring buffer.
+pub struct RingLayer;
+
+impl<S: Subscriber> Layer<S> for RingLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        push(LogRecord {
+            timestamp: now(),
+            level: meta.level().to_string(),
+            module: meta.module_path().unwrap_or(meta.target()).to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+        });
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields
+                .push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields
+                .push((field.name().to_string(), format!("{value:?}")));
+        }
+    }
+}
+
+fn push(record: LogRecord) {
+    let mut records = RECORDS.lock().unwrap();
+    while records.len() >= *LOG_BUFFER_SIZE as u64 {
+        records.pop_first();
+    }
+    let seq = records.last_key_value().map_or(0, |(seq, _)| seq + 1);
+    records.insert(seq, record);
+}
+
+/// Return matching records in chronological order.
+pub fn query(filter: &LogFilter) -> Result<Vec<LogRecord>, String> {
+    let max_level = match filter.level.as_deref() {
+        Some(l) => Some(Level::from_str(l).map_err(|_| format!("invalid level {l}"))?),
+        None => None,
+    };
+    let records = RECORDS.lock().unwrap();
+    let mut out: Vec<LogRecord> = records
+        .iter()
+        .map(|(_, r)| r)
+        .filter(|r| matches(filter, max_level, r))
+        .collect();
+    if let Some(limit) = filter.limit {
+        let limit = limit as usize;
+        if out.len() > limit {
+            out.drain(..out.len() - limit);
+        }
+    }
+    Ok(out)
+}
+
+fn matches(filter: &LogFilter, max_level: Option<Level>, record: &LogRecord) -> bool {
+    if let Some(max) = max_level {
+        if Level::from_str(&record.level).is_ok_and(|lvl| lvl > max) {
+            return false;
+        }
+    }
+    if let Some(module) = filter.module.as_deref() {
+        if !record.module.starts_with(module) {
+            return false;
+        }
+    }
+    if filter.since.is_some_and(|s| record.timestamp < s) {
+        return false;
+    }
+    if filter.until.is_some_and(|u| record.timestamp > u) {
+        return false;
+    }
+    true
+}
+
+/// Replace the buffer with records saved on the heap by an older release.
+pub fn stable_restore(data: Vec<LogRecord>) {
+    let mut records = RECORDS.lock().unwrap();
+    records.clear_new();
+    let skip = data.len().saturating_sub(*LOG_BUFFER_SIZE);
+    for (seq, record) in data.into_iter().skip(skip).enumerate() {
+        records.insert(seq as u64, record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn record(ts: u64, level: &str, module: &str) -> LogRecord {
+        LogRecord {
+            timestamp: ts,
+            level: level.into(),
+            module: module.into(),
+            message: format!("{level} at {ts}"),
+            fields: Vec::new(),
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn layer_captures_structured_fields() {
+        stable_restore(Vec::new());
+        let subscriber = tracing_subscriber::registry().with(RingLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(pool = "p1", retries = 3, "fetch failed");
+        });
+        let logs = query(&LogFilter::default()).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].level, "WARN");
+        assert_eq!(logs[0].module, module_path!());
+        assert_eq!(logs[0].message, "fetch failed");
+        assert_eq!(
+            logs[0].fields,
+            vec![
+                ("pool".to_string(), "p1".to_string()),
+                ("retries".to_string(), "3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn buffer_is_bounded() {
+        let data = (0..*LOG_BUFFER_SIZE as u64 + 5)
+            .map(|i| record(i, "INFO", "aggregator"))
+            .collect();
+        stable_restore(data);
+        push(record(u64::MAX, "INFO", "aggregator"));
+        let logs = query(&LogFilter::default()).unwrap();
+        assert_eq!(logs.len(), *LOG_BUFFER_SIZE);
+        assert_eq!(logs[0].timestamp, 6);
+        assert_eq!(logs.last().unwrap().timestamp, u64::MAX);
+    }
+
+    #[test]
+    #[serial]
+    fn filters_by_level_module_and_time() {
+        stable_restore(vec![
+            record(1, "DEBUG", "aggregator::dex::dex_sonic"),
+            record(2, "ERROR", "aggregator::dex::dex_icpswap"),
+            record(3, "WARN", "aggregator::cycles"),
+            record(4, "INFO", "aggregator::dex::dex_icpswap"),
+        ]);
+        let warn = query(&LogFilter {
+            level: Some("warn".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            warn.iter().map(|r| r.timestamp).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        let dex = query(&LogFilter {
+            module: Some("aggregator::dex".into()),
+            since: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            dex.iter().map(|r| r.timestamp).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        let newest = query(&LogFilter {
+            until: Some(3),
+            limit: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(newest[0].timestamp, 3);
+        assert!(query(&LogFilter {
+            level: Some("loud".into()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
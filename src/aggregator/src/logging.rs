@@ -1,8 +1,33 @@
-use once_cell::sync::OnceCell;
-use tracing::Level;
-use tracing_subscriber::fmt;
+use candid::CandidType;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt,
+    layer::SubscriberExt,
+    reload, Registry,
+};
 
 static INIT: OnceCell<()> = OnceCell::new();
+static HANDLE: OnceCell<reload::Handle<Targets, Registry>> = OnceCell::new();
+
+/// Active filter settings; persisted across upgrades.
+static CONFIG: Lazy<Mutex<LogConfig>> = Lazy::new(|| {
+    Mutex::new(LogConfig {
+        level: option_env!("LOG_LEVEL").unwrap_or("info").to_lowercase(),
+        modules: Vec::new(),
+    })
+});
+
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// Global level: `trace`, `debug`, `info`, `warn`, `error` or `off`
+    pub level: String,
+    /// Per-module overrides such as `("aggregator::dex", "trace")`
+    pub modules: Vec<(String, String)>,
+}
 
 #[cfg(target_arch = "wasm32")]
 struct IcWriter;
@@ -18,33 +43,105 @@ impl std::io::Write for IcWriter {
     }
 }
 
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level).map_err(|_| format!("invalid level {level}"))
+}
+
+fn build_targets(cfg: &LogConfig) -> Result<Targets, String> {
+    let mut targets = Targets::new().with_default(parse_level(&cfg.level)?);
+    for (module, level) in &cfg.modules {
+        if module.is_empty() {
+            return Err("empty module path".into());
+        }
+        targets = targets.with_target(module.clone(), parse_level(level)?);
+    }
+    Ok(targets)
+}
+
 pub fn init() {
     INIT.get_or_init(|| {
-        let level = option_env!("LOG_LEVEL").unwrap_or("info").to_lowercase();
-        let lvl = match level.as_str() {
-            "trace" => Level::TRACE,
-            "debug" => Level::DEBUG,
-            "warn" => Level::WARN,
-            "error" => Level::ERROR,
-            _ => Level::INFO,
-        };
+        let targets = build_targets(&config())
+            .unwrap_or_else(|_| Targets::new().with_default(LevelFilter::INFO));
+        let (filter, handle) = reload::Layer::new(targets);
+        let _ = HANDLE.set(handle);
         #[cfg(target_arch = "wasm32")]
         {
-            let subscriber = fmt::Subscriber::builder()
-                .with_max_level(lvl)
-                .with_ansi(false)
-                .with_writer(|| IcWriter)
-                .without_time()
-                .finish();
+            let subscriber = tracing_subscriber::registry()
+                .with(filter)
+                .with(
+                    fmt::layer()
+                        .with_ansi(false)
+                        .with_writer(|| IcWriter)
+                        .without_time(),
+                )
+                .with(crate::log_store::RingLayer);
             let _ = tracing::subscriber::set_global_default(subscriber);
         }
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let subscriber = fmt::Subscriber::builder()
-                .with_max_level(lvl)
-                .with_target(false)
-                .finish();
+            let subscriber = tracing_subscriber::registry()
+                .with(filter)
+                .with(fmt::layer().with_target(false))
+                .with(crate::log_store::RingLayer);
             let _ = tracing::subscriber::set_global_default(subscriber);
         }
     });
 }
+
+pub fn config() -> LogConfig {
+    CONFIG.lock().unwrap().clone()
+}
+
+/// Validate and apply a new filter without rebuilding the canister.
+pub fn set_config(cfg: LogConfig) -> Result<(), String> {
+    let targets = build_targets(&cfg)?;
+    if let Some(handle) = HANDLE.get() {
+        handle.reload(targets).map_err(|e| e.to_string())?;
+    }
+    tracing::info!(level = %cfg.level, overrides = cfg.modules.len(), "log filter updated");
+    *CONFIG.lock().unwrap() = cfg;
+    Ok(())
+}
+
+pub fn stable_save() -> LogConfig {
+    config()
+}
+
+pub fn stable_restore(cfg: LogConfig) {
+    if let Err(e) = set_config(cfg) {
+        tracing::warn!("ignoring stored log filter: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::Level;
+
+    #[test]
+    fn module_overrides_take_precedence() {
+        let targets = build_targets(&LogConfig {
+            level: "warn".into(),
+            modules: vec![("aggregator::dex".into(), "trace".into())],
+        })
+        .unwrap();
+        assert!(targets.would_enable("aggregator::dex::dex_icpswap", &Level::TRACE));
+        assert!(!targets.would_enable("aggregator::cycles", &Level::INFO));
+        assert!(targets.would_enable("aggregator::cycles", &Level::WARN));
+    }
+
+    #[test]
+    fn invalid_levels_are_rejected() {
+        let bad_global = LogConfig {
+            level: "verbose".into(),
+            modules: Vec::new(),
+        };
+        assert!(build_targets(&bad_global).is_err());
+        let bad_module = LogConfig {
+            level: "info".into(),
+            modules: vec![("aggregator::dex".into(), "loud".into())],
+        };
+        assert!(build_targets(&bad_module).is_err());
+        assert!(set_config(bad_module).is_err());
+    }
+}
@@ -1,39 +1,63 @@
+use crate::lru::{holdings_bytes, Lru, Usage};
 use crate::utils::{now, WEEK_NS};
 use bx_core::Holding;
 use candid::Principal;
-use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use std::future::Future;
 
+#[derive(Clone)]
 struct Entry {
+    /// DEX the pool belongs to, so entries can be flushed per source
+    source: Option<String>,
     data: Vec<Holding>,
     height: u64,
     ts: u64,
 }
 
-static CACHE: Lazy<DashMap<(Principal, String), Entry>> = Lazy::new(DashMap::new);
+static CACHE: Lazy<Lru<(Principal, String), Entry>> = Lazy::new(|| {
+    let max_entries = option_env!("LP_CACHE_MAX_ENTRIES")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(50_000);
+    let max_bytes = option_env!("LP_CACHE_MAX_BYTES")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(32 * 1024 * 1024);
+    Lru::new(max_entries, max_bytes)
+});
+
+fn entry_bytes(pool: &str, e: &Entry) -> usize {
+    std::mem::size_of::<Entry>()
+        + pool.len()
+        + e.source.as_ref().map_or(0, String::len)
+        + holdings_bytes(&e.data)
+}
+
+fn insert(principal: Principal, pool: String, entry: Entry) {
+    let bytes = entry_bytes(&pool, &entry);
+    CACHE.insert((principal, pool), entry, bytes);
+}
 
 #[derive(candid::CandidType, serde::Serialize, serde::Deserialize)]
 pub struct StableEntry {
     principal: Principal,
     pool: String,
+    source: Option<String>,
     data: Vec<Holding>,
     height: u64,
     ts: u64,
 }
 
 pub fn stable_save() -> Vec<StableEntry> {
+    // least recently used first so a restore rebuilds the same order
     CACHE
-        .iter()
-        .map(|e| {
-            let (p, pool) = e.key();
-            StableEntry {
-                principal: *p,
-                pool: pool.clone(),
-                data: e.value().data.clone(),
-                height: e.value().height,
-                ts: e.value().ts,
-            }
+        .entries()
+        .into_iter()
+        .map(|((principal, pool), e)| StableEntry {
+            principal,
+            pool,
+            source: e.source,
+            data: e.data,
+            height: e.height,
+            ts: e.ts,
         })
         .collect()
 }
@@ -41,9 +65,11 @@ pub fn stable_save() -> Vec<StableEntry> {
 pub fn stable_restore(entries: Vec<StableEntry>) {
     CACHE.clear();
     for e in entries {
-        CACHE.insert(
-            (e.principal, e.pool.clone()),
+        insert(
+            e.principal,
+            e.pool,
             Entry {
+                source: e.source,
                 data: e.data,
                 height: e.height,
                 ts: e.ts,
@@ -56,6 +82,7 @@ const STALE_NS: u64 = WEEK_NS; // one week
 
 pub async fn get_or_fetch<F, Fut>(
     principal: Principal,
+    source: &str,
     pool: &str,
     height: u64,
     fetch: F,
@@ -64,19 +91,21 @@ where
     F: FnOnce() -> Fut,
     Fut: Future<Output = Vec<Holding>>,
 {
-    if let Some(e) = CACHE.get(&(principal, pool.to_string())) {
-        if e.height == height && now() - e.ts < STALE_NS {
-            return e.data.clone();
-        }
+    let cached = CACHE.get_with(&(principal, pool.to_string()), |e| {
+        (e.height == height && now() - e.ts < STALE_NS).then(|| e.data.clone())
+    });
+    if let Some(data) = cached.flatten() {
+        return data;
     }
     let data = fetch().await;
-    let ts = now();
-    CACHE.insert(
-        (principal, pool.to_string()),
+    insert(
+        principal,
+        pool.to_string(),
         Entry {
+            source: Some(source.to_string()),
             data: data.clone(),
             height,
-            ts,
+            ts: now(),
         },
     );
     data
@@ -87,6 +116,19 @@ pub fn evict_stale() {
     CACHE.retain(|_, v| n - v.ts < STALE_NS);
 }
 
+/// Drop LP entries for `principal`, `source`, both, or all of them.
+/// Entries restored from before sources were recorded only match `None`.
+pub fn flush(principal: Option<Principal>, source: Option<&str>) -> usize {
+    CACHE.retain(|(p, _), e| {
+        principal.is_some_and(|want| want != *p)
+            || source.is_some_and(|want| e.source.as_deref() != Some(want))
+    })
+}
+
+pub fn usage() -> Usage {
+    CACHE.usage()
+}
+
 #[cfg(target_arch = "wasm32")]
 pub fn schedule_eviction() {
     use std::time::Duration;
@@ -119,7 +161,7 @@ mod tests {
         let principal = Principal::from_text("aaaaa-aa").unwrap();
         let pool = "p1";
         let h1 = 1u64;
-        let v1 = get_or_fetch(principal, pool, h1, || async {
+        let v1 = get_or_fetch(principal, "x", pool, h1, || async {
             CALLS.fetch_add(1, Ordering::SeqCst);
             vec![Holding {
                 source: "x".into(),
@@ -130,14 +172,14 @@ mod tests {
         })
         .await;
         assert_eq!(CALLS.load(Ordering::SeqCst), 1);
-        let v2 = get_or_fetch(principal, pool, h1, || async {
+        let v2 = get_or_fetch(principal, "x", pool, h1, || async {
             CALLS.fetch_add(1, Ordering::SeqCst);
             vec![]
         })
         .await;
         assert_eq!(CALLS.load(Ordering::SeqCst), 1);
         assert_eq!(v2, v1);
-        let v3 = get_or_fetch(principal, pool, h1 + 1, || async {
+        let v3 = get_or_fetch(principal, "x", pool, h1 + 1, || async {
             CALLS.fetch_add(1, Ordering::SeqCst);
             vec![Holding {
                 source: "x".into(),
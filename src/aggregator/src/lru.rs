@@ -0,0 +1,309 @@
+use bx_core::Holding;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Approximate heap footprint of a list of holdings.
+pub fn holdings_bytes(holdings: &[Holding]) -> usize {
+    holdings
+        .iter()
+        .map(|h| {
+            std::mem::size_of::<Holding>()
+                + h.source.len()
+                + h.token.len()
+                + h.amount.len()
+                + h.status.len()
+        })
+        .sum()
+}
+
+struct Slot<V> {
+    value: V,
+    bytes: usize,
+    tick: u64,
+}
+
+struct Inner<K, V> {
+    map: HashMap<K, Slot<V>>,
+    /// access tick -> key, oldest first
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    bytes: usize,
+    evictions: u64,
+}
+
+/// Map bounded by entry count and approximate bytes; inserting past either
+/// limit evicts the least recently used entries.
+pub struct Lru<K, V> {
+    inner: Mutex<Inner<K, V>>,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub entries: u64,
+    pub bytes: u64,
+    pub evictions: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Lru<K, V> {
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                map: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+                bytes: 0,
+                evictions: 0,
+            }),
+            max_entries: max_entries.max(1),
+            max_bytes,
+        }
+    }
+
+    /// Value for `key`, marking it as recently used.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_with(key, V::clone)
+    }
+
+    /// Like `get`, but reads the value in place instead of cloning it.
+    pub fn get_with<R>(&self, key: &K, f: impl FnOnce(&V) -> R) -> Option<R> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        inner.tick += 1;
+        let slot = inner.map.get_mut(key)?;
+        inner.order.remove(&slot.tick);
+        slot.tick = inner.tick;
+        inner.order.insert(slot.tick, key.clone());
+        Some(f(&slot.value))
+    }
+
+    /// Value for `key` without touching its position.
+    pub fn peek(&self, key: &K) -> Option<V> {
+        let inner = self.inner.lock().unwrap();
+        inner.map.get(key).map(|s| s.value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V, bytes: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        if let Some(old) = inner.map.remove(&key) {
+            inner.order.remove(&old.tick);
+            inner.bytes -= old.bytes;
+        }
+        self.put(inner, key, value, bytes);
+    }
+
+    /// Modify the value for `key`, starting from the default when absent, and
+    /// mark it as recently used. `f` returns the new size; the lock is held
+    /// throughout so concurrent upserts of one key are not lost.
+    pub fn upsert<F>(&self, key: K, f: F)
+    where
+        V: Default,
+        F: FnOnce(&mut V) -> usize,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let mut value = match inner.map.remove(&key) {
+            Some(old) => {
+                inner.order.remove(&old.tick);
+                inner.bytes -= old.bytes;
+                old.value
+            }
+            None => V::default(),
+        };
+        let bytes = f(&mut value);
+        self.put(inner, key, value, bytes);
+    }
+
+    fn put(&self, inner: &mut Inner<K, V>, key: K, value: V, bytes: usize) {
+        inner.tick += 1;
+        inner.order.insert(inner.tick, key.clone());
+        inner.bytes += bytes;
+        inner.map.insert(
+            key,
+            Slot {
+                value,
+                bytes,
+                tick: inner.tick,
+            },
+        );
+        // the newest entry is kept even if it alone exceeds the byte limit
+        while inner.map.len() > self.max_entries
+            || (inner.bytes > self.max_bytes && inner.map.len() > 1)
+        {
+            let Some((_, oldest)) = inner.order.pop_first() else {
+                break;
+            };
+            if let Some(slot) = inner.map.remove(&oldest) {
+                inner.bytes -= slot.bytes;
+                inner.evictions += 1;
+            }
+        }
+    }
+
+    /// Update the value for `key` in place, keeping its position. Entries
+    /// left empty by `f` are removed.
+    pub fn update<F>(&self, key: &K, f: F)
+    where
+        F: FnOnce(&mut V) -> Option<usize>,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let Some(slot) = inner.map.get_mut(key) else {
+            return;
+        };
+        match f(&mut slot.value) {
+            Some(bytes) => {
+                inner.bytes = inner.bytes - slot.bytes + bytes;
+                slot.bytes = bytes;
+            }
+            None => {
+                let tick = slot.tick;
+                inner.bytes -= slot.bytes;
+                inner.map.remove(key);
+                inner.order.remove(&tick);
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        let slot = inner.map.remove(key)?;
+        inner.order.remove(&slot.tick);
+        inner.bytes -= slot.bytes;
+        Some(slot.value)
+    }
+
+    /// Keep only entries for which `f` returns true. Returns how many were removed.
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&self, mut f: F) -> usize {
+        self.retain_resize(|k, v, bytes| f(k, v).then_some(bytes))
+    }
+
+    /// Like `retain`, but `f` may modify values; it returns the new size of
+    /// entries to keep and `None` for entries to drop.
+    pub fn retain_mut<F: FnMut(&K, &mut V) -> Option<usize>>(&self, mut f: F) -> usize {
+        self.retain_resize(|k, v, _| f(k, v))
+    }
+
+    fn retain_resize<F: FnMut(&K, &mut V, usize) -> Option<usize>>(&self, mut f: F) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let before = inner.map.len();
+        let mut bytes = 0;
+        let order = &mut inner.order;
+        inner
+            .map
+            .retain(|k, slot| match f(k, &mut slot.value, slot.bytes) {
+                Some(size) => {
+                    slot.bytes = size;
+                    bytes += size;
+                    true
+                }
+                None => {
+                    order.remove(&slot.tick);
+                    false
+                }
+            });
+        inner.bytes = bytes;
+        before - inner.map.len()
+    }
+
+    /// Visit every entry without changing recency.
+    pub fn for_each<F: FnMut(&K, &V)>(&self, mut f: F) {
+        let inner = self.inner.lock().unwrap();
+        for (k, slot) in &inner.map {
+            f(k, &slot.value);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.map.clear();
+        inner.order.clear();
+        inner.bytes = 0;
+    }
+
+    /// Entries from least to most recently used.
+    pub fn entries(&self) -> Vec<(K, V)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .order
+            .values()
+            .filter_map(|k| inner.map.get(k).map(|s| (k.clone(), s.value.clone())))
+            .collect()
+    }
+
+    pub fn usage(&self) -> Usage {
+        let inner = self.inner.lock().unwrap();
+        Usage {
+            entries: inner.map.len() as u64,
+            bytes: inner.bytes as u64,
+            evictions: inner.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_by_count_and_bytes() {
+        let lru: Lru<u8, &str> = Lru::new(3, 100);
+        lru.insert(1, "a", 10);
+        lru.insert(2, "b", 10);
+        lru.insert(3, "c", 10);
+        assert_eq!(lru.get(&1), Some("a"));
+        lru.insert(4, "d", 10);
+        assert_eq!(lru.peek(&2), None);
+        assert_eq!(lru.entries(), vec![(3, "c"), (1, "a"), (4, "d")]);
+
+        lru.insert(5, "e", 85);
+        assert_eq!(lru.entries(), vec![(4, "d"), (5, "e")]);
+        assert_eq!(
+            lru.usage(),
+            Usage {
+                entries: 2,
+                bytes: 95,
+                evictions: 3
+            }
+        );
+
+        lru.update(&4, |_| None);
+        assert_eq!(lru.retain_mut(|_, v| (*v == "e").then_some(40)), 0);
+        assert_eq!(lru.usage().bytes, 40);
+        assert_eq!(lru.retain(|_, v| *v != "e"), 1);
+        assert_eq!(lru.usage().bytes, 0);
+        lru.insert(6, "f", 500);
+        assert_eq!(lru.peek(&6), Some("f"));
+    }
+
+    #[test]
+    fn upsert_merges_under_the_lock() {
+        let lru: std::sync::Arc<Lru<u8, Vec<u32>>> = std::sync::Arc::new(Lru::new(4, 1_000));
+        lru.insert(1, vec![0], 1);
+        let threads: Vec<_> = (1..=8)
+            .map(|n| {
+                let lru = lru.clone();
+                std::thread::spawn(move || {
+                    lru.upsert(1, |v| {
+                        v.push(n);
+                        v.len()
+                    })
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        let mut merged = lru.peek(&1).unwrap();
+        merged.sort();
+        assert_eq!(merged, (0..=8).collect::<Vec<_>>());
+        assert_eq!(lru.usage().bytes, 9);
+        lru.upsert(2, |v| {
+            v.push(7);
+            1
+        });
+        assert_eq!(lru.entries().last(), Some(&(2, vec![7])));
+    }
+}
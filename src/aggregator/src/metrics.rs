@@ -1,6 +1,6 @@
 use candid::CandidType;
 use core::sync::atomic::{AtomicU64, Ordering};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 static QUERY_COUNT: AtomicU64 = AtomicU64::new(0);
 static HEARTBEAT_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -9,6 +9,12 @@ static CLAIM_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
 static CLAIM_SUCCESSES: AtomicU64 = AtomicU64::new(0);
 static CYCLE_REFILL_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
 static CYCLE_REFILL_SUCCESSES: AtomicU64 = AtomicU64::new(0);
+// position index counters are not persisted across upgrades
+static POSITION_INDEX_HITS: AtomicU64 = AtomicU64::new(0);
+static POSITION_INDEX_MISSES: AtomicU64 = AtomicU64::new(0);
+static POSITION_SCANS: AtomicU64 = AtomicU64::new(0);
+static POSITION_POOLS_SCANNED: AtomicU64 = AtomicU64::new(0);
+static COALESCED_REQUESTS: AtomicU64 = AtomicU64::new(0);
 
 #[derive(CandidType, Serialize)]
 pub struct Metrics {
@@ -20,6 +26,23 @@ pub struct Metrics {
     pub claim_successes: u64,
     pub cycle_refill_attempts: u64,
     pub cycle_refill_successes: u64,
+    pub position_index_hits: u64,
+    pub position_index_misses: u64,
+    /// Position requests served and pools they queried; the ratio is the
+    /// typical number of pools a request touches
+    pub position_scans: u64,
+    pub position_pools_scanned: u64,
+    /// Calls answered by another caller's in-flight fetch
+    pub coalesced_requests: u64,
+    /// Size of the holdings and LP caches; byte counts are estimates
+    pub cache_entries: u64,
+    pub cache_bytes: u64,
+    pub cache_evictions: u64,
+    pub lp_cache_entries: u64,
+    pub lp_cache_bytes: u64,
+    pub lp_cache_evictions: u64,
+    /// Wasm heap size; zero off-chain
+    pub heap_bytes: u64,
 }
 
 pub fn inc_query() {
@@ -42,17 +65,47 @@ pub fn inc_cycle_refill_success() {
     CYCLE_REFILL_SUCCESSES.fetch_add(1, Ordering::Relaxed);
 }
 
+pub fn inc_position_index(hit: bool) {
+    let counter = if hit {
+        &POSITION_INDEX_HITS
+    } else {
+        &POSITION_INDEX_MISSES
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_position_scan(pools: usize) {
+    POSITION_SCANS.fetch_add(1, Ordering::Relaxed);
+    POSITION_POOLS_SCANNED.fetch_add(pools as u64, Ordering::Relaxed);
+}
+
+pub fn inc_coalesced() {
+    COALESCED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
 pub fn inc_heartbeat(now: u64) {
     HEARTBEAT_COUNT.fetch_add(1, Ordering::Relaxed);
     LAST_HEARTBEAT.store(now, Ordering::Relaxed);
 }
 
+#[cfg(target_arch = "wasm32")]
+fn heap_bytes() -> u64 {
+    core::arch::wasm32::memory_size(0) as u64 * 65_536
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn heap_bytes() -> u64 {
+    0
+}
+
 pub fn get() -> Metrics {
     let cycles = if cfg!(target_arch = "wasm32") {
         ic_cdk::api::canister_balance128()
     } else {
         0
     };
+    let cache = crate::cache::usage();
+    let lp_cache = crate::lp_cache::usage();
     Metrics {
         cycles,
         query_count: QUERY_COUNT.load(Ordering::Relaxed),
@@ -62,37 +115,62 @@ pub fn get() -> Metrics {
         claim_successes: CLAIM_SUCCESSES.load(Ordering::Relaxed),
         cycle_refill_attempts: CYCLE_REFILL_ATTEMPTS.load(Ordering::Relaxed),
         cycle_refill_successes: CYCLE_REFILL_SUCCESSES.load(Ordering::Relaxed),
+        position_index_hits: POSITION_INDEX_HITS.load(Ordering::Relaxed),
+        position_index_misses: POSITION_INDEX_MISSES.load(Ordering::Relaxed),
+        position_scans: POSITION_SCANS.load(Ordering::Relaxed),
+        position_pools_scanned: POSITION_POOLS_SCANNED.load(Ordering::Relaxed),
+        coalesced_requests: COALESCED_REQUESTS.load(Ordering::Relaxed),
+        cache_entries: cache.entries,
+        cache_bytes: cache.bytes,
+        cache_evictions: cache.evictions,
+        lp_cache_entries: lp_cache.entries,
+        lp_cache_bytes: lp_cache.bytes,
+        lp_cache_evictions: lp_cache.evictions,
+        heap_bytes: heap_bytes(),
     }
 }
 
+/// Persisted counters; the position, coalescing and cache counters restart
+/// from zero after an upgrade.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StableMetrics {
+    pub query_count: u64,
+    pub heartbeat_count: u64,
+    pub last_heartbeat: u64,
+    pub claim_attempts: u64,
+    pub claim_successes: u64,
+    pub cycle_refill_attempts: u64,
+    pub cycle_refill_successes: u64,
+}
+
 #[cfg(target_arch = "wasm32")]
-pub fn stable_save() -> (u64, u64, u64, u64, u64, u64, u64) {
-    (
-        QUERY_COUNT.load(Ordering::Relaxed),
-        HEARTBEAT_COUNT.load(Ordering::Relaxed),
-        LAST_HEARTBEAT.load(Ordering::Relaxed),
-        CLAIM_ATTEMPTS.load(Ordering::Relaxed),
-        CLAIM_SUCCESSES.load(Ordering::Relaxed),
-        CYCLE_REFILL_ATTEMPTS.load(Ordering::Relaxed),
-        CYCLE_REFILL_SUCCESSES.load(Ordering::Relaxed),
-    )
+pub fn stable_save() -> StableMetrics {
+    StableMetrics {
+        query_count: QUERY_COUNT.load(Ordering::Relaxed),
+        heartbeat_count: HEARTBEAT_COUNT.load(Ordering::Relaxed),
+        last_heartbeat: LAST_HEARTBEAT.load(Ordering::Relaxed),
+        claim_attempts: CLAIM_ATTEMPTS.load(Ordering::Relaxed),
+        claim_successes: CLAIM_SUCCESSES.load(Ordering::Relaxed),
+        cycle_refill_attempts: CYCLE_REFILL_ATTEMPTS.load(Ordering::Relaxed),
+        cycle_refill_successes: CYCLE_REFILL_SUCCESSES.load(Ordering::Relaxed),
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn stable_restore(data: (u64, u64, u64, u64, u64, u64, u64)) {
-    QUERY_COUNT.store(data.0, Ordering::Relaxed);
-    HEARTBEAT_COUNT.store(data.1, Ordering::Relaxed);
-    LAST_HEARTBEAT.store(data.2, Ordering::Relaxed);
-    CLAIM_ATTEMPTS.store(data.3, Ordering::Relaxed);
-    CLAIM_SUCCESSES.store(data.4, Ordering::Relaxed);
-    CYCLE_REFILL_ATTEMPTS.store(data.5, Ordering::Relaxed);
-    CYCLE_REFILL_SUCCESSES.store(data.6, Ordering::Relaxed);
+pub fn stable_restore(data: StableMetrics) {
+    QUERY_COUNT.store(data.query_count, Ordering::Relaxed);
+    HEARTBEAT_COUNT.store(data.heartbeat_count, Ordering::Relaxed);
+    LAST_HEARTBEAT.store(data.last_heartbeat, Ordering::Relaxed);
+    CLAIM_ATTEMPTS.store(data.claim_attempts, Ordering::Relaxed);
+    CLAIM_SUCCESSES.store(data.claim_successes, Ordering::Relaxed);
+    CYCLE_REFILL_ATTEMPTS.store(data.cycle_refill_attempts, Ordering::Relaxed);
+    CYCLE_REFILL_SUCCESSES.store(data.cycle_refill_successes, Ordering::Relaxed);
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn stable_save() -> (u64, u64, u64, u64, u64, u64, u64) {
-    (0, 0, 0, 0, 0, 0, 0)
+pub fn stable_save() -> StableMetrics {
+    StableMetrics::default()
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn stable_restore(_: (u64, u64, u64, u64, u64, u64, u64)) {}
+pub fn stable_restore(_: StableMetrics) {}
@@ -0,0 +1,470 @@
+use crate::dex::call;
+use crate::error::FetchError;
+use crate::lru::Lru;
+use crate::transactions::Value;
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use num_traits::ToPrimitive;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha224};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+// Collections to scan are seeded from the `[nfts]` (ICRC-7) and `[ext_nfts]`
+// (EXT) sections of `ledgers.toml`, managed by controllers afterwards and
+// kept across upgrades. `get_nfts` pages through them in collection id
+// order. ICRC-7 collections page by token id themselves; EXT collections key
+// holdings by account identifier and return them all at once, so their
+// token indices are sorted and cut here.
+
+/// NFTs per page when the caller does not ask for fewer
+pub const DEFAULT_PAGE: u32 = 50;
+/// Upper bound on a page, also the largest `take` sent to a collection
+pub const MAX_PAGE: u32 = 100;
+
+/// Metadata keys read for a token's name and image, most specific first
+const NAME_KEYS: [&str; 2] = ["icrc7:name", "name"];
+const IMAGE_KEYS: [&str; 4] = ["icrc7:image", "icrc7:logo", "image", "logo"];
+
+const COLLECTION_NAME_CACHE: usize = 1_000;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, CandidType, Serialize, Deserialize,
+)]
+pub enum NftStandard {
+    Icrc7,
+    Ext,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct NftCollection {
+    pub name: String,
+    pub id: Principal,
+    pub standard: NftStandard,
+}
+
+/// A collection as saved across upgrades; `standard` is `None` for
+/// collections saved before EXT support, which were all ICRC-7.
+#[derive(CandidType, Deserialize)]
+pub struct StableEntry {
+    pub name: String,
+    pub id: Principal,
+    pub standard: Option<NftStandard>,
+}
+
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct Nft {
+    pub collection: Principal,
+    pub collection_name: String,
+    /// ICRC-7 token id, or the token index within an EXT collection
+    pub token_id: Nat,
+    pub name: Option<String>,
+    pub image: Option<String>,
+}
+
+/// Position after the last NFT of a page
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct NftCursor {
+    pub collection: Principal,
+    pub token_id: Nat,
+}
+
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct NftPage {
+    pub nfts: Vec<Nft>,
+    /// `None` once every collection has been read
+    pub next: Option<NftCursor>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<ByteBuf>,
+}
+
+/// A marketplace listing attached to an EXT token
+#[derive(CandidType, Deserialize)]
+struct Listing {
+    locked: Option<Int>,
+    seller: Principal,
+    price: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+enum CommonError {
+    InvalidToken(String),
+    Other(String),
+}
+
+#[derive(CandidType, Deserialize)]
+enum TokensExtResult {
+    #[serde(rename = "ok")]
+    Ok(Vec<(u32, Option<Listing>, Option<ByteBuf>)>),
+    #[serde(rename = "err")]
+    Err(CommonError),
+}
+
+static COLLECTIONS: Lazy<RwLock<BTreeMap<String, (Principal, NftStandard)>>> = Lazy::new(|| {
+    let text = crate::config::seed_text().unwrap_or_default();
+    let mut seeded = BTreeMap::new();
+    for (section, standard) in [("nfts", NftStandard::Icrc7), ("ext_nfts", NftStandard::Ext)] {
+        for (name, id) in crate::config::parse_principals(&text, section) {
+            seeded.insert(name, (id, standard));
+        }
+    }
+    RwLock::new(seeded)
+});
+
+static COLLECTION_NAMES: Lazy<Lru<Principal, String>> =
+    Lazy::new(|| Lru::new(COLLECTION_NAME_CACHE, usize::MAX));
+
+pub fn collections() -> Vec<NftCollection> {
+    COLLECTIONS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, (id, standard))| NftCollection {
+            name: name.clone(),
+            id: *id,
+            standard: *standard,
+        })
+        .collect()
+}
+
+/// Insert or replace a collection.
+pub fn add_collection(name: String, id: Principal, standard: NftStandard) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("empty name".into());
+    }
+    tracing::info!(%name, %id, ?standard, "nft collection added");
+    COLLECTIONS.write().unwrap().insert(name, (id, standard));
+    Ok(())
+}
+
+pub fn remove_collection(name: &str) -> Result<(), String> {
+    COLLECTIONS
+        .write()
+        .unwrap()
+        .remove(name)
+        .map(|_| tracing::info!(%name, "nft collection removed"))
+        .ok_or_else(|| format!("unknown nft collection {name}"))
+}
+
+pub fn stable_save() -> Vec<StableEntry> {
+    collections()
+        .into_iter()
+        .map(|c| StableEntry {
+            name: c.name,
+            id: c.id,
+            standard: Some(c.standard),
+        })
+        .collect()
+}
+
+pub fn stable_restore(saved: Vec<StableEntry>) {
+    *COLLECTIONS.write().unwrap() = saved
+        .into_iter()
+        .map(|e| (e.name, (e.id, e.standard.unwrap_or(NftStandard::Icrc7))))
+        .collect();
+}
+
+/// Ledger account identifier of `owner`'s default subaccount, as hex
+fn account_identifier(owner: Principal) -> String {
+    let mut hash = Sha224::new();
+    hash.update(b"\x0Aaccount-id");
+    hash.update(owner.as_slice());
+    hash.update([0u8; 32]);
+    let hash = hash.finalize();
+    let mut out = crc32fast::hash(&hash).to_be_bytes().to_vec();
+    out.extend_from_slice(&hash);
+    hex::encode(out)
+}
+
+/// EXT token identifier of token `index` in `collection`; `None` when the
+/// collection id is too long to fit, as only canister ids do
+fn ext_token_id(collection: Principal, index: u32) -> Option<String> {
+    let mut bytes = b"\x0Atid".to_vec();
+    bytes.extend_from_slice(collection.as_slice());
+    bytes.extend_from_slice(&index.to_be_bytes());
+    Principal::try_from_slice(&bytes).ok().map(|p| p.to_text())
+}
+
+/// Up to `take` EXT tokens of `owner` with an index above `prev`. EXT has no
+/// token metadata, so the image is the collection's own asset URL.
+async fn ext_page(
+    name: &str,
+    id: Principal,
+    owner: Principal,
+    prev: Option<Nat>,
+    take: u32,
+) -> Result<Vec<Nft>, FetchError> {
+    let res: TokensExtResult = call(id, "tokens_ext", (account_identifier(owner),), false).await?;
+    // EXT answers an owner without tokens with an error
+    let TokensExtResult::Ok(tokens) = res else {
+        return Ok(Vec::new());
+    };
+    let after = prev.map(|p| p.0.to_u64().unwrap_or(u64::MAX));
+    let mut indices: Vec<u32> = tokens
+        .into_iter()
+        .map(|(index, _, _)| index)
+        .filter(|index| after.is_none_or(|a| u64::from(*index) > a))
+        .collect();
+    indices.sort_unstable();
+    Ok(indices
+        .into_iter()
+        .take(take as usize)
+        .map(|index| Nft {
+            collection: id,
+            collection_name: name.to_string(),
+            token_id: Nat::from(index),
+            name: Some(format!("{name} #{index}")),
+            image: ext_token_id(id, index)
+                .map(|tid| format!("https://{id}.raw.icp0.io/?tokenid={tid}")),
+        })
+        .collect())
+}
+
+fn text_field(meta: &[(String, Value)], keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| {
+        meta.iter().find_map(|(k, v)| match v {
+            Value::Text(t) if k == key => Some(t.clone()),
+            _ => None,
+        })
+    })
+}
+
+async fn collection_name(id: Principal, fallback: &str) -> String {
+    if let Some(name) = COLLECTION_NAMES.get(&id) {
+        return name;
+    }
+    let meta: Result<Vec<(String, Value)>, FetchError> =
+        call(id, "icrc7_collection_metadata", (), false).await;
+    match meta.ok().and_then(|m| text_field(&m, &NAME_KEYS)) {
+        Some(name) => {
+            COLLECTION_NAMES.insert(id, name.clone(), name.len());
+            name
+        }
+        None => fallback.to_string(),
+    }
+}
+
+/// Up to `take` NFTs of `owner` in one ICRC-7 collection, after token `prev`.
+async fn icrc7_page(
+    name: &str,
+    id: Principal,
+    owner: Principal,
+    prev: Option<Nat>,
+    take: u32,
+) -> Result<Vec<Nft>, FetchError> {
+    let account = Account {
+        owner,
+        subaccount: None,
+    };
+    let ids: Vec<Nat> = call(
+        id,
+        "icrc7_tokens_of",
+        (account, prev, Some(Nat::from(take))),
+        false,
+    )
+    .await?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (collection_name, meta) = futures::join!(
+        collection_name(id, name),
+        call::<_, Vec<Option<Vec<(String, Value)>>>>(
+            id,
+            "icrc7_token_metadata",
+            (ids.clone(),),
+            false
+        )
+    );
+    // a collection without metadata still lists its tokens
+    let meta = meta.unwrap_or_default();
+    Ok(ids
+        .into_iter()
+        .take(take as usize)
+        .enumerate()
+        .map(|(i, token_id)| {
+            let m = meta.get(i).cloned().flatten().unwrap_or_default();
+            Nft {
+                collection: id,
+                collection_name: collection_name.clone(),
+                token_id,
+                name: text_field(&m, &NAME_KEYS),
+                image: text_field(&m, &IMAGE_KEYS),
+            }
+        })
+        .collect())
+}
+
+/// Up to `take` NFTs of one collection after `prev`, asking for more pages
+/// until one comes back empty. Collections may return fewer than asked for
+/// (ICRC-7 caps `take` at `icrc7_max_take_value`), so a short page does not
+/// mean the collection is done.
+async fn collection_nfts<F, Fut>(
+    mut prev: Option<Nat>,
+    take: u32,
+    mut page: F,
+) -> Result<Vec<Nft>, FetchError>
+where
+    F: FnMut(Option<Nat>, u32) -> Fut,
+    Fut: std::future::Future<Output = Result<Vec<Nft>, FetchError>>,
+{
+    let mut out: Vec<Nft> = Vec::new();
+    while (out.len() as u32) < take {
+        let next = page(prev.clone(), take - out.len() as u32).await?;
+        let Some(last) = next.last() else {
+            break;
+        };
+        prev = Some(last.token_id.clone());
+        out.extend(next);
+    }
+    Ok(out)
+}
+
+/// One page of `owner`'s NFTs across every collection, starting after
+/// `after`. Collections that cannot be reached are skipped.
+pub async fn get_nfts(owner: Principal, after: Option<NftCursor>, limit: Option<u32>) -> NftPage {
+    let limit = limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let mut by_id: Vec<(Principal, String, NftStandard)> = collections()
+        .into_iter()
+        .map(|c| (c.id, c.name, c.standard))
+        .collect();
+    by_id.sort();
+    by_id.dedup_by_key(|(id, _, _)| *id);
+    let mut nfts: Vec<Nft> = Vec::new();
+    for (id, name, standard) in by_id {
+        let prev = match &after {
+            Some(c) if id < c.collection => continue,
+            Some(c) if id == c.collection => Some(c.token_id.clone()),
+            _ => None,
+        };
+        let take = limit - nfts.len() as u32;
+        let page = match standard {
+            NftStandard::Icrc7 => {
+                collection_nfts(prev, take, |prev, take| {
+                    icrc7_page(&name, id, owner, prev, take)
+                })
+                .await
+            }
+            // `tokens_ext` returns every token at once
+            NftStandard::Ext => ext_page(&name, id, owner, prev, take).await,
+        };
+        match page {
+            Ok(page) => nfts.extend(page),
+            Err(e) => tracing::warn!(collection = %id, "nft fetch failed: {e}"),
+        }
+        if nfts.len() as u32 >= limit {
+            let last = nfts.last().expect("page is full");
+            let next = NftCursor {
+                collection: last.collection,
+                token_id: last.token_id.clone(),
+            };
+            return NftPage {
+                nfts,
+                next: Some(next),
+            };
+        }
+    }
+    NftPage { nfts, next: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_fields_prefer_standard_keys() {
+        let meta = vec![
+            ("name".to_string(), Value::Text("plain".into())),
+            ("icrc7:name".to_string(), Value::Text("Punk #7".into())),
+            ("image".to_string(), Value::Nat(Nat::from(1u64))),
+            ("logo".to_string(), Value::Text("https://x/7.png".into())),
+        ];
+        assert_eq!(text_field(&meta, &NAME_KEYS).as_deref(), Some("Punk #7"));
+        assert_eq!(
+            text_field(&meta, &IMAGE_KEYS).as_deref(),
+            Some("https://x/7.png")
+        );
+        assert!(text_field(&[], &NAME_KEYS).is_none());
+    }
+
+    #[tokio::test]
+    async fn capped_collections_are_paged_until_empty() {
+        // a collection of tokens 1 to 5 that returns at most 2 per call
+        let calls = std::cell::Cell::new(0);
+        let capped = |prev: Option<Nat>, take: u32| {
+            calls.set(calls.get() + 1);
+            let start = prev.map_or(1, |p| p.0.to_u64().unwrap() + 1);
+            let page = (start..=5)
+                .take(take.min(2) as usize)
+                .map(|id| Nft {
+                    collection: Principal::anonymous(),
+                    collection_name: "Capped".into(),
+                    token_id: Nat::from(id),
+                    name: None,
+                    image: None,
+                })
+                .collect();
+            async { Ok(page) }
+        };
+        let ids = |nfts: Vec<Nft>| nfts.into_iter().map(|n| n.token_id).collect::<Vec<_>>();
+        let first = collection_nfts(None, 3, capped).await.unwrap();
+        assert_eq!(ids(first), [1u64, 2, 3].map(Nat::from));
+        assert_eq!(calls.get(), 2);
+        let rest = collection_nfts(Some(Nat::from(3u64)), 10, capped)
+            .await
+            .unwrap();
+        assert_eq!(ids(rest), [4u64, 5].map(Nat::from));
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn collections_survive_upgrade() {
+        let id = Principal::from_slice(&[7]);
+        add_collection("Punks".into(), id, NftStandard::Ext).unwrap();
+        assert!(add_collection(" ".into(), id, NftStandard::Icrc7).is_err());
+        let mut saved = stable_save();
+        remove_collection("Punks").unwrap();
+        assert!(remove_collection("Punks").is_err());
+        // entries saved before EXT support have no standard
+        saved.push(StableEntry {
+            name: "Old".into(),
+            id,
+            standard: None,
+        });
+        stable_restore(saved);
+        let restored = collections();
+        assert!(restored.contains(&NftCollection {
+            name: "Punks".into(),
+            id,
+            standard: NftStandard::Ext,
+        }));
+        assert!(restored.contains(&NftCollection {
+            name: "Old".into(),
+            id,
+            standard: NftStandard::Icrc7,
+        }));
+        remove_collection("Punks").unwrap();
+        remove_collection("Old").unwrap();
+    }
+
+    #[test]
+    fn ext_identifiers_match_ledger_encoding() {
+        assert_eq!(
+            account_identifier(Principal::anonymous()),
+            "1c7a48ba6a562aa9eaa2481a9049cdf0433b9738c992d698c31d8abf89cadc79"
+        );
+        let collection = Principal::from_text("bxdf4-baaaa-aaaah-qaruq-cai").unwrap();
+        let tid = Principal::from_text(ext_token_id(collection, 42).unwrap()).unwrap();
+        let bytes = tid.as_slice();
+        assert_eq!(&bytes[..4], b"\x0Atid");
+        assert_eq!(&bytes[4..bytes.len() - 4], collection.as_slice());
+        assert_eq!(&bytes[bytes.len() - 4..], &42u32.to_be_bytes());
+        // self-authenticating ids leave no room for the prefix and index
+        let user = Principal::self_authenticating([1; 32]);
+        assert_eq!(ext_token_id(user, 42), None);
+    }
+}
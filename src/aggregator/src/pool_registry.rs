@@ -15,6 +15,13 @@ pub struct PoolMeta {
     pub decimals_b: u8,
     pub image_a: Option<String>,
     pub image_b: Option<String>,
+    /// `manual` for `data/pools.toml` entries, otherwise the DEX that reported the pool
+    #[serde(default = "manual_source")]
+    pub source: String,
+}
+
+fn manual_source() -> String {
+    "manual".into()
 }
 
 #[derive(Deserialize)]
@@ -22,8 +29,19 @@ struct PoolsFile {
     pool: Vec<PoolMeta>,
 }
 
+static POOL_DISCOVERY_INTERVAL_SECS: Lazy<u64> = Lazy::new(|| {
+    option_env!("POOL_DISCOVERY_INTERVAL_SECS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(3600)
+        .max(60)
+});
+
+// Merged view served to callers; manual entries override discovered ones.
 static REGISTRY: Lazy<RwLock<HashMap<String, PoolMeta>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
+static MANUAL: Lazy<RwLock<HashMap<String, PoolMeta>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+static DISCOVERED: Lazy<RwLock<HashMap<String, PoolMeta>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
 
 #[cfg(not(target_arch = "wasm32"))]
 static WATCHER: OnceCell<notify::RecommendedWatcher> = OnceCell::new();
@@ -91,14 +109,182 @@ fn load_content(content: &str) {
     if let Ok(pf) = toml::from_str::<PoolsFile>(content) {
         let count = pf.pool.len();
         let mut map = HashMap::with_capacity(count);
-        for p in pf.pool.into_iter() {
+        for mut p in pf.pool.into_iter() {
+            p.source = manual_source();
             map.insert(p.id.clone(), p);
         }
-        *REGISTRY.write().unwrap() = map;
+        *MANUAL.write().unwrap() = map;
+        rebuild();
         tracing::info!(count, "pool registry loaded");
     }
 }
 
+fn rebuild() {
+    let mut map = DISCOVERED.read().unwrap().clone();
+    map.extend(
+        MANUAL
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone())),
+    );
+    *REGISTRY.write().unwrap() = map;
+}
+
+/// Resolve a token through its ledger's `icrc1_metadata`, falling back to the
+/// raw address and pool-reported decimals when the ledger is unreachable.
+async fn resolve_token(
+    known: &mut HashMap<String, Option<crate::utils::TokenInfo>>,
+    address: &str,
+    decimals: Option<u8>,
+) -> Option<crate::utils::TokenInfo> {
+    if !known.contains_key(address) {
+        let info = match candid::Principal::from_text(address) {
+            Ok(cid) => crate::utils::token_info(cid).await,
+            Err(_) => None,
+        };
+        known.insert(address.to_string(), info);
+    }
+    known[address].clone().or_else(|| {
+        decimals.map(|decimals| crate::utils::TokenInfo {
+            symbol: address.to_string(),
+            decimals,
+            logo: None,
+        })
+    })
+}
+
+/// Pull pools from every DEX factory and merge them into the registry.
+/// Sources whose factory cannot be reached keep their previous pools.
+pub async fn discover() {
+    use crate::dex::{dex_icpswap::IcpswapAdapter, dex_sonic::SonicAdapter, DexAdapter};
+    use std::collections::HashSet;
+    let adapters: Vec<Box<dyn DexAdapter>> = vec![Box::new(IcpswapAdapter), Box::new(SonicAdapter)];
+    let mut known = HashMap::new();
+    let mut found = HashMap::new();
+    let mut refreshed = HashSet::new();
+    for adapter in adapters {
+        let pools = match adapter.list_pools().await {
+            Ok(p) => p,
+            Err(crate::error::FetchError::InvalidConfig(_)) => continue,
+            Err(e) => {
+                tracing::warn!("pool discovery failed: {e}");
+                continue;
+            }
+        };
+        for pool in pools {
+            refreshed.insert(pool.source.clone());
+            let a = resolve_token(&mut known, &pool.token_a, pool.decimals_a).await;
+            let b = resolve_token(&mut known, &pool.token_b, pool.decimals_b).await;
+            let (Some(a), Some(b)) = (a, b) else {
+                tracing::debug!(pool = %pool.id, "skipping pool with unresolved tokens");
+                continue;
+            };
+            found.insert(
+                pool.id.clone(),
+                PoolMeta {
+                    id: pool.id,
+                    token_a: a.symbol,
+                    token_b: b.symbol,
+                    decimals_a: a.decimals,
+                    decimals_b: b.decimals,
+                    image_a: a.logo,
+                    image_b: b.logo,
+                    source: pool.source,
+                },
+            );
+        }
+    }
+    {
+        let mut discovered = DISCOVERED.write().unwrap();
+        for (id, pool) in discovered.drain() {
+            if !refreshed.contains(&pool.source) {
+                found.entry(id).or_insert(pool);
+            }
+        }
+        tracing::info!(count = found.len(), "pools discovered");
+        *discovered = found;
+    }
+    rebuild();
+    // give newly found pools stats without waiting for the next stats tick
+    crate::pool_stats::refresh().await;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn schedule_discovery() {
+    use std::time::Duration;
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(discover()));
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(*POOL_DISCOVERY_INTERVAL_SECS), || {
+        ic_cdk::spawn(discover());
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn schedule_discovery() {
+    use std::time::Duration;
+    tokio::spawn(async {
+        let mut timer = tokio::time::interval(Duration::from_secs(*POOL_DISCOVERY_INTERVAL_SECS));
+        loop {
+            timer.tick().await;
+            discover().await;
+        }
+    });
+}
+
+/// Report unknown keys, missing or mistyped fields and duplicate ids in a pools file.
+pub fn validate(content: &str) -> Vec<String> {
+    const REQUIRED: [&str; 5] = ["id", "token_a", "token_b", "decimals_a", "decimals_b"];
+    const OPTIONAL: [&str; 2] = ["image_a", "image_b"];
+    let value: toml::Value = match toml::from_str(content) {
+        Ok(v) => v,
+        Err(e) => return vec![format!("parse error: {e}")],
+    };
+    let mut problems = Vec::new();
+    if let Some(root) = value.as_table() {
+        for key in root.keys().filter(|k| k.as_str() != "pool") {
+            problems.push(format!("unknown section [{key}]"));
+        }
+    }
+    let Some(pools) = value.get("pool").and_then(|p| p.as_array()) else {
+        problems.push("missing [[pool]] entries".into());
+        return problems;
+    };
+    let mut seen = std::collections::HashSet::with_capacity(pools.len());
+    for (i, pool) in pools.iter().enumerate() {
+        let Some(table) = pool.as_table() else {
+            problems.push(format!("pool #{i}: expected a table"));
+            continue;
+        };
+        let label = table
+            .get("id")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("#{i}"));
+        for key in table.keys() {
+            if !REQUIRED.contains(&key.as_str()) && !OPTIONAL.contains(&key.as_str()) {
+                problems.push(format!("pool {label}: unknown field {key}"));
+            }
+        }
+        for key in REQUIRED {
+            if !table.contains_key(key) {
+                problems.push(format!("pool {label}: missing field {key}"));
+            }
+        }
+        for key in ["decimals_a", "decimals_b"] {
+            if let Some(v) = table.get(key) {
+                match v.as_integer() {
+                    Some(d) if (0..=crate::utils::MAX_DECIMALS as i64).contains(&d) => {}
+                    _ => problems.push(format!("pool {label}: {key} out of range")),
+                }
+            }
+        }
+        if table.contains_key("id") && !seen.insert(label.clone()) {
+            problems.push(format!("pool {label}: duplicate id"));
+        }
+    }
+    problems
+}
+
 #[cfg(target_arch = "wasm32")]
 pub fn schedule_refresh() {
     use std::time::Duration;
@@ -119,7 +305,85 @@ pub fn schedule_refresh() {
     });
 }
 
-pub fn graphql(_query: String) -> String {
-    let data = list();
-    serde_json::json!({"data": {"pools": data}}).to_string()
+pub fn graphql(query: String) -> String {
+    crate::graphql::execute(&query, &list(), &crate::pool_stats::snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discovered(id: &str, token_a: &str) -> PoolMeta {
+        PoolMeta {
+            id: id.into(),
+            token_a: token_a.into(),
+            token_b: "ICP".into(),
+            decimals_a: 8,
+            decimals_b: 8,
+            image_a: None,
+            image_b: None,
+            source: "ICPSwap".into(),
+        }
+    }
+
+    #[test]
+    fn manual_entries_override_discovered_pools() {
+        *DISCOVERED.write().unwrap() = [
+            ("pool1".to_string(), discovered("pool1", "FOUND")),
+            ("other".to_string(), discovered("other", "ckBTC")),
+        ]
+        .into_iter()
+        .collect();
+        load_content(include_str!("../../../data/pools.toml"));
+        let pools = list();
+        let pool1 = pools.iter().find(|p| p.id == "pool1").unwrap();
+        assert_eq!(pool1.token_a, "ICP");
+        assert_eq!(pool1.source, "manual");
+        let other = pools.iter().find(|p| p.id == "other").unwrap();
+        assert_eq!(other.source, "ICPSwap");
+        assert_eq!(pools.len(), 3);
+        DISCOVERED.write().unwrap().clear();
+        rebuild();
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn unresolved_tokens_fall_back_to_pool_decimals() {
+        let mut known = HashMap::new();
+        let info = resolve_token(&mut known, "mock0", Some(6)).await.unwrap();
+        assert_eq!(info.symbol, "mock0");
+        assert_eq!(info.decimals, 6);
+        assert!(resolve_token(&mut known, "mock0", None).await.is_none());
+        assert_eq!(known.len(), 1);
+    }
+
+    #[test]
+    fn validate_flags_bad_pools() {
+        let problems = validate(
+            r#"
+[[pool]]
+id = "p1"
+token_a = "ICP"
+token_b = "XTC"
+decimals_a = 8
+decimals_b = 40
+colour = "red"
+
+[[pool]]
+id = "p1"
+token_a = "ICP"
+decimals_a = 8
+decimals_b = 8
+"#,
+        );
+        assert_eq!(
+            problems,
+            vec![
+                "pool p1: unknown field colour",
+                "pool p1: decimals_b out of range",
+                "pool p1: missing field token_b",
+                "pool p1: duplicate id",
+            ]
+        );
+        assert!(validate(include_str!("../../../data/pools.toml")).is_empty());
+    }
 }
@@ -0,0 +1,260 @@
+use crate::dex::PoolReserves;
+use crate::pool_registry::PoolMeta;
+use candid::{CandidType, Nat};
+use num_traits::ToPrimitive;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+/// Analytics for a registry pool. Values are in whole tokens; anything
+/// denominated in the quote token is `None` when the pool cannot be priced.
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct PoolStats {
+    pub pool_id: String,
+    pub reserve_a: f64,
+    pub reserve_b: f64,
+    /// Symbol that `tvl` and `volume_24h` are expressed in
+    pub quote: String,
+    pub tvl: Option<f64>,
+    pub volume_24h: Option<f64>,
+    /// Yearly LP fee income relative to TVL, as a fraction (0.12 = 12%)
+    pub fee_apr: Option<f64>,
+    pub updated_at: u64,
+}
+
+static QUOTE_TOKEN: Lazy<String> =
+    Lazy::new(|| option_env!("POOL_QUOTE_TOKEN").unwrap_or("ICP").to_string());
+
+static POOL_STATS_INTERVAL_SECS: Lazy<u64> = Lazy::new(|| {
+    option_env!("POOL_STATS_INTERVAL_SECS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(900)
+        .max(60)
+});
+
+static STATS: Lazy<RwLock<HashMap<String, PoolStats>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+pub fn list() -> Vec<PoolStats> {
+    let mut out: Vec<PoolStats> = STATS.read().unwrap().values().cloned().collect();
+    out.sort_by(|a, b| a.pool_id.cmp(&b.pool_id));
+    out
+}
+
+pub fn snapshot() -> HashMap<String, PoolStats> {
+    STATS.read().unwrap().clone()
+}
+
+fn scale(n: &Nat, decimals: u8) -> f64 {
+    n.0.to_f64().unwrap_or(0.0) / 10f64.powi(decimals as i32)
+}
+
+/// Price every reachable token in units of `quote` by walking pools that
+/// pair an unpriced token with a priced one.
+fn derive_prices(pools: &[(&PoolMeta, f64, f64)], quote: &str) -> HashMap<String, f64> {
+    let mut prices = HashMap::from([(quote.to_string(), 1.0)]);
+    loop {
+        let mut changed = false;
+        for (meta, ra, rb) in pools {
+            if *ra <= 0.0 || *rb <= 0.0 {
+                continue;
+            }
+            match (prices.get(&meta.token_a), prices.get(&meta.token_b)) {
+                (Some(pa), None) => {
+                    prices.insert(meta.token_b.clone(), ra * pa / rb);
+                    changed = true;
+                }
+                (None, Some(pb)) => {
+                    prices.insert(meta.token_a.clone(), rb * pb / ra);
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+        if !changed {
+            return prices;
+        }
+    }
+}
+
+/// Join DEX reserves with registry metadata; reserves for unknown pools are ignored.
+fn compute(
+    registry: &[PoolMeta],
+    reserves: &[PoolReserves],
+    quote: &str,
+    now: u64,
+) -> Vec<PoolStats> {
+    let by_id: HashMap<&str, &PoolMeta> = registry.iter().map(|p| (p.id.as_str(), p)).collect();
+    let mut joined: Vec<(&PoolMeta, f64, f64, &PoolReserves)> = reserves
+        .iter()
+        .filter_map(|r| {
+            let meta = by_id.get(r.id.as_str())?;
+            Some((
+                *meta,
+                scale(&r.reserve_a, meta.decimals_a),
+                scale(&r.reserve_b, meta.decimals_b),
+                r,
+            ))
+        })
+        .collect();
+    joined.sort_by(|a, b| a.0.id.cmp(&b.0.id));
+    let priced: Vec<(&PoolMeta, f64, f64)> = joined.iter().map(|j| (j.0, j.1, j.2)).collect();
+    let prices = derive_prices(&priced, quote);
+    joined
+        .into_iter()
+        .map(|(meta, ra, rb, r)| {
+            let pa = prices.get(&meta.token_a);
+            let pb = prices.get(&meta.token_b);
+            let (tvl, volume_24h) = match (pa, pb) {
+                (Some(pa), Some(pb)) => {
+                    let volume = match (&r.volume_a, &r.volume_b) {
+                        (Some(va), Some(vb)) => {
+                            Some(scale(va, meta.decimals_a) * pa + scale(vb, meta.decimals_b) * pb)
+                        }
+                        _ => None,
+                    };
+                    (Some(ra * pa + rb * pb), volume)
+                }
+                _ => (None, None),
+            };
+            let fee_apr = match (tvl, volume_24h, r.fee_ppm) {
+                (Some(tvl), Some(volume), Some(fee)) if tvl > 0.0 => {
+                    Some(volume * fee as f64 / 1_000_000.0 * 365.0 / tvl)
+                }
+                _ => None,
+            };
+            PoolStats {
+                pool_id: meta.id.clone(),
+                reserve_a: ra,
+                reserve_b: rb,
+                quote: quote.to_string(),
+                tvl,
+                volume_24h,
+                fee_apr,
+                updated_at: now,
+            }
+        })
+        .collect()
+}
+
+/// Fetch reserves from every DEX and recompute stats for registry pools.
+/// Pools of a DEX that cannot be reached keep their previous stats.
+pub async fn refresh() {
+    use crate::dex::{dex_icpswap::IcpswapAdapter, dex_sonic::SonicAdapter, DexAdapter};
+    let adapters: Vec<(&str, Box<dyn DexAdapter>)> = vec![
+        ("ICPSwap", Box::new(IcpswapAdapter)),
+        ("Sonic", Box::new(SonicAdapter)),
+    ];
+    let mut reserves = Vec::new();
+    let mut failed = HashSet::new();
+    for (source, adapter) in adapters {
+        match adapter.pool_reserves().await {
+            Ok(r) => reserves.extend(r),
+            Err(crate::error::FetchError::InvalidConfig(_)) => {}
+            Err(e) => {
+                tracing::warn!(source, "pool reserves fetch failed: {e}");
+                failed.insert(source);
+            }
+        }
+    }
+    let registry = crate::pool_registry::list();
+    let fresh = compute(&registry, &reserves, &QUOTE_TOKEN, crate::utils::now());
+    let mut stats = STATS.write().unwrap();
+    let kept: HashMap<String, PoolStats> = registry
+        .iter()
+        .filter(|p| failed.contains(p.source.as_str()))
+        .filter_map(|p| stats.remove(&p.id).map(|s| (p.id.clone(), s)))
+        .collect();
+    tracing::info!(
+        count = fresh.len(),
+        kept = kept.len(),
+        "pool stats refreshed"
+    );
+    *stats = kept;
+    stats.extend(fresh.into_iter().map(|s| (s.pool_id.clone(), s)));
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn schedule_refresh() {
+    use std::time::Duration;
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(*POOL_STATS_INTERVAL_SECS), || {
+        ic_cdk::spawn(refresh());
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn schedule_refresh() {
+    use std::time::Duration;
+    tokio::spawn(async {
+        let mut timer = tokio::time::interval(Duration::from_secs(*POOL_STATS_INTERVAL_SECS));
+        loop {
+            timer.tick().await;
+            refresh().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(id: &str, a: &str, b: &str) -> PoolMeta {
+        PoolMeta {
+            id: id.into(),
+            token_a: a.into(),
+            token_b: b.into(),
+            decimals_a: 8,
+            decimals_b: 8,
+            image_a: None,
+            image_b: None,
+            source: "ICPSwap".into(),
+        }
+    }
+
+    fn reserves(id: &str, a: u64, b: u64, volume: Option<(u64, u64)>) -> PoolReserves {
+        PoolReserves {
+            id: id.into(),
+            reserve_a: Nat::from(a * 100_000_000),
+            reserve_b: Nat::from(b * 100_000_000),
+            volume_a: volume.map(|v| Nat::from(v.0 * 100_000_000)),
+            volume_b: volume.map(|v| Nat::from(v.1 * 100_000_000)),
+            fee_ppm: Some(3000),
+        }
+    }
+
+    #[test]
+    fn prices_propagate_through_intermediate_pools() {
+        let registry = vec![
+            pool("p1", "ICP", "XTC"),
+            pool("p2", "XTC", "ckBTC"),
+            pool("p3", "FOO", "BAR"),
+        ];
+        let stats = compute(
+            &registry,
+            &[
+                reserves("p2", 100, 1, None),
+                reserves("p1", 1_000, 2_000, Some((10, 20))),
+                reserves("p3", 5, 5, Some((1, 1))),
+                reserves("unknown", 1, 1, None),
+            ],
+            "ICP",
+            7,
+        );
+        assert_eq!(stats.len(), 3);
+        let p1 = &stats[0];
+        assert_eq!(p1.tvl, Some(2_000.0));
+        assert_eq!(p1.volume_24h, Some(20.0));
+        let apr = p1.fee_apr.unwrap();
+        assert!((apr - 20.0 * 0.003 * 365.0 / 2_000.0).abs() < 1e-9);
+        // XTC is worth 0.5 ICP, so 100 XTC + 1 ckBTC at 50 ICP each
+        let p2 = &stats[1];
+        assert_eq!(p2.tvl, Some(100.0));
+        assert_eq!(p2.volume_24h, None);
+        assert_eq!(p2.fee_apr, None);
+        let p3 = &stats[2];
+        assert_eq!((p3.reserve_a, p3.reserve_b), (5.0, 5.0));
+        assert_eq!(p3.tvl, None);
+        assert_eq!(p3.fee_apr, None);
+        assert_eq!(p3.updated_at, 7);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::stable::{self, Memory};
+use candid::{CandidType, Principal};
+use ic_stable_structures::storable::{Blob, Bound};
+use ic_stable_structures::{StableBTreeMap, Storable};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+// principal -> pool canisters where the last scan found positions, kept in a
+// stable map so the index survives upgrades without a full rescan. Adapters
+// only query these pools on the hot path; a slow background rescan picks up
+// positions opened elsewhere and entries older than the max age are ignored.
+
+#[derive(CandidType, Deserialize)]
+struct Entry {
+    pools: Vec<Principal>,
+    scanned_at: u64,
+}
+
+impl Storable for Entry {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        stable::to_bytes(self)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        stable::from_bytes(bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+type Key = Blob<29>;
+
+static INDEX: Lazy<Mutex<StableBTreeMap<Key, Entry, Memory>>> =
+    Lazy::new(|| Mutex::new(StableBTreeMap::init(stable::memory(stable::POSITION_INDEX))));
+
+static MAX_AGE_NS: Lazy<u64> = Lazy::new(|| {
+    option_env!("POSITION_INDEX_MAX_AGE_SECS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(crate::utils::DAY_SECS)
+        .saturating_mul(1_000_000_000)
+});
+
+static MAX_ENTRIES: Lazy<usize> = Lazy::new(|| {
+    option_env!("POSITION_INDEX_MAX_ENTRIES")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(10_000)
+        .max(1)
+});
+
+static RESCAN_INTERVAL_SECS: Lazy<u64> = Lazy::new(|| {
+    option_env!("POSITION_RESCAN_INTERVAL_SECS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(600)
+        .max(60)
+});
+
+/// Floor on principals rescanned per run
+static RESCAN_BATCH: Lazy<usize> = Lazy::new(|| {
+    option_env!("POSITION_RESCAN_BATCH")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(5)
+});
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct StableEntry {
+    pub principal: Principal,
+    pub pools: Vec<Principal>,
+    pub scanned_at: u64,
+}
+
+fn key(principal: Principal) -> Key {
+    Blob::try_from(principal.as_slice()).expect("principals are at most 29 bytes")
+}
+
+/// Indexed pools for `principal`, or `None` when a full scan is needed.
+pub fn lookup(principal: Principal) -> Option<Vec<Principal>> {
+    let now = crate::utils::now();
+    let pools = INDEX
+        .lock()
+        .unwrap()
+        .get(&key(principal))
+        .filter(|e| now.saturating_sub(e.scanned_at) < *MAX_AGE_NS)
+        .map(|e| e.pools);
+    crate::metrics::inc_position_index(pools.is_some());
+    pools
+}
+
+/// Store the result of a scan over every pool.
+pub fn record_full_scan(principal: Principal, pools: Vec<Principal>) {
+    let mut index = INDEX.lock().unwrap();
+    let key = key(principal);
+    if !index.contains_key(&key) && index.len() >= *MAX_ENTRIES as u64 {
+        evict_oldest(&mut index);
+    }
+    index.insert(
+        key,
+        Entry {
+            pools,
+            scanned_at: crate::utils::now(),
+        },
+    );
+}
+
+/// Store the result of a scan over indexed pools only; this drops pools the
+/// principal has left without resetting the entry's age.
+pub fn record_partial_scan(principal: Principal, pools: Vec<Principal>) {
+    update(principal, |e| e.pools = pools);
+}
+
+/// Rewrite the principal's entry, if it has one.
+fn update(principal: Principal, f: impl FnOnce(&mut Entry)) {
+    let mut index = INDEX.lock().unwrap();
+    let key = key(principal);
+    if let Some(mut entry) = index.get(&key) {
+        f(&mut entry);
+        index.insert(key, entry);
+    }
+}
+
+fn evict_oldest(index: &mut StableBTreeMap<Key, Entry, Memory>) {
+    let oldest = index
+        .iter()
+        .min_by_key(|(_, e)| e.scanned_at)
+        .map(|(k, _)| k);
+    if let Some(k) = oldest {
+        index.remove(&k);
+    }
+}
+
+/// Principals with the oldest scans first.
+fn due_for_rescan(limit: usize) -> Vec<Principal> {
+    let mut entries: Vec<(u64, Principal)> = INDEX
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(k, e)| (e.scanned_at, Principal::from_slice(k.as_slice())))
+        .collect();
+    entries.sort();
+    entries.into_iter().take(limit).map(|(_, p)| p).collect()
+}
+
+pub fn clear() {
+    INDEX.lock().unwrap().clear_new();
+}
+
+/// Principals to rescan per run so that every entry is refreshed once per
+/// max age, never fewer than `RESCAN_BATCH`.
+fn batch_size(entries: usize) -> usize {
+    let interval_ns = RESCAN_INTERVAL_SECS.saturating_mul(1_000_000_000);
+    let runs = (*MAX_AGE_NS / interval_ns).max(1) as usize;
+    entries.div_ceil(runs).max(*RESCAN_BATCH)
+}
+
+async fn rescan_batch() {
+    let entries = INDEX.lock().unwrap().len() as usize;
+    for principal in due_for_rescan(batch_size(entries)) {
+        if let Err(e) = crate::dex::dex_icpswap::rescan(principal).await {
+            tracing::warn!(%principal, "position rescan failed: {e}");
+            // push the entry back so one failing principal does not stall the queue
+            update(principal, |e| e.scanned_at = crate::utils::now());
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn schedule_rescan() {
+    use std::time::Duration;
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(*RESCAN_INTERVAL_SECS), || {
+        ic_cdk::spawn(rescan_batch());
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn schedule_rescan() {
+    use std::time::Duration;
+    tokio::spawn(async {
+        let mut timer = tokio::time::interval(Duration::from_secs(*RESCAN_INTERVAL_SECS));
+        loop {
+            timer.tick().await;
+            rescan_batch().await;
+        }
+    });
+}
+
+/// Replace the index with entries saved on the heap by an older release.
+pub fn stable_restore(entries: Vec<StableEntry>) {
+    let mut index = INDEX.lock().unwrap();
+    index.clear_new();
+    for e in entries {
+        index.insert(
+            key(e.principal),
+            Entry {
+                pools: e.pools,
+                scanned_at: e.scanned_at,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[serial_test::serial]
+    fn entries_expire_track_exits_and_migrate_from_the_heap() {
+        clear();
+        let user = Principal::from_slice(&[7; 29]);
+        let pool = Principal::from_slice(&[8; 29]);
+        assert_eq!(lookup(user), None);
+        // a partial scan never creates an entry
+        record_partial_scan(user, vec![pool]);
+        assert_eq!(lookup(user), None);
+        record_full_scan(user, vec![pool]);
+        assert_eq!(lookup(user), Some(vec![pool]));
+        record_partial_scan(user, Vec::new());
+        assert_eq!(lookup(user), Some(Vec::new()));
+        // a partial scan never revives an expired entry
+        update(user, |e| e.scanned_at = 0);
+        record_partial_scan(user, vec![pool]);
+        assert_eq!(lookup(user), None);
+
+        clear();
+        stable_restore(vec![StableEntry {
+            principal: user,
+            pools: vec![pool],
+            scanned_at: crate::utils::now(),
+        }]);
+        assert_eq!(lookup(user), Some(vec![pool]));
+
+        update(user, |e| e.scanned_at = 0);
+        assert_eq!(lookup(user), None);
+        assert_eq!(due_for_rescan(5), vec![user]);
+        clear();
+    }
+
+    #[test]
+    fn batch_covers_the_index_within_the_max_age() {
+        let runs = (*MAX_AGE_NS / (*RESCAN_INTERVAL_SECS * 1_000_000_000)) as usize;
+        assert_eq!(batch_size(0), *RESCAN_BATCH);
+        let size = batch_size(*MAX_ENTRIES);
+        assert!(size * runs >= *MAX_ENTRIES, "{size} per run");
+        assert_eq!(batch_size(10_000), 70);
+    }
+}
@@ -0,0 +1,116 @@
+use futures::channel::oneshot;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Coalesces concurrent calls for the same key: the first caller runs the
+/// work, later callers wait for its result instead of repeating it.
+pub struct Group<K, V> {
+    waiters: Mutex<HashMap<K, Vec<oneshot::Sender<V>>>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Default for Group<K, V> {
+    fn default() -> Self {
+        Self {
+            waiters: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Removes the in-flight marker even if the leading call is cancelled, so
+/// waiters fall back to running the work themselves.
+struct Leader<'a, K: Hash + Eq, V> {
+    group: &'a Group<K, V>,
+    key: &'a K,
+}
+
+impl<K: Hash + Eq, V> Drop for Leader<'_, K, V> {
+    fn drop(&mut self) {
+        self.group.waiters.lock().unwrap().remove(self.key);
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Group<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let waiting = {
+            let mut waiters = self.waiters.lock().unwrap();
+            match waiters.get_mut(&key) {
+                Some(list) => {
+                    let (tx, rx) = oneshot::channel();
+                    list.push(tx);
+                    Some(rx)
+                }
+                None => {
+                    waiters.insert(key.clone(), Vec::new());
+                    None
+                }
+            }
+        };
+        if let Some(rx) = waiting {
+            if let Ok(value) = rx.await {
+                crate::metrics::inc_coalesced();
+                return value;
+            }
+            return work().await;
+        }
+        let leader = Leader {
+            group: self,
+            key: &key,
+        };
+        let value = work().await;
+        let list = self.waiters.lock().unwrap().remove(&key);
+        drop(leader);
+        for tx in list.unwrap_or_default() {
+            let _ = tx.send(value.clone());
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn concurrent_calls_share_one_run() {
+        let group: Group<u8, usize> = Group::new();
+        let runs = AtomicUsize::new(0);
+        let work = || async {
+            tokio::task::yield_now().await;
+            runs.fetch_add(1, Ordering::SeqCst) + 10
+        };
+        let (a, b, c) = futures::join!(group.run(1, work), group.run(1, work), group.run(2, work));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        // later calls start a fresh flight
+        assert_eq!(group.run(1, work).await, 12);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn waiters_recover_when_leader_is_dropped() {
+        let group: Group<u8, u8> = Group::new();
+        let (tx, rx) = oneshot::channel::<()>();
+        let mut leader = Box::pin(group.run(1, || async {
+            let _ = rx.await;
+            1
+        }));
+        let follower = group.run(1, || async { 2 });
+        futures::pin_mut!(follower);
+        assert!(futures::poll!(leader.as_mut()).is_pending());
+        assert!(futures::poll!(follower.as_mut()).is_pending());
+        drop(leader);
+        drop(tx);
+        assert_eq!(follower.await, 2);
+    }
+}
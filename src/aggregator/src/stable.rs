@@ -0,0 +1,143 @@
+use candid::{CandidType, Deserialize};
+use std::borrow::Cow;
+
+// Stable memory is split into virtual memories by a `MemoryManager`, one per
+// structure that lives there instead of on the heap. The upgrade blob written
+// by `upgrade::save` has its own memory so it no longer starts at offset zero.
+//
+// Releases before the manager wrote the blob straight to stable memory, which
+// the manager would overwrite with its header, so that layout is copied out
+// before the manager is first created and handed to `upgrade::restore`.
+
+pub const WASM_PAGE_SIZE: u64 = 65_536;
+
+/// The state written by `upgrade::save`.
+pub const UPGRADES: u8 = 0;
+/// Structured log records, see `log_store`.
+pub const LOGS: u8 = 1;
+/// Ledger and DEX entries, see `config`.
+pub const CONFIG: u8 = 2;
+/// Pools each principal holds positions in, see `position_index`.
+pub const POSITION_INDEX: u8 = 3;
+
+#[cfg(target_arch = "wasm32")]
+mod imp {
+    use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+    use ic_stable_structures::{DefaultMemoryImpl, Memory as _};
+    use std::cell::RefCell;
+
+    thread_local! {
+        static LEGACY: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+        static MANAGER: MemoryManager<DefaultMemoryImpl> = {
+            let raw = DefaultMemoryImpl::default();
+            if raw.size() > 0 {
+                let mut magic = [0; 3];
+                raw.read(0, &mut magic);
+                if &magic != b"MGR" {
+                    LEGACY.with(|l| *l.borrow_mut() = Some(crate::upgrade::read_saved(&raw)));
+                }
+            }
+            MemoryManager::init(raw)
+        };
+    }
+
+    #[derive(Clone)]
+    pub struct Memory(VirtualMemory<DefaultMemoryImpl>);
+
+    // canisters run on a single thread, so the shared manager is never
+    // touched concurrently
+    unsafe impl Send for Memory {}
+    unsafe impl Sync for Memory {}
+
+    impl ic_stable_structures::Memory for Memory {
+        fn size(&self) -> u64 {
+            self.0.size()
+        }
+
+        fn grow(&self, pages: u64) -> i64 {
+            self.0.grow(pages)
+        }
+
+        fn read(&self, offset: u64, dst: &mut [u8]) {
+            self.0.read(offset, dst)
+        }
+
+        fn write(&self, offset: u64, src: &[u8]) {
+            self.0.write(offset, src)
+        }
+    }
+
+    pub fn memory(id: u8) -> Memory {
+        MANAGER.with(|m| Memory(m.get(MemoryId::new(id))))
+    }
+
+    pub fn take_legacy() -> Option<Vec<u8>> {
+        MANAGER.with(|_| ());
+        LEGACY.with(|l| l.borrow_mut().take())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod imp {
+    use super::WASM_PAGE_SIZE;
+    use std::sync::{Arc, Mutex};
+
+    /// Heap-backed memory for native builds and tests.
+    #[derive(Clone, Default)]
+    pub struct Memory(Arc<Mutex<Vec<u8>>>);
+
+    impl ic_stable_structures::Memory for Memory {
+        fn size(&self) -> u64 {
+            self.0.lock().unwrap().len() as u64 / WASM_PAGE_SIZE
+        }
+
+        fn grow(&self, pages: u64) -> i64 {
+            let mut bytes = self.0.lock().unwrap();
+            let old = bytes.len() as u64 / WASM_PAGE_SIZE;
+            bytes.resize(((old + pages) * WASM_PAGE_SIZE) as usize, 0);
+            old as i64
+        }
+
+        fn read(&self, offset: u64, dst: &mut [u8]) {
+            let bytes = self.0.lock().unwrap();
+            let start = offset as usize;
+            dst.copy_from_slice(&bytes[start..start + dst.len()]);
+        }
+
+        fn write(&self, offset: u64, src: &[u8]) {
+            let mut bytes = self.0.lock().unwrap();
+            let start = offset as usize;
+            bytes[start..start + src.len()].copy_from_slice(src);
+        }
+    }
+
+    pub fn memory(_id: u8) -> Memory {
+        Memory::default()
+    }
+
+    pub fn take_legacy() -> Option<Vec<u8>> {
+        None
+    }
+}
+
+pub use imp::Memory;
+
+/// The virtual memory reserved for `id`.
+pub fn memory(id: u8) -> Memory {
+    imp::memory(id)
+}
+
+/// State written by a release that predates the memory manager, if this
+/// canister was upgraded from one; only the first call returns it.
+pub fn take_legacy() -> Option<Vec<u8>> {
+    imp::take_legacy()
+}
+
+/// Candid encoding for `Storable` values without a fixed size.
+pub fn to_bytes<T: CandidType>(value: &T) -> Cow<'static, [u8]> {
+    Cow::Owned(candid::encode_one(value).expect("encode stable value"))
+}
+
+pub fn from_bytes<T: CandidType + for<'de> Deserialize<'de>>(bytes: Cow<[u8]>) -> T {
+    candid::decode_one(&bytes).expect("decode stable value")
+}
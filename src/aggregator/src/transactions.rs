@@ -0,0 +1,535 @@
+use crate::dex::call;
+use crate::error::FetchError;
+use crate::utils::format_amount;
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use dashmap::DashMap;
+use num_traits::ToPrimitive;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+
+// Transaction history of one principal on one configured ledger, newest
+// first. Ledgers that list ICRC-3 are read block by block with
+// `icrc3_get_blocks`, following archive callbacks, and the blocks touching
+// the principal are kept; a page covers `SCAN_WINDOW` blocks and may be
+// empty while `next` is still set. Older ledgers are read through the index
+// canister set for the ledger (under `[indexes]` or by `add_ledger`), which
+// filters by account itself. Either way `next` is the id to continue below.
+
+/// Blocks scanned per ICRC-3 page
+pub const SCAN_WINDOW: u64 = 1_000;
+/// Transactions asked of an index canister per page
+pub const INDEX_PAGE: u64 = 50;
+
+/// ICRC-3 generic value, used for blocks and ICRC-7 metadata
+#[derive(Debug, Clone, PartialEq, CandidType, Deserialize)]
+pub enum Value {
+    Blob(ByteBuf),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum TxKind {
+    Mint,
+    Burn,
+    Transfer,
+    Approve,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct TxAccount {
+    pub owner: Principal,
+    pub subaccount: Option<ByteBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: Nat,
+    pub kind: TxKind,
+    pub from: Option<TxAccount>,
+    pub to: Option<TxAccount>,
+    pub spender: Option<TxAccount>,
+    pub amount: String,
+    pub fee: Option<String>,
+    pub memo: Option<ByteBuf>,
+    /// Nanoseconds since the epoch
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct TransactionPage {
+    pub token: String,
+    pub transactions: Vec<Transaction>,
+    /// `None` once the start of the history has been reached
+    pub next: Option<Nat>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct GetBlocksArgs {
+    start: Nat,
+    length: Nat,
+}
+
+#[derive(CandidType, Deserialize)]
+struct BlockWithId {
+    id: Nat,
+    block: Value,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ArchivedBlocks {
+    args: Vec<GetBlocksArgs>,
+    callback: ArchiveFn,
+}
+
+#[derive(CandidType, Deserialize)]
+struct GetBlocksResult {
+    log_length: Nat,
+    blocks: Vec<BlockWithId>,
+    archived_blocks: Vec<ArchivedBlocks>,
+}
+
+candid::define_function!(ArchiveFn : (Vec<GetBlocksArgs>) -> (GetBlocksResult) query);
+
+#[derive(CandidType, Deserialize)]
+struct Standard {
+    name: String,
+}
+
+// The ICRC-1 transaction record served by index canisters; fields not
+// reported here are left out.
+#[derive(CandidType, Deserialize)]
+struct IndexMint {
+    to: TxAccount,
+    amount: Nat,
+    memo: Option<ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct IndexBurn {
+    from: TxAccount,
+    spender: Option<TxAccount>,
+    amount: Nat,
+    memo: Option<ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct IndexTransfer {
+    from: TxAccount,
+    to: TxAccount,
+    spender: Option<TxAccount>,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct IndexApprove {
+    from: TxAccount,
+    spender: TxAccount,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct IndexTransaction {
+    mint: Option<IndexMint>,
+    burn: Option<IndexBurn>,
+    transfer: Option<IndexTransfer>,
+    approve: Option<IndexApprove>,
+    timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct IndexTransactionWithId {
+    id: Nat,
+    transaction: IndexTransaction,
+}
+
+#[derive(CandidType, Deserialize)]
+struct IndexTransactions {
+    transactions: Vec<IndexTransactionWithId>,
+    oldest_tx_id: Option<Nat>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct IndexError {
+    message: String,
+}
+
+#[derive(CandidType, Deserialize)]
+enum IndexResult {
+    Ok(IndexTransactions),
+    Err(IndexError),
+}
+
+#[derive(CandidType)]
+struct GetAccountTransactionsArgs {
+    account: TxAccount,
+    start: Option<Nat>,
+    max_results: Nat,
+}
+
+/// Whether each ledger serves ICRC-3 blocks, and when that was checked
+static ICRC3_SUPPORT: Lazy<DashMap<Principal, (bool, u64)>> = Lazy::new(DashMap::new);
+
+fn field<'a>(map: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    map.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+fn nat_field(map: &[(String, Value)], key: &str) -> Option<Nat> {
+    match field(map, key)? {
+        Value::Nat(n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+/// An account encoded as `[owner]` or `[owner, subaccount]` blobs
+fn account_field(map: &[(String, Value)], key: &str) -> Option<TxAccount> {
+    let Value::Array(parts) = field(map, key)? else {
+        return None;
+    };
+    let (Some(Value::Blob(owner)), sub) = (parts.first(), parts.get(1)) else {
+        return None;
+    };
+    Some(TxAccount {
+        owner: Principal::try_from_slice(owner).ok()?,
+        subaccount: match sub {
+            Some(Value::Blob(s)) => Some(s.clone()),
+            _ => None,
+        },
+    })
+}
+
+fn kind_of(block: &[(String, Value)], tx: &[(String, Value)]) -> Option<TxKind> {
+    let op = match field(block, "btype").or_else(|| field(tx, "op"))? {
+        Value::Text(t) => t.as_str(),
+        _ => return None,
+    };
+    match op {
+        "1mint" | "mint" => Some(TxKind::Mint),
+        "1burn" | "burn" => Some(TxKind::Burn),
+        "1xfer" | "2xfer" | "xfer" => Some(TxKind::Transfer),
+        "2approve" | "approve" => Some(TxKind::Approve),
+        _ => None,
+    }
+}
+
+/// Decode an ICRC-3 block; unknown block types yield `None`.
+fn decode_block(id: Nat, block: &Value, decimals: u8) -> Option<Transaction> {
+    let Value::Map(block) = block else {
+        return None;
+    };
+    let Some(Value::Map(tx)) = field(block, "tx") else {
+        return None;
+    };
+    let kind = kind_of(block, tx)?;
+    let fee = nat_field(tx, "fee").or_else(|| nat_field(block, "fee"));
+    Some(Transaction {
+        id,
+        kind,
+        from: account_field(tx, "from"),
+        to: account_field(tx, "to"),
+        spender: account_field(tx, "spender"),
+        amount: format_amount(nat_field(tx, "amt")?, decimals),
+        fee: fee.map(|f| format_amount(f, decimals)),
+        memo: match field(tx, "memo") {
+            Some(Value::Blob(m)) => Some(m.clone()),
+            _ => None,
+        },
+        timestamp: nat_field(block, "ts")
+            .or_else(|| nat_field(tx, "ts"))
+            .and_then(|t| t.0.to_u64())
+            .unwrap_or(0),
+    })
+}
+
+fn involves(tx: &Transaction, principal: Principal) -> bool {
+    [&tx.from, &tx.to, &tx.spender]
+        .into_iter()
+        .flatten()
+        .any(|a| a.owner == principal)
+}
+
+/// Cached for a day, so a ledger upgraded to ICRC-3 is picked up.
+async fn supports_icrc3(ledger: Principal) -> Result<bool, FetchError> {
+    let now = crate::utils::now();
+    if let Some(entry) = ICRC3_SUPPORT.get(&ledger) {
+        let (supported, checked_at) = *entry;
+        if now.saturating_sub(checked_at) < crate::utils::DAY_NS {
+            return Ok(supported);
+        }
+    }
+    let standards: Vec<Standard> = call(ledger, "icrc1_supported_standards", (), false).await?;
+    let supported = standards.iter().any(|s| s.name == "ICRC-3");
+    ICRC3_SUPPORT.insert(ledger, (supported, now));
+    Ok(supported)
+}
+
+async fn icrc3_page(
+    ledger: Principal,
+    principal: Principal,
+    before: Option<Nat>,
+    decimals: u8,
+) -> Result<(Vec<Transaction>, Option<Nat>), FetchError> {
+    let end = match before {
+        Some(n) => n.0.to_u64().unwrap_or(u64::MAX),
+        None => {
+            let probe = vec![GetBlocksArgs {
+                start: Nat::from(0u64),
+                length: Nat::from(0u64),
+            }];
+            let res: GetBlocksResult = call(ledger, "icrc3_get_blocks", (probe,), false).await?;
+            res.log_length.0.to_u64().unwrap_or(u64::MAX)
+        }
+    };
+    let start = end.saturating_sub(SCAN_WINDOW);
+    let range = vec![GetBlocksArgs {
+        start: Nat::from(start),
+        length: Nat::from(end - start),
+    }];
+    let res: GetBlocksResult = call(ledger, "icrc3_get_blocks", (range,), false).await?;
+    let mut blocks = res.blocks;
+    for archived in res.archived_blocks {
+        let callback = archived.callback.0;
+        let res: GetBlocksResult = call(
+            callback.principal,
+            &callback.method,
+            (archived.args,),
+            false,
+        )
+        .await?;
+        blocks.extend(res.blocks);
+    }
+    let mut txs: Vec<Transaction> = blocks
+        .into_iter()
+        .filter_map(|b| decode_block(b.id, &b.block, decimals))
+        .filter(|tx| involves(tx, principal))
+        .collect();
+    txs.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok((txs, (start > 0).then(|| Nat::from(start))))
+}
+
+fn index_transaction(id: Nat, tx: IndexTransaction, decimals: u8) -> Option<Transaction> {
+    let amount = |n: Nat| format_amount(n, decimals);
+    let fee = |f: Option<Nat>| f.map(|f| format_amount(f, decimals));
+    let base = |kind, amt: Nat, memo| Transaction {
+        id: id.clone(),
+        kind,
+        from: None,
+        to: None,
+        spender: None,
+        amount: amount(amt),
+        fee: None,
+        memo,
+        timestamp: tx.timestamp,
+    };
+    if let Some(m) = tx.mint {
+        Some(Transaction {
+            to: Some(m.to),
+            ..base(TxKind::Mint, m.amount, m.memo)
+        })
+    } else if let Some(b) = tx.burn {
+        Some(Transaction {
+            from: Some(b.from),
+            spender: b.spender,
+            ..base(TxKind::Burn, b.amount, b.memo)
+        })
+    } else if let Some(t) = tx.transfer {
+        Some(Transaction {
+            from: Some(t.from),
+            to: Some(t.to),
+            spender: t.spender,
+            fee: fee(t.fee),
+            ..base(TxKind::Transfer, t.amount, t.memo)
+        })
+    } else {
+        tx.approve.map(|a| Transaction {
+            from: Some(a.from),
+            spender: Some(a.spender),
+            fee: fee(a.fee),
+            ..base(TxKind::Approve, a.amount, a.memo)
+        })
+    }
+}
+
+async fn index_page(
+    index: Principal,
+    principal: Principal,
+    before: Option<Nat>,
+    decimals: u8,
+) -> Result<(Vec<Transaction>, Option<Nat>), FetchError> {
+    let args = GetAccountTransactionsArgs {
+        account: TxAccount {
+            owner: principal,
+            subaccount: None,
+        },
+        start: before,
+        max_results: Nat::from(INDEX_PAGE),
+    };
+    let page = match call(index, "get_account_transactions", (args,), false).await? {
+        IndexResult::Ok(page) => page,
+        IndexResult::Err(e) => return Err(FetchError::Network(e.message)),
+    };
+    let full = page.transactions.len() as u64 >= INDEX_PAGE;
+    let txs: Vec<Transaction> = page
+        .transactions
+        .into_iter()
+        .filter_map(|t| index_transaction(t.id, t.transaction, decimals))
+        .collect();
+    let next = txs
+        .last()
+        .map(|t| t.id.clone())
+        .filter(|id| full && page.oldest_tx_id.as_ref() != Some(id));
+    Ok((txs, next))
+}
+
+/// One page of `principal`'s transactions on `ledger`, which must be one of
+/// the configured ledgers, continuing below `before` when given.
+pub async fn get_transactions(
+    principal: Principal,
+    ledger: Principal,
+    before: Option<Nat>,
+) -> Result<TransactionPage, String> {
+    let entry = crate::config::get()
+        .ledgers
+        .into_iter()
+        .find(|l| l.id == ledger)
+        .ok_or_else(|| format!("unknown ledger {ledger}"))?;
+    let info = crate::utils::token_info(ledger)
+        .await
+        .ok_or_else(|| format!("no metadata for ledger {}", entry.name))?;
+    let page = if supports_icrc3(ledger).await.map_err(|e| e.to_string())? {
+        icrc3_page(ledger, principal, before, info.decimals).await
+    } else {
+        let index = entry
+            .index
+            .ok_or_else(|| format!("{} has no ICRC-3 blocks or index", entry.name))?;
+        index_page(index, principal, before, info.decimals).await
+    };
+    let (transactions, next) = page.map_err(|e| e.to_string())?;
+    Ok(TransactionPage {
+        token: info.symbol,
+        transactions,
+        next,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Decode;
+    use quickcheck_macros::quickcheck;
+
+    fn account(owner: Principal) -> Value {
+        Value::Array(vec![Value::Blob(ByteBuf::from(owner.as_slice().to_vec()))])
+    }
+
+    fn entry(key: &str, value: Value) -> (String, Value) {
+        (key.to_string(), value)
+    }
+
+    #[test]
+    fn decodes_icrc3_blocks() {
+        let alice = Principal::from_slice(&[1]);
+        let bob = Principal::from_slice(&[2]);
+        let block = |btype: Option<&str>, tx: Vec<(String, Value)>| {
+            let mut fields = vec![
+                entry("ts", Value::Nat(Nat::from(7u64))),
+                entry("tx", Value::Map(tx)),
+            ];
+            fields.extend(btype.map(|b| entry("btype", Value::Text(b.into()))));
+            Value::Map(fields)
+        };
+        let xfer = block(
+            None,
+            vec![
+                entry("op", Value::Text("xfer".into())),
+                entry("from", account(alice)),
+                entry("to", account(bob)),
+                entry("amt", Value::Nat(Nat::from(150_000_000u64))),
+                entry("fee", Value::Nat(Nat::from(10_000u64))),
+            ],
+        );
+        let tx = decode_block(Nat::from(3u64), &xfer, 8).unwrap();
+        assert_eq!(tx.kind, TxKind::Transfer);
+        assert_eq!(tx.amount, "1.50000000");
+        assert_eq!(tx.fee.as_deref(), Some("0.00010000"));
+        assert_eq!(tx.timestamp, 7);
+        assert!(involves(&tx, bob));
+        assert!(!involves(&tx, Principal::anonymous()));
+
+        let mint = block(
+            Some("1mint"),
+            vec![
+                entry("to", account(alice)),
+                entry("amt", Value::Nat(Nat::from(1u64))),
+            ],
+        );
+        let tx = decode_block(Nat::from(0u64), &mint, 8).unwrap();
+        assert_eq!(tx.kind, TxKind::Mint);
+        assert!(tx.from.is_none());
+
+        let unknown = block(
+            Some("99custom"),
+            vec![entry("amt", Value::Nat(Nat::from(1u64)))],
+        );
+        assert!(decode_block(Nat::from(1u64), &unknown, 8).is_none());
+    }
+
+    #[test]
+    fn converts_index_transactions() {
+        let alice = TxAccount {
+            owner: Principal::from_slice(&[1]),
+            subaccount: None,
+        };
+        let approve = IndexTransaction {
+            mint: None,
+            burn: None,
+            transfer: None,
+            approve: Some(IndexApprove {
+                from: alice.clone(),
+                spender: alice.clone(),
+                amount: Nat::from(5u64),
+                fee: Some(Nat::from(1u64)),
+                memo: None,
+            }),
+            timestamp: 9,
+        };
+        let tx = index_transaction(Nat::from(4u64), approve, 0).unwrap();
+        assert_eq!(tx.kind, TxKind::Approve);
+        assert_eq!(tx.spender, Some(alice));
+        assert_eq!(tx.fee.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn archive_callbacks_round_trip() {
+        let res = GetBlocksResult {
+            log_length: Nat::from(6u64),
+            blocks: Vec::new(),
+            archived_blocks: vec![ArchivedBlocks {
+                args: vec![GetBlocksArgs {
+                    start: Nat::from(0u64),
+                    length: Nat::from(2u64),
+                }],
+                callback: ArchiveFn::new(Principal::anonymous(), "archived_blocks".into()),
+            }],
+        };
+        let bytes = candid::encode_one(&res).unwrap();
+        let back = Decode!(&bytes, GetBlocksResult).unwrap();
+        let callback = &back.archived_blocks[0].callback.0;
+        assert_eq!(callback.method, "archived_blocks");
+        assert_eq!(back.archived_blocks[0].args[0].length, 2u64);
+    }
+
+    #[quickcheck]
+    fn fuzz_decode_blocks(data: Vec<u8>) -> bool {
+        let _ = Decode!(&data, GetBlocksResult);
+        true
+    }
+}
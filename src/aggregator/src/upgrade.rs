@@ -0,0 +1,360 @@
+use crate::{
+    cache, cert, config, dex::locker, ledger_fetcher, log_store, logging, lp_cache, metrics, nft,
+    position_index, stable,
+};
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{writer::Writer, Memory};
+
+// Upgrade memory layout: MAGIC, the layout version as a little-endian u32, the
+// payload length as a little-endian u64, then the state for that version as
+// a Candid record. Records decode by field name, so adding an `Option` field
+// needs no new version; renaming or retyping a field does, together with a
+// migration from the previous version below.
+//
+// Canisters released before the header wrote an anonymous Candid tuple at
+// offset zero, which starts with "DIDL" instead of MAGIC. Both were written
+// to raw stable memory before it was shared out by `stable`.
+
+const MAGIC: &[u8; 4] = b"BXST";
+const HEADER_LEN: usize = 16;
+pub const VERSION: u32 = 1;
+
+#[derive(CandidType, Deserialize)]
+pub struct StateV1 {
+    pub cycles_log: Vec<String>,
+    pub ledger_meta: Vec<ledger_fetcher::StableMeta>,
+    pub lp_cache: Vec<lp_cache::StableEntry>,
+    pub metrics: metrics::StableMetrics,
+    /// Only written by releases that kept logs on the heap
+    pub logs: Vec<log_store::LogRecord>,
+    pub log_config: Option<logging::LogConfig>,
+    /// Only written by releases that kept the config on the heap
+    pub config: Option<config::Config>,
+    /// Only written by releases that kept the position index on the heap
+    pub positions: Vec<position_index::StableEntry>,
+    pub cache_config: Option<cache::CacheConfig>,
+    pub cache: Vec<cache::StableEntry>,
+    pub cert: Vec<cert::StableLeaf>,
+    /// `None` keeps the collections from `[nfts]` and `[ext_nfts]`
+    pub nft_collections: Option<Vec<nft::StableEntry>>,
+    /// `None` keeps the lockers from `[lockers]`
+    pub lockers: Option<Vec<locker::LockerConfig>>,
+}
+
+/// The state layout written by this build.
+pub type State = StateV1;
+
+/// The tuple written before versioning; fields added over time are optional.
+type LegacyState = (
+    Vec<String>,
+    Vec<ledger_fetcher::StableMeta>,
+    Vec<lp_cache::StableEntry>,
+    (u64, u64, u64, u64, u64, u64, u64),
+    Option<Vec<log_store::LogRecord>>,
+    Option<logging::LogConfig>,
+    Option<config::Config>,
+    Option<Vec<position_index::StableEntry>>,
+    Option<cache::CacheConfig>,
+    Option<Vec<cache::StableEntry>>,
+    Option<Vec<cert::StableLeaf>>,
+);
+
+fn migrate_legacy(legacy: LegacyState) -> StateV1 {
+    let (log, meta, lp, m, logs, log_config, config, positions, cache_config, cache, cert) = legacy;
+    StateV1 {
+        cycles_log: log,
+        ledger_meta: meta,
+        lp_cache: lp,
+        metrics: metrics::StableMetrics {
+            query_count: m.0,
+            heartbeat_count: m.1,
+            last_heartbeat: m.2,
+            claim_attempts: m.3,
+            claim_successes: m.4,
+            cycle_refill_attempts: m.5,
+            cycle_refill_successes: m.6,
+        },
+        logs: logs.unwrap_or_default(),
+        log_config,
+        config,
+        positions: positions.unwrap_or_default(),
+        cache_config,
+        cache: cache.unwrap_or_default(),
+        cert: cert.unwrap_or_default(),
+        nft_collections: None,
+        lockers: None,
+    }
+}
+
+impl State {
+    pub fn capture() -> Self {
+        Self {
+            cycles_log: crate::cycles::take_log(),
+            ledger_meta: ledger_fetcher::stable_save(),
+            lp_cache: lp_cache::stable_save(),
+            metrics: metrics::stable_save(),
+            logs: Vec::new(),
+            log_config: Some(logging::stable_save()),
+            config: None,
+            positions: Vec::new(),
+            cache_config: Some(cache::stable_save_config()),
+            cache: cache::stable_save(),
+            cert: cert::stable_save(),
+            nft_collections: Some(nft::stable_save()),
+            lockers: Some(locker::stable_save()),
+        }
+    }
+
+    pub fn apply(self) {
+        crate::cycles::set_log(self.cycles_log);
+        ledger_fetcher::stable_restore(self.ledger_meta);
+        lp_cache::stable_restore(self.lp_cache);
+        metrics::stable_restore(self.metrics);
+        if !self.logs.is_empty() {
+            log_store::stable_restore(self.logs);
+        }
+        if let Some(cfg) = self.log_config {
+            logging::stable_restore(cfg);
+        }
+        if let Some(cfg) = self.config {
+            config::stable_restore(cfg);
+        }
+        if !self.positions.is_empty() {
+            position_index::stable_restore(self.positions);
+        }
+        if let Some(cfg) = self.cache_config {
+            cache::stable_restore_config(cfg);
+        }
+        cache::stable_restore(self.cache);
+        cert::stable_restore(self.cert);
+        if let Some(collections) = self.nft_collections {
+            nft::stable_restore(collections);
+        }
+        if let Some(lockers) = self.lockers {
+            locker::stable_restore(lockers);
+        }
+    }
+}
+
+pub fn encode(state: &State) -> Result<Vec<u8>, String> {
+    let payload = candid::encode_one(state).map_err(|e| e.to_string())?;
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend(payload);
+    Ok(out)
+}
+
+/// Decode stable memory contents, migrating older layouts to the current
+/// one. `Ok(None)` means nothing was ever saved. Trailing bytes are ignored
+/// because stable memory is allocated in whole pages.
+pub fn decode(bytes: &[u8]) -> Result<Option<State>, String> {
+    if bytes.iter().all(|b| *b == 0) {
+        return Ok(None);
+    }
+    if bytes.starts_with(b"DIDL") {
+        return decode_legacy(bytes).map(|s| Some(migrate_legacy(s)));
+    }
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err("unrecognised stable memory layout".into());
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let len = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+    let payload = usize::try_from(len)
+        .ok()
+        .and_then(|len| bytes.get(HEADER_LEN..HEADER_LEN.checked_add(len)?))
+        .ok_or("stable state is truncated")?;
+    match version {
+        1 => candid::decode_one::<StateV1>(payload)
+            .map(Some)
+            .map_err(|e| format!("stable state v1: {e}")),
+        v => Err(format!(
+            "stable state version {v} is newer than this build ({VERSION})"
+        )),
+    }
+}
+
+/// Bytes written by `save`, header included, going by the header at the
+/// start of its memory; `None` when it is not the current layout.
+fn saved_len(header: &[u8]) -> Option<u64> {
+    if header.len() < HEADER_LEN || &header[..4] != MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    len.checked_add(HEADER_LEN as u64)
+}
+
+fn decode_legacy(bytes: &[u8]) -> Result<LegacyState, String> {
+    let mut de = candid::de::IDLDeserialize::new(bytes).map_err(|e| e.to_string())?;
+    candid::utils::ArgumentDecoder::decode(&mut de).map_err(|e| format!("legacy stable state: {e}"))
+}
+
+pub fn save() {
+    let bytes = encode(&State::capture()).unwrap_or_else(|e| ic_cdk::api::trap(&e));
+    let mut memory = stable::memory(stable::UPGRADES);
+    Writer::new(&mut memory, 0)
+        .write(&bytes)
+        .unwrap_or_else(|_| ic_cdk::api::trap("stable memory is full"));
+}
+
+/// Restore state saved by `save` or an older release. Failing to decode
+/// traps, which rolls the upgrade back instead of discarding the state.
+pub fn restore() {
+    let bytes =
+        stable::take_legacy().unwrap_or_else(|| read_saved(&stable::memory(stable::UPGRADES)));
+    match decode(&bytes) {
+        Ok(Some(state)) => state.apply(),
+        Ok(None) => {}
+        Err(e) => ic_cdk::api::trap(&e),
+    }
+}
+
+/// The bytes `decode` needs from `memory`, going by the header.
+pub(crate) fn read_saved<M: Memory>(memory: &M) -> Vec<u8> {
+    let capacity = memory.size() * stable::WASM_PAGE_SIZE;
+    if capacity < HEADER_LEN as u64 {
+        return Vec::new();
+    }
+    let mut header = [0; HEADER_LEN];
+    memory.read(0, &mut header);
+    // only the legacy layout has no length, so only it needs all of memory
+    let total = if header.starts_with(b"DIDL") {
+        capacity
+    } else if let Some(total) = saved_len(&header) {
+        total.min(capacity)
+    } else {
+        return header.to_vec();
+    };
+    let mut buf = vec![0; total as usize];
+    memory.read(0, &mut buf);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        StateV1 {
+            cycles_log: vec!["refill".into()],
+            ledger_meta: Vec::new(),
+            lp_cache: Vec::new(),
+            metrics: metrics::StableMetrics {
+                query_count: 5,
+                ..Default::default()
+            },
+            logs: Vec::new(),
+            log_config: None,
+            config: Some(config::Config::default()),
+            positions: Vec::new(),
+            cache_config: Some(cache::CacheConfig::default()),
+            cache: Vec::new(),
+            cert: vec![cert::StableLeaf {
+                key: b"aaaaa-aa".to_vec(),
+                hash: vec![1; 32],
+            }],
+            nft_collections: None,
+            lockers: None,
+        }
+    }
+
+    #[test]
+    fn current_layout_round_trips_with_page_padding() {
+        let mut bytes = encode(&state()).unwrap();
+        bytes.resize(65_536, 0);
+        let decoded = decode(&bytes).unwrap().unwrap();
+        assert_eq!(decoded.cycles_log, vec!["refill".to_string()]);
+        assert_eq!(decoded.metrics.query_count, 5);
+        assert_eq!(decoded.config, Some(config::Config::default()));
+        assert_eq!(decoded.cert[0].hash, vec![1; 32]);
+        assert!(decode(&[0; 64]).unwrap().is_none());
+    }
+
+    #[test]
+    fn header_gives_the_saved_length() {
+        let bytes = encode(&state()).unwrap();
+        assert_eq!(saved_len(&bytes[..HEADER_LEN]), Some(bytes.len() as u64));
+        assert_eq!(saved_len(&[0; HEADER_LEN]), None);
+        assert_eq!(saved_len(b"DIDL\0\0\0\0\0\0\0\0\0\0\0\0"), None);
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn saved_state_is_read_back_from_its_memory() {
+        let bytes = encode(&state()).unwrap();
+        let mut memory = stable::memory(stable::UPGRADES);
+        assert!(read_saved(&memory).is_empty());
+        Writer::new(&mut memory, 0).write(&bytes).unwrap();
+        assert_eq!(read_saved(&memory), bytes);
+        let legacy = candid::encode_args((vec!["old".to_string()],)).unwrap();
+        Writer::new(&mut memory, 0).write(&legacy).unwrap();
+        assert_eq!(read_saved(&memory).len() as u64, stable::WASM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn upgrades_from_tuple_layouts() {
+        let metrics = (1u64, 2u64, 3u64, 4u64, 5u64, 6u64, 7u64);
+        let log = vec!["old".to_string()];
+        // the original four-element tuple
+        let mut bytes = candid::encode_args((
+            log.clone(),
+            Vec::<ledger_fetcher::StableMeta>::new(),
+            Vec::<lp_cache::StableEntry>::new(),
+            metrics,
+        ))
+        .unwrap();
+        bytes.resize(65_536, 0);
+        let state = decode(&bytes).unwrap().unwrap();
+        assert_eq!(state.cycles_log, log);
+        assert_eq!(state.metrics.heartbeat_count, 2);
+        assert_eq!(state.metrics.cycle_refill_successes, 7);
+        assert!(state.config.is_none());
+        assert!(state.cache.is_empty());
+
+        // the last tuple layout, with every optional section present
+        let entry = cache::StableEntry {
+            principal: candid::Principal::anonymous(),
+            source: "ledger".into(),
+            holdings: Vec::new(),
+            fetched_at: 9,
+        };
+        let bytes = candid::encode_args((
+            log,
+            Vec::<ledger_fetcher::StableMeta>::new(),
+            Vec::<lp_cache::StableEntry>::new(),
+            metrics,
+            Some(Vec::<log_store::LogRecord>::new()),
+            Some(logging::LogConfig {
+                level: "debug".into(),
+                modules: Vec::new(),
+            }),
+            Some(config::Config::default()),
+            Some(Vec::<position_index::StableEntry>::new()),
+            Some(cache::CacheConfig::default()),
+            Some(vec![entry]),
+            Some(Vec::<cert::StableLeaf>::new()),
+        ))
+        .unwrap();
+        let state = decode(&bytes).unwrap().unwrap();
+        assert_eq!(state.log_config.unwrap().level, "debug");
+        assert_eq!(state.config, Some(config::Config::default()));
+        assert_eq!(state.cache[0].fetched_at, 9);
+    }
+
+    #[test]
+    fn unreadable_state_is_an_error() {
+        let bytes = encode(&state()).unwrap();
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        let mut newer = bytes.clone();
+        newer[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(decode(&newer).err().unwrap().contains("newer"));
+        let mut corrupt = bytes;
+        corrupt[HEADER_LEN] ^= 0xff;
+        assert!(decode(&corrupt).is_err());
+        assert!(decode(b"garbage").is_err());
+        // a legacy tuple whose types no longer match
+        let bytes = candid::encode_args(("not a log",)).unwrap();
+        assert!(decode(&bytes).is_err());
+    }
+}
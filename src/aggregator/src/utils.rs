@@ -1,12 +1,7 @@
 use candid::Nat;
-#[cfg(not(target_arch = "wasm32"))]
 use num_traits::cast::ToPrimitive;
 #[cfg(not(target_arch = "wasm32"))]
-use once_cell::sync::{Lazy, OnceCell};
-#[cfg(not(target_arch = "wasm32"))]
-use std::collections::HashMap;
-#[cfg(not(target_arch = "wasm32"))]
-use std::sync::RwLock;
+use once_cell::sync::OnceCell;
 
 /// Common time constants in nanoseconds
 pub const MINUTE_NS: u64 = 60_000_000_000;
@@ -56,7 +51,6 @@ pub fn format_amount(n: Nat, _decimals: u8) -> String {
     n.0.to_string()
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub fn idl_to_u64(val: &candid::types::value::IDLValue) -> Option<u64> {
     use candid::types::value::IDLValue;
     match val {
@@ -69,12 +63,10 @@ pub fn idl_to_u64(val: &candid::types::value::IDLValue) -> Option<u64> {
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub fn idl_to_u8(val: &candid::types::value::IDLValue) -> Option<u8> {
     idl_to_u64(val).map(|v| v as u8)
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub fn idl_to_string(val: &candid::types::value::IDLValue) -> Option<String> {
     use candid::types::value::IDLValue;
     match val {
@@ -86,10 +78,6 @@ pub fn idl_to_string(val: &candid::types::value::IDLValue) -> Option<String> {
 #[cfg(not(target_arch = "wasm32"))]
 static AGENT: OnceCell<ic_agent::Agent> = OnceCell::new();
 
-#[cfg(not(target_arch = "wasm32"))]
-static PRINCIPAL_CACHE: Lazy<RwLock<HashMap<String, Option<candid::Principal>>>> =
-    Lazy::new(|| RwLock::new(HashMap::new()));
-
 #[cfg(not(target_arch = "wasm32"))]
 pub async fn get_agent() -> ic_agent::Agent {
     if let Some(a) = AGENT.get() {
@@ -107,107 +95,35 @@ pub async fn get_agent() -> ic_agent::Agent {
     agent
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub struct DexEntry {
-    pub id: candid::Principal,
-    pub controller: Option<candid::Principal>,
-    pub enabled: bool,
-}
-
-#[cfg(not(target_arch = "wasm32"))]
-static DEX_CONFIG: once_cell::sync::Lazy<
-    std::sync::RwLock<std::collections::HashMap<String, DexEntry>>,
-> = once_cell::sync::Lazy::new(|| std::sync::RwLock::new(std::collections::HashMap::new()));
-
 #[cfg(not(target_arch = "wasm32"))]
 static CONFIG_LOCK: once_cell::sync::Lazy<tokio::sync::Mutex<()>> =
     once_cell::sync::Lazy::new(|| tokio::sync::Mutex::new(()));
 
 #[cfg(not(target_arch = "wasm32"))]
 pub async fn load_dex_config() {
-    use std::path::Path;
     use tracing::{info, warn};
     let _guard = CONFIG_LOCK.lock().await;
 
-    let path = std::env::var("LEDGERS_FILE").unwrap_or_else(|_| "config/ledgers.toml".to_string());
-    if !Path::new(&path).exists() {
-        tracing::error!("dex config {path} missing");
+    // keep the current entries if the file is missing or not valid TOML
+    let Some(cfg) = crate::config::load_file(crate::config::Source::Dex) else {
         return;
-    }
-    let text = std::fs::read_to_string(&path).unwrap_or_default();
-    let value: toml::Value =
-        toml::from_str(&text).unwrap_or(toml::Value::Table(Default::default()));
-
-    let dex_table = value
-        .get("dex")
-        .and_then(|d| d.as_table())
-        .cloned()
-        .unwrap_or_default();
-    let ctrl_table = value
-        .get("dex_controllers")
-        .and_then(|d| d.as_table())
-        .cloned()
-        .unwrap_or_default();
+    };
+    let mut entries = cfg.dex;
 
     use std::collections::HashSet;
-    let mut map = std::collections::HashMap::with_capacity(dex_table.len());
-    let mut seen = HashSet::with_capacity(dex_table.len());
-    for (name, val) in dex_table.iter() {
-        if let Some(id_str) = val.as_str() {
-            match candid::Principal::from_text(id_str) {
-                Ok(id) => {
-                    if !seen.insert(id) {
-                        warn!("duplicate dex id {}", id);
-                    }
-                    let controller = ctrl_table
-                        .get(name)
-                        .and_then(|v| v.as_str())
-                        .and_then(|s| candid::Principal::from_text(s).ok());
-                    map.insert(
-                        name.clone(),
-                        DexEntry {
-                            id,
-                            controller,
-                            enabled: true,
-                        },
-                    );
-                }
-                Err(e) => warn!("invalid dex id {id_str}: {e}"),
-            }
+    let mut seen = HashSet::with_capacity(entries.len());
+    for e in &entries {
+        if !seen.insert(e.id) {
+            warn!("duplicate dex id {}", e.id);
         }
     }
 
-    {
-        let mut cfg = DEX_CONFIG.write().unwrap();
-        *cfg = map;
-    }
-
-    // clear cached principals so updates take effect immediately
-    PRINCIPAL_CACHE.write().unwrap().clear();
-
-    for key in [
-        "ICPSWAP_FACTORY",
-        "SONIC_ROUTER",
-        "INFINITY_VAULT",
-        "SNS_DISTRIBUTOR",
-    ] {
+    for key in crate::config::DEX_ENV_KEYS {
         if let Ok(val) = std::env::var(key) {
             match candid::Principal::from_text(&val) {
                 Ok(p) => {
                     info!("{key} set; overriding ledgers.toml value");
-                    if let Some(e) = DEX_CONFIG.write().unwrap().get_mut(key) {
-                        e.id = p;
-                        e.enabled = true;
-                    } else {
-                        DEX_CONFIG.write().unwrap().insert(
-                            key.to_string(),
-                            DexEntry {
-                                id: p,
-                                controller: None,
-                                enabled: true,
-                            },
-                        );
-                    }
+                    crate::config::apply_override(&mut entries, key, p);
                 }
                 Err(e) => warn!("{key} is not a valid principal: {e}"),
             }
@@ -216,6 +132,7 @@ pub async fn load_dex_config() {
         }
     }
 
+    crate::config::replace_dex(entries);
     sanity_check_dex().await;
 }
 
@@ -223,42 +140,50 @@ pub async fn load_dex_config() {
 async fn sanity_check_dex() {
     use tracing::error;
     let agent = get_agent().await;
-    let names: Vec<String> = { DEX_CONFIG.read().unwrap().keys().cloned().collect() };
-    for name in names {
-        let id;
-        let controller;
-        {
-            let cfg = DEX_CONFIG.read().unwrap();
-            if let Some(e) = cfg.get(&name) {
-                if !e.enabled {
-                    continue;
-                }
-                id = e.id;
-                controller = e.controller;
-            } else {
-                continue;
-            }
+    for entry in crate::config::get().dex {
+        if !entry.enabled {
+            continue;
         }
+        let name = entry.name;
         let mut disable = false;
-        if icrc1_metadata(&agent, id).await.is_none() {
+        if icrc1_metadata(&agent, entry.id).await.is_none() {
             error!("{name} metadata failed; disabling adapter");
             disable = true;
-        } else if let Some(c) = controller {
-            if !controller_matches(&agent, id, c).await {
+        } else if let Some(c) = entry.controller {
+            if !controller_matches(&agent, entry.id, c).await {
                 error!("{name} controller mismatch; disabling adapter");
                 disable = true;
             }
         }
         if disable {
-            if let Some(e) = DEX_CONFIG.write().unwrap().get_mut(&name) {
-                e.enabled = false;
-            }
+            let _ = crate::config::set_dex_enabled(&name, false);
         }
     }
 }
 
+/// Check that `expected` is among the controllers reported by `canister_info`.
 #[cfg(not(target_arch = "wasm32"))]
-async fn icrc1_metadata(
+pub async fn verify_controller(cid: candid::Principal, expected: candid::Principal) -> bool {
+    let agent = get_agent().await;
+    controller_matches(&agent, cid, expected).await
+}
+
+#[cfg(target_arch = "wasm32")]
+pub async fn verify_controller(cid: candid::Principal, expected: candid::Principal) -> bool {
+    use ic_cdk::api::management_canister::main::{canister_info, CanisterInfoRequest};
+    match canister_info(CanisterInfoRequest {
+        canister_id: cid,
+        num_requested_changes: None,
+    })
+    .await
+    {
+        Ok((info,)) => info.controllers.contains(&expected),
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn icrc1_metadata(
     agent: &ic_agent::Agent,
     cid: candid::Principal,
 ) -> Option<Vec<(String, candid::types::value::IDLValue)>> {
@@ -325,7 +250,7 @@ pub fn watch_dex_config() {
         tracing::debug!("dex config watcher already running");
         return;
     }
-    let path = std::env::var("LEDGERS_FILE").unwrap_or_else(|_| "config/ledgers.toml".to_string());
+    let path = crate::config::ledgers_path();
     if !Path::new(&path).exists() {
         tracing::error!("dex config {path} missing");
         return;
@@ -350,6 +275,7 @@ pub fn watch_dex_config() {
     tracing::info!("watching dex config at {}", path);
     tokio::spawn(async move {
         while rx.recv().await.is_some() {
+            crate::config::reload_ledgers();
             load_dex_config().await;
             crate::dex::clear_all_caches();
             crate::warm::init();
@@ -357,65 +283,54 @@ pub fn watch_dex_config() {
     });
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub fn env_principal(name: &str) -> Option<candid::Principal> {
-    if let Some(p) = PRINCIPAL_CACHE.read().unwrap().get(name) {
-        return *p;
-    }
-    let val = DEX_CONFIG
-        .read()
-        .unwrap()
-        .get(name)
-        .filter(|e| e.enabled)
-        .map(|e| e.id);
-    PRINCIPAL_CACHE
-        .write()
-        .unwrap()
-        .insert(name.to_string(), val);
-    val
+    crate::config::dex_id(name)
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub fn dex_ids() -> Vec<candid::Principal> {
-    DEX_CONFIG
-        .read()
-        .unwrap()
-        .values()
-        .filter(|e| e.enabled)
-        .map(|e| e.id)
-        .collect()
+    crate::config::dex_ids()
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn env_principal(name: &str) -> Option<candid::Principal> {
-    match name {
-        "ICPSWAP_FACTORY" => {
-            option_env!("ICPSWAP_FACTORY").and_then(|s| candid::Principal::from_text(s).ok())
-        }
-        "SONIC_ROUTER" => {
-            option_env!("SONIC_ROUTER").and_then(|s| candid::Principal::from_text(s).ok())
-        }
-        "INFINITY_VAULT" => {
-            option_env!("INFINITY_VAULT").and_then(|s| candid::Principal::from_text(s).ok())
-        }
-        "SNS_DISTRIBUTOR" => {
-            option_env!("SNS_DISTRIBUTOR").and_then(|s| candid::Principal::from_text(s).ok())
+/// Symbol, decimals and logo advertised by an ICRC-1 ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub decimals: u8,
+    pub logo: Option<String>,
+}
+
+fn token_info_from(meta: Vec<(String, candid::types::value::IDLValue)>) -> Option<TokenInfo> {
+    let mut symbol = None;
+    let mut decimals = None;
+    let mut logo = None;
+    for (key, val) in &meta {
+        match key.as_str() {
+            "icrc1:symbol" => symbol = idl_to_string(val),
+            "icrc1:decimals" => decimals = idl_to_u8(val),
+            "icrc1:logo" => logo = idl_to_string(val),
+            _ => {}
         }
-        _ => None,
     }
+    Some(TokenInfo {
+        symbol: symbol?,
+        decimals: decimals?,
+        logo,
+    })
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn dex_ids() -> Vec<candid::Principal> {
-    [
-        "ICPSWAP_FACTORY",
-        "SONIC_ROUTER",
-        "INFINITY_VAULT",
-        "SNS_DISTRIBUTOR",
-    ]
-    .into_iter()
-    .filter_map(env_principal)
-    .collect()
+pub async fn token_info(cid: candid::Principal) -> Option<TokenInfo> {
+    let (meta,): (Vec<(String, candid::types::value::IDLValue)>,) =
+        ic_cdk::api::call::call(cid, "icrc1_metadata", ())
+            .await
+            .ok()?;
+    token_info_from(meta)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn token_info(cid: candid::Principal) -> Option<TokenInfo> {
+    let agent = get_agent().await;
+    token_info_from(icrc1_metadata(&agent, cid).await?)
 }
 
 #[cfg(target_arch = "wasm32")]
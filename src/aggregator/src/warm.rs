@@ -26,7 +26,7 @@ pub fn init() {
     let mut q = QUEUE.lock().unwrap();
     q.clear();
     let mut seen = HashSet::with_capacity(*MAX_QUEUE_SIZE);
-    for cid in crate::ledger_fetcher::LEDGERS.iter().cloned() {
+    for cid in crate::config::ledger_ids() {
         if q.len() >= *MAX_QUEUE_SIZE {
             break;
         }
@@ -3,6 +3,7 @@ pub use aggregator::*;
 #[ic_cdk_macros::init]
 fn init() {
     aggregator::logging::init();
+    aggregator::config::init();
     #[cfg(not(target_arch = "wasm32"))]
     ic_cdk::spawn(async { aggregator::utils::load_dex_config().await });
     #[cfg(not(target_arch = "wasm32"))]
@@ -11,32 +12,30 @@ fn init() {
     aggregator::pool_registry::watch_pools_file();
     ic_cdk::spawn(async { aggregator::pool_registry::refresh().await });
     aggregator::pool_registry::schedule_refresh();
+    aggregator::pool_registry::schedule_discovery();
+    aggregator::pool_stats::schedule_refresh();
+    aggregator::position_index::schedule_rescan();
+    aggregator::holdings::schedule_revalidation();
     aggregator::lp_cache::schedule_eviction();
     aggregator::warm::init();
 }
 
 #[ic_cdk_macros::pre_upgrade]
 fn pre_upgrade() {
-    let log = aggregator::cycles::take_log();
-    let meta = aggregator::ledger_fetcher::stable_save();
-    let lp = aggregator::lp_cache::stable_save();
-    let metrics = aggregator::metrics::stable_save();
-    ic_cdk::storage::stable_save((log, meta, lp, metrics)).unwrap();
+    aggregator::upgrade::save();
 }
 
 #[ic_cdk_macros::post_upgrade]
 fn post_upgrade() {
-    if let Ok((log, meta, lp, metrics)) = ic_cdk::storage::stable_restore::<(
-        Vec<String>,
-        Vec<aggregator::ledger_fetcher::StableMeta>,
-        Vec<aggregator::lp_cache::StableEntry>,
-        (u64, u64, u64, u64, u64, u64, u64),
-    )>() {
-        aggregator::cycles::set_log(log);
-        aggregator::ledger_fetcher::stable_restore(meta);
-        aggregator::lp_cache::stable_restore(lp);
-        aggregator::metrics::stable_restore(metrics);
-    }
+    aggregator::logging::init();
+    aggregator::config::init();
+    // traps on unreadable state so the upgrade is rolled back
+    aggregator::upgrade::restore();
+    // discovered pools are not persisted; timers do not survive upgrades
+    aggregator::pool_registry::schedule_discovery();
+    aggregator::pool_stats::schedule_refresh();
+    aggregator::position_index::schedule_rescan();
+    aggregator::holdings::schedule_revalidation();
 }
 
 #[ic_cdk_macros::heartbeat]
@@ -0,0 +1,112 @@
+use aggregator::config::{self, Config};
+use aggregator::utils::{get_agent, icrc1_metadata, verify_controller};
+use anyhow::{bail, Context, Result};
+use candid::{Decode, Encode, Principal};
+
+const USAGE: &str = "usage: config_check [--ledgers PATH] [--pools PATH] [--probe] [--canister ID]";
+
+struct Args {
+    ledgers: String,
+    pools: String,
+    probe: bool,
+    canister: Option<Principal>,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        ledgers: std::env::var("LEDGERS_FILE").unwrap_or_else(|_| "config/ledgers.toml".into()),
+        pools: std::env::var("POOLS_FILE").unwrap_or_else(|_| "data/pools.toml".into()),
+        probe: false,
+        canister: None,
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--ledgers" => args.ledgers = it.next().context(USAGE)?,
+            "--pools" => args.pools = it.next().context(USAGE)?,
+            "--probe" => args.probe = true,
+            "--canister" => {
+                let id = it.next().context(USAGE)?;
+                args.canister = Some(Principal::from_text(&id).context("invalid --canister")?);
+            }
+            _ => bail!("{USAGE}"),
+        }
+    }
+    Ok(args)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let args = parse_args()?;
+    let mut failures = 0usize;
+
+    let ledgers = std::fs::read_to_string(&args.ledgers)
+        .with_context(|| format!("cannot read {}", args.ledgers))?;
+    for problem in config::validate_toml(&ledgers) {
+        println!("{}: {problem}", args.ledgers);
+        failures += 1;
+    }
+    let pools = std::fs::read_to_string(&args.pools)
+        .with_context(|| format!("cannot read {}", args.pools))?;
+    for problem in aggregator::pool_registry::validate(&pools) {
+        println!("{}: {problem}", args.pools);
+        failures += 1;
+    }
+
+    let wanted = config::parse_toml(&ledgers)
+        .map(|(cfg, _)| cfg)
+        .unwrap_or_default();
+    if args.probe {
+        failures += probe(&wanted).await;
+    }
+    if let Some(canister) = args.canister {
+        let active = deployed_config(canister).await?;
+        let lines = config::diff(&active, &wanted);
+        if lines.is_empty() {
+            println!("{canister} matches {}", args.ledgers);
+        }
+        for line in lines {
+            println!("{line}");
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("{failures} config problem(s) found");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Query every canister on the local replica; returns the number of failures.
+async fn probe(cfg: &Config) -> usize {
+    let agent = get_agent().await;
+    let mut failures = 0;
+    for e in &cfg.ledgers {
+        if icrc1_metadata(&agent, e.id).await.is_none() {
+            println!("probe: ledger {} ({}) icrc1_metadata failed", e.name, e.id);
+            failures += 1;
+        }
+    }
+    for e in &cfg.dex {
+        if icrc1_metadata(&agent, e.id).await.is_none() {
+            println!("probe: dex {} ({}) icrc1_metadata failed", e.name, e.id);
+            failures += 1;
+        } else if let Some(c) = e.controller {
+            if !verify_controller(e.id, c).await {
+                println!("probe: dex {} ({}) not controlled by {c}", e.name, e.id);
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+async fn deployed_config(canister: Principal) -> Result<Config> {
+    let agent = get_agent().await;
+    let bytes = agent
+        .query(&canister, "get_config")
+        .with_arg(Encode!()?)
+        .call()
+        .await?;
+    Ok(Decode!(&bytes, Config)?)
+}
@@ -0,0 +1,157 @@
+use candid::{CandidType, Principal, Reserved};
+use ic_cdk_macros::{query, update};
+use serde::Deserialize;
+
+// A deposit still confirming and a withdrawal being signed, for any caller.
+// The minter doubles as the Bitcoin canister holding the deposit address.
+
+/// Tip height reported by `bitcoin_get_utxos_query`
+const TIP_HEIGHT: u32 = 800_000;
+
+#[derive(CandidType, Deserialize)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct OwnerArg {
+    owner: Option<Principal>,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Outpoint {
+    txid: Vec<u8>,
+    vout: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Utxo {
+    outpoint: Outpoint,
+    value: u64,
+    height: u32,
+}
+
+#[derive(CandidType, Deserialize)]
+struct MinterInfo {
+    min_confirmations: u32,
+    retrieve_btc_min_amount: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+enum Network {
+    #[serde(rename = "mainnet")]
+    Mainnet,
+    #[serde(rename = "testnet")]
+    Testnet,
+    #[serde(rename = "regtest")]
+    Regtest,
+}
+
+#[derive(CandidType, Deserialize)]
+enum UtxosFilter {
+    #[serde(rename = "min_confirmations")]
+    MinConfirmations(u32),
+    #[serde(rename = "page")]
+    Page(Vec<u8>),
+}
+
+#[derive(CandidType, Deserialize)]
+struct GetUtxosRequest {
+    network: Network,
+    address: String,
+    filter: Option<UtxosFilter>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct GetUtxosResponse {
+    utxos: Vec<Utxo>,
+    tip_block_hash: Vec<u8>,
+    tip_height: u32,
+    next_page: Option<Vec<u8>>,
+}
+
+fn utxo(first: u8, height: u32) -> Utxo {
+    Utxo {
+        outpoint: Outpoint {
+            txid: vec![first; 32],
+            vout: 0,
+        },
+        value: 250_000,
+        height,
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum RetrieveBtcStatusV2 {
+    Unknown,
+    Pending,
+    Signing,
+    Sending(Reserved),
+    Submitted(Reserved),
+    AmountTooLow,
+    Confirmed(Reserved),
+    Reimbursed(Reserved),
+    WillReimburse(Reserved),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct BtcRetrievalStatusV2 {
+    block_index: u64,
+    amount: Option<u64>,
+    status_v2: Option<RetrieveBtcStatusV2>,
+}
+
+#[candid::candid_method(update)]
+#[update]
+fn get_btc_address(_arg: OwnerArg) -> String {
+    "bc1qmockdepositaddress0000000000000000000000".to_string()
+}
+
+/// The deposit that has already been minted
+#[candid::candid_method(query)]
+#[query]
+fn get_known_utxos(_arg: OwnerArg) -> Vec<Utxo> {
+    vec![utxo(0x01, TIP_HEIGHT - 100)]
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn get_minter_info() -> MinterInfo {
+    MinterInfo {
+        min_confirmations: 6,
+        retrieve_btc_min_amount: 100_000,
+    }
+}
+
+/// A minted deposit and one with two confirmations
+#[candid::candid_method(query)]
+#[query]
+fn bitcoin_get_utxos_query(_req: GetUtxosRequest) -> GetUtxosResponse {
+    GetUtxosResponse {
+        utxos: vec![utxo(0x01, TIP_HEIGHT - 100), utxo(0xab, TIP_HEIGHT - 1)],
+        tip_block_hash: vec![0; 32],
+        tip_height: TIP_HEIGHT,
+        next_page: None,
+    }
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn retrieve_btc_status_v2_by_account(_account: Option<Account>) -> Vec<BtcRetrievalStatusV2> {
+    vec![
+        BtcRetrievalStatusV2 {
+            block_index: 41,
+            amount: Some(1_000_000),
+            status_v2: Some(RetrieveBtcStatusV2::Signing),
+        },
+        BtcRetrievalStatusV2 {
+            block_index: 12,
+            amount: Some(5_000_000),
+            status_v2: Some(RetrieveBtcStatusV2::Confirmed(Reserved)),
+        },
+    ]
+}
+
+ic_cdk::export_candid!();
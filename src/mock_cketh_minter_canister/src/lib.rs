@@ -0,0 +1,136 @@
+use candid::{CandidType, Nat, Principal};
+use ic_cdk_macros::query;
+use serde::Deserialize;
+
+// The same four withdrawals for every sender, one in each terminal or
+// in-flight state.
+
+#[derive(CandidType, Deserialize)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(CandidType, Deserialize)]
+enum WithdrawalSearchParameter {
+    ByRecipient(String),
+    BySenderAccount(Account),
+    ByWithdrawalId(u64),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct EthTransaction {
+    transaction_hash: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum TxFinalizedStatus {
+    Success {
+        transaction_hash: String,
+        effective_transaction_fee: Option<Nat>,
+    },
+    Reimbursed {
+        transaction_hash: String,
+        reimbursed_amount: Nat,
+        reimbursed_in_block: Nat,
+    },
+    PendingReimbursement(EthTransaction),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum WithdrawalStatus {
+    Pending,
+    TxCreated,
+    TxSent(EthTransaction),
+    TxFinalized(TxFinalizedStatus),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct WithdrawalDetail {
+    withdrawal_id: u64,
+    recipient_address: String,
+    from: Principal,
+    from_subaccount: Option<Vec<u8>>,
+    token_symbol: String,
+    withdrawal_amount: Nat,
+    max_transaction_fee: Option<Nat>,
+    status: WithdrawalStatus,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Erc20Token {
+    ckerc20_token_symbol: String,
+    erc20_contract_address: String,
+    ledger_canister_id: Principal,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct MinterInfo {
+    supported_ckerc20_tokens: Option<Vec<Erc20Token>>,
+}
+
+const ETH: u64 = 1_000_000_000_000_000_000;
+
+fn tx(hash: &str) -> EthTransaction {
+    EthTransaction {
+        transaction_hash: hash.to_string(),
+    }
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn withdrawal_status(search: WithdrawalSearchParameter) -> Vec<WithdrawalDetail> {
+    let from = match search {
+        WithdrawalSearchParameter::BySenderAccount(a) => a.owner,
+        _ => Principal::anonymous(),
+    };
+    let detail = |id, token: &str, amount: u64, status| WithdrawalDetail {
+        withdrawal_id: id,
+        recipient_address: "0x0000000000000000000000000000000000000001".to_string(),
+        from,
+        from_subaccount: None,
+        token_symbol: token.to_string(),
+        withdrawal_amount: Nat::from(amount),
+        max_transaction_fee: None,
+        status,
+    };
+    vec![
+        detail(101, "ckETH", ETH / 2, WithdrawalStatus::Pending),
+        detail(
+            102,
+            "ckUSDC",
+            25_000_000,
+            WithdrawalStatus::TxSent(tx("0x02")),
+        ),
+        detail(
+            103,
+            "ckETH",
+            ETH,
+            WithdrawalStatus::TxFinalized(TxFinalizedStatus::Success {
+                transaction_hash: "0x03".to_string(),
+                effective_transaction_fee: Some(Nat::from(21_000u64)),
+            }),
+        ),
+        detail(
+            104,
+            "ckETH",
+            ETH / 5,
+            WithdrawalStatus::TxFinalized(TxFinalizedStatus::PendingReimbursement(tx("0x04"))),
+        ),
+    ]
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn get_minter_info() -> MinterInfo {
+    MinterInfo {
+        supported_ckerc20_tokens: Some(vec![Erc20Token {
+            ckerc20_token_symbol: "ckUSDC".to_string(),
+            erc20_contract_address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+            ledger_canister_id: Principal::anonymous(),
+        }]),
+    }
+}
+
+ic_cdk::export_candid!();
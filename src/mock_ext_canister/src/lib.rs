@@ -0,0 +1,47 @@
+use candid::{CandidType, Int, Principal};
+use ic_cdk_macros::query;
+use serde::Deserialize;
+
+// Any well-formed account identifier owns tokens 8, 0 and 3, returned out of
+// order with one of them listed for sale, as real EXT canisters do.
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Listing {
+    locked: Option<Int>,
+    seller: Principal,
+    price: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+enum CommonError {
+    InvalidToken(String),
+    Other(String),
+}
+
+#[derive(CandidType, Deserialize)]
+enum TokensExtResult {
+    #[serde(rename = "ok")]
+    Ok(Vec<(u32, Option<Listing>, Option<Vec<u8>>)>),
+    #[serde(rename = "err")]
+    Err(CommonError),
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn tokens_ext(aid: String) -> TokensExtResult {
+    if aid.len() != 64 || !aid.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return TokensExtResult::Err(CommonError::Other("No tokens".to_string()));
+    }
+    let listing = Listing {
+        locked: None,
+        seller: Principal::anonymous(),
+        price: 150_000_000,
+    };
+    TokensExtResult::Ok(vec![
+        (8, None, None),
+        (0, Some(listing), None),
+        (3, None, None),
+    ])
+}
+
+ic_cdk::export_candid!();
@@ -0,0 +1,84 @@
+use candid::{CandidType, Nat, Principal};
+use ic_cdk_macros::query;
+use serde::Deserialize;
+
+// One canister plays both the router and its only trading pair.
+
+#[derive(CandidType, Deserialize, Clone)]
+struct TokenInfo {
+    symbol: String,
+    decimals: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct PairInfo {
+    canister_id: Principal,
+    token0: TokenInfo,
+    token1: TokenInfo,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy)]
+enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Order {
+    id: u64,
+    side: OrderSide,
+    price: Nat,
+    remaining: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct AccountBalance {
+    token0: Nat,
+    token1: Nat,
+}
+
+#[candid::candid_method(query)]
+#[query(name = "getPairs")]
+fn get_pairs() -> Vec<PairInfo> {
+    vec![PairInfo {
+        canister_id: ic_cdk::id(),
+        token0: TokenInfo {
+            symbol: "ICP".to_string(),
+            decimals: 8,
+        },
+        token1: TokenInfo {
+            symbol: "ckUSDT".to_string(),
+            decimals: 6,
+        },
+    }]
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn pending(_user: Principal) -> Vec<Order> {
+    vec![
+        Order {
+            id: 1,
+            side: OrderSide::Sell,
+            price: Nat::from(9_000_000u64),
+            remaining: Nat::from(150_000_000u64),
+        },
+        Order {
+            id: 2,
+            side: OrderSide::Buy,
+            price: Nat::from(7_000_000u64),
+            remaining: Nat::from(200_000_000u64),
+        },
+    ]
+}
+
+#[candid::candid_method(query)]
+#[query(name = "accountBalance")]
+fn account_balance(_user: Principal) -> AccountBalance {
+    AccountBalance {
+        token0: Nat::from(50_000_000u64),
+        token1: Nat::from(3_000_000u64),
+    }
+}
+
+ic_cdk::export_candid!();
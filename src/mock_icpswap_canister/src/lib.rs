@@ -23,9 +23,10 @@ struct PoolData {
     key: String,
     token0: Token,
     token1: Token,
-    fee: u64,
+    fee: Nat,
     #[serde(rename = "tickSpacing")]
     tick_spacing: i32,
+    #[serde(rename = "canisterId")]
     canister_id: Principal,
 }
 
@@ -58,8 +59,32 @@ fn metadata() -> PoolMetadata {
     }
 }
 
+#[derive(CandidType, Deserialize)]
+struct TokenAmounts {
+    token0: Nat,
+    token1: Nat,
+}
+
 #[candid::candid_method(query)]
 #[query]
+fn get_reserves() -> TokenAmounts {
+    TokenAmounts {
+        token0: Nat::from(50_000_000_000u64),
+        token1: Nat::from(10_000_000_000u64),
+    }
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn get_volume_24h() -> TokenAmounts {
+    TokenAmounts {
+        token0: Nat::from(5_000_000_000u64),
+        token1: Nat::from(1_000_000_000u64),
+    }
+}
+
+#[candid::candid_method(query, rename = "getPools")]
+#[query(name = "getPools")]
 fn get_pools() -> Vec<PoolData> {
     vec![PoolData {
         key: "MOCK/ICP".to_string(),
@@ -71,7 +96,7 @@ fn get_pools() -> Vec<PoolData> {
             address: "mock1".to_string(),
             standard: "ICRC1".to_string(),
         },
-        fee: 0,
+        fee: Nat::from(3000u64),
         tick_spacing: 1,
         canister_id: ic_cdk::api::id(),
     }]
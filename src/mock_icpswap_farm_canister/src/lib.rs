@@ -0,0 +1,118 @@
+use candid::{CandidType, Nat, Principal};
+use ic_cdk_macros::{query, update};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+// One canister plays the farm index, an LP farm and a staking pool, so
+// `harvest` is called once per listed farm.
+
+#[derive(CandidType, Deserialize, Clone)]
+struct TokenInfo {
+    symbol: String,
+    decimals: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy)]
+enum FarmKind {
+    Farm,
+    Stake,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct FarmInfo {
+    canister_id: Principal,
+    kind: FarmKind,
+    reward_token: TokenInfo,
+    stake_token: Option<TokenInfo>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Deposit {
+    position_id: Nat,
+    token0: TokenInfo,
+    token0_amount: Nat,
+    token1: TokenInfo,
+    token1_amount: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct UserStake {
+    staked: Nat,
+}
+
+const REWARD: u64 = 12_000_000;
+
+/// Users whose reward has been harvested
+static HARVESTED: Lazy<Mutex<BTreeSet<Principal>>> = Lazy::new(Default::default);
+
+fn token(symbol: &str, decimals: u8) -> TokenInfo {
+    TokenInfo {
+        symbol: symbol.to_string(),
+        decimals,
+    }
+}
+
+#[candid::candid_method(query, rename = "getFarms")]
+#[query(name = "getFarms")]
+fn get_farms() -> Vec<FarmInfo> {
+    vec![
+        FarmInfo {
+            canister_id: ic_cdk::id(),
+            kind: FarmKind::Farm,
+            reward_token: token("ICS", 8),
+            stake_token: None,
+        },
+        FarmInfo {
+            canister_id: ic_cdk::id(),
+            kind: FarmKind::Stake,
+            reward_token: token("ICS", 8),
+            stake_token: Some(token("ICS", 8)),
+        },
+    ]
+}
+
+#[candid::candid_method(query, rename = "getUserDeposits")]
+#[query(name = "getUserDeposits")]
+fn get_user_deposits(_user: Principal) -> Vec<Deposit> {
+    vec![Deposit {
+        position_id: Nat::from(1u64),
+        token0: token("ICP", 8),
+        token0_amount: Nat::from(300_000_000u64),
+        token1: token("ckUSDC", 6),
+        token1_amount: Nat::from(27_000_000u64),
+    }]
+}
+
+#[candid::candid_method(query, rename = "getUserInfo")]
+#[query(name = "getUserInfo")]
+fn get_user_info(_user: Principal) -> UserStake {
+    UserStake {
+        staked: Nat::from(1_000_000_000u64),
+    }
+}
+
+fn pending(user: Principal) -> u64 {
+    if HARVESTED.lock().unwrap().contains(&user) {
+        0
+    } else {
+        REWARD
+    }
+}
+
+#[candid::candid_method(query, rename = "pendingReward")]
+#[query(name = "pendingReward")]
+fn pending_reward(user: Principal) -> Nat {
+    Nat::from(pending(user))
+}
+
+#[candid::candid_method(update)]
+#[update]
+fn harvest(user: Principal) -> Result<Nat, String> {
+    let amount = pending(user);
+    HARVESTED.lock().unwrap().insert(user);
+    Ok(Nat::from(amount))
+}
+
+ic_cdk::export_candid!();
@@ -0,0 +1,218 @@
+use candid::{CandidType, Int, Nat, Principal};
+use ic_cdk_macros::query;
+use serde::Deserialize;
+
+// Six fixed blocks touching the anonymous principal in every way but one.
+// Blocks below ARCHIVED are served through an archive callback that points
+// back at this canister, like a ledger with a single archive.
+
+const ARCHIVED: u64 = 2;
+const LOG_LENGTH: u64 = 6;
+
+#[derive(CandidType, Deserialize, Clone)]
+enum Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct GetBlocksArgs {
+    start: Nat,
+    length: Nat,
+}
+
+#[derive(CandidType, Deserialize)]
+struct BlockWithId {
+    id: Nat,
+    block: Value,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ArchivedBlocks {
+    args: Vec<GetBlocksArgs>,
+    callback: ArchiveFn,
+}
+
+#[derive(CandidType, Deserialize)]
+struct GetBlocksResult {
+    log_length: Nat,
+    blocks: Vec<BlockWithId>,
+    archived_blocks: Vec<ArchivedBlocks>,
+}
+
+candid::define_function!(ArchiveFn : (Vec<GetBlocksArgs>) -> (GetBlocksResult) query);
+
+#[derive(CandidType, Deserialize)]
+struct Standard {
+    name: String,
+    url: String,
+}
+
+fn account(owner: Principal) -> Value {
+    Value::Array(vec![Value::Blob(owner.as_slice().to_vec())])
+}
+
+fn entry(key: &str, value: Value) -> (String, Value) {
+    (key.to_string(), value)
+}
+
+fn amount(n: u64) -> Value {
+    Value::Nat(Nat::from(n))
+}
+
+fn block(id: u64) -> Option<Value> {
+    let anon = Principal::anonymous();
+    let other = |n: u8| Principal::from_slice(&[n]);
+    let (btype, tx) = match id {
+        0 => (
+            Some("1mint"),
+            vec![
+                entry("to", account(anon)),
+                entry("amt", amount(1_000_000_000)),
+            ],
+        ),
+        // blocks written before `btype` carry the operation in the tx
+        1 => (
+            None,
+            vec![
+                entry("op", Value::Text("xfer".to_string())),
+                entry("from", account(anon)),
+                entry("to", account(other(1))),
+                entry("amt", amount(200_000_000)),
+                entry("fee", amount(10_000)),
+            ],
+        ),
+        2 => (
+            Some("2approve"),
+            vec![
+                entry("from", account(anon)),
+                entry("spender", account(other(1))),
+                entry("amt", amount(500_000_000)),
+            ],
+        ),
+        3 => (
+            Some("1xfer"),
+            vec![
+                entry("from", account(other(2))),
+                entry("to", account(other(3))),
+                entry("amt", amount(1)),
+            ],
+        ),
+        4 => (
+            Some("1burn"),
+            vec![
+                entry("from", account(anon)),
+                entry("amt", amount(100_000_000)),
+            ],
+        ),
+        5 => (
+            Some("1xfer"),
+            vec![
+                entry("from", account(other(1))),
+                entry("to", account(anon)),
+                entry("amt", amount(50_000_000)),
+                entry("memo", Value::Blob(b"refund".to_vec())),
+            ],
+        ),
+        _ => return None,
+    };
+    let mut fields = vec![
+        entry("ts", amount(1_700_000_000_000_000_000 + id)),
+        entry("tx", Value::Map(tx)),
+    ];
+    fields.extend(btype.map(|b| entry("btype", Value::Text(b.to_string()))));
+    Some(Value::Map(fields))
+}
+
+fn range(args: &GetBlocksArgs) -> (u64, u64) {
+    let start: u64 = args.start.0.clone().try_into().unwrap_or(u64::MAX);
+    let length: u64 = args.length.0.clone().try_into().unwrap_or(u64::MAX);
+    (start, start.saturating_add(length).min(LOG_LENGTH))
+}
+
+fn blocks_in(start: u64, end: u64) -> Vec<BlockWithId> {
+    (start..end)
+        .filter_map(|id| {
+            Some(BlockWithId {
+                id: Nat::from(id),
+                block: block(id)?,
+            })
+        })
+        .collect()
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc1_supported_standards() -> Vec<Standard> {
+    ["ICRC-1", "ICRC-3"]
+        .into_iter()
+        .map(|name| Standard {
+            name: name.to_string(),
+            url: format!("https://github.com/dfinity/ICRC-1/standards/{name}"),
+        })
+        .collect()
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc1_metadata() -> Vec<(String, candid::types::value::IDLValue)> {
+    vec![
+        (
+            "icrc1:symbol".to_string(),
+            candid::types::value::IDLValue::Text("BLK".to_string()),
+        ),
+        (
+            "icrc1:decimals".to_string(),
+            candid::types::value::IDLValue::Nat8(8),
+        ),
+    ]
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc3_get_blocks(args: Vec<GetBlocksArgs>) -> GetBlocksResult {
+    let mut blocks = Vec::new();
+    let mut archived = Vec::new();
+    for a in &args {
+        let (start, end) = range(a);
+        blocks.extend(blocks_in(start.max(ARCHIVED), end));
+        if start < ARCHIVED && end > start {
+            let end = end.min(ARCHIVED);
+            archived.push(ArchivedBlocks {
+                args: vec![GetBlocksArgs {
+                    start: Nat::from(start),
+                    length: Nat::from(end - start),
+                }],
+                callback: ArchiveFn::new(ic_cdk::api::id(), "archived_blocks".to_string()),
+            });
+        }
+    }
+    GetBlocksResult {
+        log_length: Nat::from(LOG_LENGTH),
+        blocks,
+        archived_blocks: archived,
+    }
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn archived_blocks(args: Vec<GetBlocksArgs>) -> GetBlocksResult {
+    let blocks = args
+        .iter()
+        .flat_map(|a| {
+            let (start, end) = range(a);
+            blocks_in(start, end.min(ARCHIVED))
+        })
+        .collect();
+    GetBlocksResult {
+        log_length: Nat::from(LOG_LENGTH),
+        blocks,
+        archived_blocks: Vec::new(),
+    }
+}
+
+ic_cdk::export_candid!();
@@ -0,0 +1,80 @@
+use candid::{CandidType, Int, Nat, Principal};
+use ic_cdk_macros::query;
+use serde::Deserialize;
+
+// Every account owns tokens 1 to 5 of a single collection, enough to page
+// through with a small `take`. Like many collections, it returns at most
+// MAX_TAKE tokens per call whatever `take` asks for.
+
+const TOKENS: u64 = 5;
+const MAX_TAKE: u64 = 2;
+
+#[derive(CandidType, Deserialize)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+fn text(key: &str, value: String) -> (String, Value) {
+    (key.to_string(), Value::Text(value))
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc7_collection_metadata() -> Vec<(String, Value)> {
+    vec![
+        text("icrc7:name", "Mock Punks".to_string()),
+        text("icrc7:symbol", "MPUNK".to_string()),
+        (
+            "icrc7:total_supply".to_string(),
+            Value::Nat(Nat::from(TOKENS)),
+        ),
+    ]
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc7_max_take_value() -> Option<Nat> {
+    Some(Nat::from(MAX_TAKE))
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc7_tokens_of(_account: Account, prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let start = prev.map(|p| p + 1u64).unwrap_or_else(|| Nat::from(1u64));
+    let take = take
+        .unwrap_or_else(|| Nat::from(MAX_TAKE))
+        .min(Nat::from(MAX_TAKE));
+    (1..=TOKENS)
+        .map(Nat::from)
+        .filter(|id| *id >= start)
+        .take(take.0.try_into().unwrap_or(usize::MAX))
+        .collect()
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc7_token_metadata(ids: Vec<Nat>) -> Vec<Option<Vec<(String, Value)>>> {
+    ids.into_iter()
+        .map(|id| {
+            (id > 0u64 && id <= TOKENS).then(|| {
+                vec![
+                    text("icrc7:name", format!("Punk #{id}")),
+                    text("icrc7:image", format!("https://punks.example/{id}.png")),
+                ]
+            })
+        })
+        .collect()
+}
+
+ic_cdk::export_candid!();
@@ -0,0 +1,104 @@
+use candid::{CandidType, Nat};
+use ic_cdk_macros::{query, update};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::sync::Mutex;
+
+#[derive(CandidType, Deserialize, Clone)]
+struct LPReply {
+    symbol: String,
+    name: String,
+    balance: f64,
+    symbol_0: String,
+    amount_0: f64,
+    symbol_1: String,
+    amount_1: f64,
+    ts: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum UserBalancesReply {
+    LP(LPReply),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct ClaimsReply {
+    claim_id: u64,
+    status: String,
+    chain: String,
+    symbol: String,
+    canister_id: Option<String>,
+    amount: Nat,
+    fee: Nat,
+    to_address: String,
+    desc: String,
+    ts: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct ClaimReply {
+    claim_id: u64,
+    status: String,
+    symbol: String,
+    amount: Nat,
+    ts: u64,
+}
+
+static CLAIMS: Lazy<Mutex<Vec<ClaimsReply>>> = Lazy::new(|| {
+    Mutex::new(vec![ClaimsReply {
+        claim_id: 1,
+        status: "Unclaimed".to_string(),
+        chain: "IC".to_string(),
+        symbol: "KONG".to_string(),
+        canister_id: None,
+        amount: Nat::from(25_000_000u64),
+        fee: Nat::from(10_000u64),
+        to_address: String::new(),
+        desc: "failed transfer".to_string(),
+        ts: 0,
+    }])
+});
+
+#[candid::candid_method(query)]
+#[query]
+fn user_balances(_user: String) -> Result<Vec<UserBalancesReply>, String> {
+    Ok(vec![UserBalancesReply::LP(LPReply {
+        symbol: "ICP_ckUSDT".to_string(),
+        name: "ICP_ckUSDT Liquidity Pool".to_string(),
+        balance: 10.0,
+        symbol_0: "ICP".to_string(),
+        amount_0: 5.5,
+        symbol_1: "ckUSDT".to_string(),
+        amount_1: 42.25,
+        ts: 0,
+    })])
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn claims(_user: String) -> Result<Vec<ClaimsReply>, String> {
+    Ok(CLAIMS.lock().unwrap().clone())
+}
+
+#[candid::candid_method(update)]
+#[update]
+fn claim(claim_id: u64) -> Result<ClaimReply, String> {
+    let mut claims = CLAIMS.lock().unwrap();
+    let c = claims
+        .iter_mut()
+        .find(|c| c.claim_id == claim_id)
+        .ok_or("Claim not found")?;
+    if c.status != "Unclaimed" {
+        return Err("Claim already processed".to_string());
+    }
+    c.status = "Claimed".to_string();
+    Ok(ClaimReply {
+        claim_id,
+        status: "Success".to_string(),
+        symbol: c.symbol.clone(),
+        amount: c.amount.clone(),
+        ts: 0,
+    })
+}
+
+ic_cdk::export_candid!();
@@ -0,0 +1,77 @@
+use candid::{CandidType, Nat, Principal};
+use ic_cdk_macros::query;
+use serde::Deserialize;
+
+// The protocol canister doubles as the stICP ledger.
+
+const HOUR_NS: u64 = 3_600_000_000_000;
+
+#[derive(CandidType, Deserialize, Clone)]
+struct TokenInfo {
+    symbol: String,
+    decimals: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct ProtocolInfo {
+    derivative: TokenInfo,
+    derivative_ledger: Principal,
+    underlying: TokenInfo,
+    exchange_rate: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct WithdrawalRequest {
+    id: u64,
+    amount: Nat,
+    maturity: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn get_info() -> ProtocolInfo {
+    ProtocolInfo {
+        derivative: TokenInfo {
+            symbol: "stICP".to_string(),
+            decimals: 8,
+        },
+        derivative_ledger: ic_cdk::id(),
+        underlying: TokenInfo {
+            symbol: "ICP".to_string(),
+            decimals: 8,
+        },
+        exchange_rate: Nat::from(105_000_000u64),
+    }
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn icrc1_balance_of(_account: Account) -> Nat {
+    Nat::from(400_000_000u64)
+}
+
+#[candid::candid_method(query)]
+#[query]
+fn get_withdrawal_requests(_user: Principal) -> Vec<WithdrawalRequest> {
+    let now = ic_cdk::api::time();
+    vec![
+        WithdrawalRequest {
+            id: 1,
+            amount: Nat::from(100_000_000u64),
+            maturity: now.saturating_sub(HOUR_NS),
+        },
+        WithdrawalRequest {
+            id: 2,
+            amount: Nat::from(250_000_000u64),
+            maturity: now + 24 * 7 * HOUR_NS,
+        },
+    ]
+}
+
+ic_cdk::export_candid!();
@@ -22,6 +22,15 @@ struct PositionInfo {
     auto_compound: bool,
 }
 
+#[derive(CandidType, Deserialize, Clone)]
+struct PairInfoExt {
+    id: String,
+    token0: String,
+    token1: String,
+    reserve0: Nat,
+    reserve1: Nat,
+}
+
 static HEIGHT: Lazy<Mutex<u64>> = Lazy::new(|| Mutex::new(0));
 static TOTAL_SUPPLY: Lazy<Mutex<u64>> = Lazy::new(|| Mutex::new(10_000_000_000));
 static TOTAL_REWARDS: Lazy<Mutex<u64>> = Lazy::new(|| Mutex::new(50_000_000));
@@ -69,6 +78,18 @@ fn get_user_positions(_p: Principal) -> Vec<PositionInfo> {
     ]
 }
 
+#[candid::candid_method(query, rename = "getAllPairs")]
+#[query(name = "getAllPairs")]
+fn get_all_pairs() -> Vec<PairInfoExt> {
+    vec![PairInfoExt {
+        id: "sonic0:sonic1".to_string(),
+        token0: "sonic0".to_string(),
+        token1: "sonic1".to_string(),
+        reserve0: Nat::from(10_000_000_000u64),
+        reserve1: Nat::from(20_000_000_000u64),
+    }]
+}
+
 #[candid::candid_method(query)]
 #[query]
 fn block_height() -> u64 {
@@ -313,6 +313,480 @@ mod tests {
         assert!(holdings.iter().any(|h| h.source == "Sonic"));
     }
 
+    #[tokio::test]
+    async fn integration_kongswap_positions() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ledger; skipping test");
+                return;
+            }
+        };
+        let dex_id = match deploy(replica.dir.path(), "mock_kongswap") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock kongswap; skipping test");
+                return;
+            }
+        };
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[ledgers]\nMOCK = \"{ledger_id}\"").unwrap();
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        std::env::set_var("LEDGERS_FILE", file.path());
+        std::env::set_var("KONGSWAP_BACKEND", &dex_id);
+
+        aggregator::utils::load_dex_config().await;
+
+        let principal = Principal::anonymous();
+        let holdings = get_holdings(principal).await;
+        assert!(holdings
+            .iter()
+            .any(|h| h.source == "KongSwap" && h.status == "lp_escrow"));
+        assert!(holdings
+            .iter()
+            .any(|h| h.source == "KongSwap" && h.status == "claimable"));
+    }
+
+    #[tokio::test]
+    async fn integration_icdex_positions() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ledger; skipping test");
+                return;
+            }
+        };
+        let dex_id = match deploy(replica.dir.path(), "mock_icdex") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock icdex; skipping test");
+                return;
+            }
+        };
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[ledgers]\nMOCK = \"{ledger_id}\"").unwrap();
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        std::env::set_var("LEDGERS_FILE", file.path());
+        std::env::set_var("ICDEX_ROUTER", &dex_id);
+
+        aggregator::utils::load_dex_config().await;
+
+        let principal = Principal::anonymous();
+        let holdings = get_holdings(principal).await;
+        assert!(holdings
+            .iter()
+            .any(|h| h.source == "ICDex" && h.status == "open_order"));
+        assert!(holdings
+            .iter()
+            .any(|h| h.source == "ICDex" && h.status == "trading_balance"));
+    }
+
+    #[tokio::test]
+    async fn integration_icpswap_farm_positions() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ledger; skipping test");
+                return;
+            }
+        };
+        let dex_id = match deploy(replica.dir.path(), "mock_icpswap_farm") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock icpswap farm; skipping test");
+                return;
+            }
+        };
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[ledgers]\nMOCK = \"{ledger_id}\"").unwrap();
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        std::env::set_var("LEDGERS_FILE", file.path());
+        std::env::set_var("ICPSWAP_FARM_INDEX", &dex_id);
+
+        aggregator::utils::load_dex_config().await;
+
+        let principal = Principal::anonymous();
+        let holdings = get_holdings(principal).await;
+        assert!(holdings
+            .iter()
+            .any(|h| h.source == "ICPSwapFarm" && h.status == "staked"));
+        assert!(holdings
+            .iter()
+            .any(|h| h.source == "ICPSwapFarm" && h.status == "claimable"));
+    }
+
+    #[tokio::test]
+    async fn integration_liquid_staking_positions() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ledger; skipping test");
+                return;
+            }
+        };
+        let dex_id = match deploy(replica.dir.path(), "mock_liquid_staking") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock liquid staking protocol; skipping test");
+                return;
+            }
+        };
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[ledgers]\nMOCK = \"{ledger_id}\"").unwrap();
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        std::env::set_var("LEDGERS_FILE", file.path());
+        std::env::set_var("LIQUID_STAKING_PROTOCOL", &dex_id);
+
+        aggregator::utils::load_dex_config().await;
+
+        let principal = Principal::anonymous();
+        let holdings = get_holdings(principal).await;
+        for status in ["liquid_staked", "unstaking", "withdrawable"] {
+            assert!(holdings
+                .iter()
+                .any(|h| h.source == "LiquidStaking" && h.status == status));
+        }
+    }
+
+    #[tokio::test]
+    async fn integration_ckbtc_pending() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ledger; skipping test");
+                return;
+            }
+        };
+        let dex_id = match deploy(replica.dir.path(), "mock_ckbtc_minter") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ckBTC minter; skipping test");
+                return;
+            }
+        };
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[ledgers]\nMOCK = \"{ledger_id}\"").unwrap();
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        std::env::set_var("LEDGERS_FILE", file.path());
+        std::env::set_var("CKBTC_MINTER", &dex_id);
+        std::env::set_var("BITCOIN_CANISTER", &dex_id);
+
+        aggregator::utils::load_dex_config().await;
+
+        let principal = Principal::anonymous();
+        let holdings = get_holdings(principal).await;
+        for status in ["pending_deposit", "pending_withdrawal"] {
+            assert!(holdings
+                .iter()
+                .any(|h| h.source == "ckBTC" && h.status == status));
+        }
+    }
+
+    #[tokio::test]
+    async fn integration_cketh_withdrawals() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ledger; skipping test");
+                return;
+            }
+        };
+        let dex_id = match deploy(replica.dir.path(), "mock_cketh_minter") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ckETH minter; skipping test");
+                return;
+            }
+        };
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[ledgers]\nMOCK = \"{ledger_id}\"").unwrap();
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        std::env::set_var("LEDGERS_FILE", file.path());
+        std::env::set_var("CKETH_MINTER", &dex_id);
+
+        aggregator::utils::load_dex_config().await;
+
+        let principal = Principal::anonymous();
+        let holdings = get_holdings(principal).await;
+        let pending: Vec<_> = holdings
+            .iter()
+            .filter(|h| h.source == "ckETH" && h.status == "pending_withdrawal")
+            .collect();
+        // pending, sent and awaiting reimbursement; the finalized one has landed
+        assert_eq!(pending.len(), 3);
+        assert!(pending.iter().any(|h| h.token == "ckUSDC"));
+    }
+
+    #[tokio::test]
+    async fn integration_icrc7_nfts() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let collection_id = match deploy(replica.dir.path(), "mock_icrc7") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock icrc7; skipping test");
+                return;
+            }
+        };
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        let collection = Principal::from_text(&collection_id).unwrap();
+        aggregator::nft::add_collection(
+            "Punks".into(),
+            collection,
+            aggregator::nft::NftStandard::Icrc7,
+        )
+        .unwrap();
+
+        let principal = Principal::anonymous();
+        let first = aggregator::nft::get_nfts(principal, None, Some(3)).await;
+        assert_eq!(first.nfts.len(), 3);
+        assert_eq!(first.nfts[0].collection_name, "Mock Punks");
+        assert_eq!(first.nfts[0].name.as_deref(), Some("Punk #1"));
+        let rest = aggregator::nft::get_nfts(principal, first.next, Some(3)).await;
+        assert_eq!(rest.nfts.len(), 2);
+        assert!(rest.next.is_none());
+        aggregator::nft::remove_collection("Punks").unwrap();
+    }
+
+    #[tokio::test]
+    async fn integration_ext_nfts() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let collection_id = match deploy(replica.dir.path(), "mock_ext") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ext; skipping test");
+                return;
+            }
+        };
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        let collection = Principal::from_text(&collection_id).unwrap();
+        aggregator::nft::add_collection(
+            "Motoko".into(),
+            collection,
+            aggregator::nft::NftStandard::Ext,
+        )
+        .unwrap();
+
+        let page = aggregator::nft::get_nfts(Principal::anonymous(), None, None).await;
+        let ids: Vec<_> = page.nfts.iter().map(|n| n.token_id.to_string()).collect();
+        assert_eq!(ids, ["0", "3", "8"]);
+        assert_eq!(page.nfts[1].name.as_deref(), Some("Motoko #3"));
+        assert!(page.nfts[0]
+            .image
+            .as_deref()
+            .is_some_and(|url| url.contains("tokenid=")));
+        assert!(page.next.is_none());
+        aggregator::nft::remove_collection("Motoko").unwrap();
+    }
+
+    #[tokio::test]
+    async fn integration_locker_positions() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock ledger; skipping test");
+                return;
+            }
+        };
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        // the mock ledger credits the anonymous principal, so let it be the locker
+        let locker = aggregator::dex::locker::LockerConfig {
+            name: "TestLocker".into(),
+            canister: Principal::anonymous(),
+            rule: aggregator::dex::locker::SubaccountRule::PrincipalPadded,
+            method: None,
+            ledgers: vec![Principal::from_text(&ledger_id).unwrap()],
+            status: "locked".into(),
+        };
+        aggregator::dex::locker::add_locker(locker).unwrap();
+
+        let holdings = aggregator::dex_fetchers::fetch_source("Lockers", Principal::anonymous())
+            .await
+            .unwrap();
+        assert!(holdings
+            .iter()
+            .any(|h| h.source == "TestLocker" && h.token == "MOCK" && h.status == "locked"));
+        aggregator::dex::locker::remove_locker("TestLocker").unwrap();
+    }
+
+    #[tokio::test]
+    async fn integration_icrc3_transactions() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let ledger_id = match deploy(replica.dir.path(), "mock_icrc3_ledger") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock icrc3 ledger; skipping test");
+                return;
+            }
+        };
+
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        let ledger = Principal::from_text(&ledger_id).unwrap();
+        aggregator::config::add_ledger("BLK".into(), ledger, None).unwrap();
+
+        let page = aggregator::transactions::get_transactions(Principal::anonymous(), ledger, None)
+            .await
+            .unwrap();
+        use aggregator::transactions::TxKind;
+        let kinds: Vec<_> = page.transactions.iter().map(|t| t.kind).collect();
+        // block 3 does not involve the principal; 0 and 1 come from the archive
+        assert_eq!(
+            kinds,
+            [
+                TxKind::Transfer,
+                TxKind::Burn,
+                TxKind::Approve,
+                TxKind::Transfer,
+                TxKind::Mint
+            ]
+        );
+        assert_eq!(page.token, "BLK");
+        assert_eq!(page.transactions[0].amount, "0.50000000");
+        assert!(page.next.is_none());
+        aggregator::config::remove_ledger("BLK").unwrap();
+    }
+
     #[tokio::test]
     async fn integration_infinity_positions() {
         if !ensure_dfx() {
@@ -495,11 +969,65 @@ mod tests {
         assert_eq!(holdings[1].status, "error");
     }
 
+    #[tokio::test]
+    async fn integration_pool_discovery() {
+        if !ensure_dfx() {
+            eprintln!("dfx not found; skipping integration test");
+            return;
+        }
+
+        let replica = match Replica::start() {
+            Some(r) => r,
+            None => {
+                eprintln!("failed to start dfx; skipping test");
+                return;
+            }
+        };
+
+        let dex_id = match deploy(replica.dir.path(), "mock_icpswap") {
+            Some(id) => id,
+            None => {
+                eprintln!("failed to deploy mock icpswap; skipping test");
+                return;
+            }
+        };
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[ledgers]").unwrap();
+        std::env::set_var("LEDGER_URL", "http://127.0.0.1:4943");
+        std::env::set_var("LEDGERS_FILE", file.path());
+        std::env::set_var("ICPSWAP_FACTORY", &dex_id);
+        aggregator::utils::load_dex_config().await;
+
+        aggregator::pool_registry::refresh().await;
+        aggregator::pool_registry::discover().await;
+        let pools = aggregator::pool_registry::list();
+        let pool = pools.iter().find(|p| p.id == dex_id).unwrap();
+        assert_eq!(pool.source, "ICPSwap");
+        assert_eq!(pool.token_a, "mock0");
+        assert_eq!(pool.decimals_a, 8);
+        assert!(pools
+            .iter()
+            .any(|p| p.id == "pool1" && p.source == "manual"));
+
+        // discovery refreshes stats; the mock pair has no ICP side so it stays unpriced
+        let stats = aggregator::pool_stats::list();
+        let stat = stats.iter().find(|s| s.pool_id == dex_id).unwrap();
+        assert_eq!((stat.reserve_a, stat.reserve_b), (500.0, 100.0));
+        assert_eq!(stat.tvl, None);
+    }
+
     #[tokio::test]
     async fn pool_registry_graphql() {
         aggregator::pool_registry::refresh().await;
         let out = blockxpand_icp::pools_graphql("query { pools { id } }".into());
         assert!(out.contains("pool1"));
+        let out =
+            blockxpand_icp::pools_graphql(r#"{ pool(id: "pool2") { tokens { symbol } } }"#.into());
+        assert_eq!(
+            out,
+            r#"{"data":{"pool":{"tokens":[{"symbol":"ICP"},{"symbol":"XYZ"}]}}}"#
+        );
     }
 
     #[tokio::test]